@@ -5,6 +5,8 @@ use std::fs;
 use std::io::{self, Write};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crossterm::{
@@ -20,23 +22,54 @@ use ftui::{KeyEventKind, MouseButton, MouseEvent, MouseEventKind, Program, Progr
 use ftui::render::budget::FrameBudgetConfig;
 use time::OffsetDateTime;
 
+use crate::config::{load_setup, save_setup, PanelSetup, Setup};
+use crate::editor::Motion;
+use crate::filter::{load_filters, save_filters, FileFilter, SavedFilter};
 use crate::fs_ops::{
-    build_tree, copy_sources, find_conflicts, find_matches, list_drive_roots, load_user_menu,
-    move_sources, read_file_lines, sync_execute, sync_plan, toggle_ext_sort, toggle_name_sort,
-    toggle_size_sort, toggle_time_sort, user_menu_path, ensure_user_menu_file,
+    available_space, build_tree, config_path, expand_tilde, file_digest, filters_path, find_filter, find_matches,
+    format_size, history_path, home_dir, list_drive_roots, load_history, measure_transfer,
+    normalize_path, same_filesystem, save_history, sync_plan, toggle_ext_sort,
+    toggle_name_sort, toggle_size_sort, toggle_time_sort, unique_name, user_menu_path,
+    TransferOptions, HISTORY_LIMIT, LOCAL_MENU_NAME,
 };
+use crate::jobs::{ConflictAnswer, JobManager, JobTask, JobUpdate};
 use crate::menu::{menu_items, MENU_TITLES};
+use crate::pack::{PackFormat, PackOptions};
 use crate::model::{
-    ActivePane, ClickInfo, CopyDialogFocus, CopyDialogState, LayoutCache, MenuAction, Modal,
-    OverwriteKind, Pane, PanelMode, PendingConfirm, PendingPrompt, RefreshMode, SortMode, Viewer,
-    ViewerAction, VfsState,
+    ActivePane, ArchiveEdit, ClickInfo, Compare, CopyDialogFocus, CopyDialogState, Editor, EditorAction, FilterTarget, LayoutCache, MenuAction,
+    MenuMacro, Modal, OverwriteKind, PackDialogFocus, PackDialogState, Pane, PanelMode, PendingConfirm, PendingPrompt, RefreshMode, SortMode,
+    UserMenuItem, UserMenuLevel, Viewer, ViewerAction, ViewerSearch,
+};
+use crate::usermenu::{
+    ensure_user_menu_file, expand_macros, filter_user_menu, load_user_menu, Expansion, MacroContext,
 };
 use crate::ui::{
-    render_background, render_layout, render_modal_wrapper, render_status_and_keybar, render_viewer,
+    render_background, render_compare, render_editor, render_layout, render_modal_wrapper, render_status_and_keybar,
+    render_viewer,
 };
-use crate::vfs::read_zip_file_lines;
+use crate::vfs::is_archive;
+use crate::viewer::{Finder, ViewSource};
 
 const DOUBLE_CLICK_MS: u64 = 400;
+/// Quick search ends after this long without a keystroke.
+const QUICK_SEARCH_TIMEOUT_MS: u64 = 1500;
+/// Characters that make a `cd` line a job for the shell rather than the
+/// built-in.
+const SHELL_METACHARACTERS: &[char] = &[';', '&', '|', '<', '>', '$', '`', '(', ')', '*', '?', '[', '{', '\\', '\n'];
+/// Tab order of the pack dialog.
+const PACK_DIALOG_FOCUS: [PackDialogFocus; 9] = [
+    PackDialogFocus::Input,
+    PackDialogFocus::Format,
+    PackDialogFocus::Level,
+    PackDialogFocus::RelativePaths,
+    PackDialogFocus::UseFilters,
+    PackDialogFocus::DeleteSources,
+    PackDialogFocus::BtnPack,
+    PackDialogFocus::BtnFilters,
+    PackDialogFocus::BtnCancel,
+];
+/// Poll interval for worker thread progress while jobs are running.
+const JOB_TICK_MS: u64 = 100;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ThemeColors {
@@ -58,6 +91,12 @@ pub struct ThemeColors {
     pub status_fg: PackedRgba,
     pub dialog_bg: PackedRgba,
     pub dialog_fg: PackedRgba,
+    pub match_bg: PackedRgba,
+    pub match_fg: PackedRgba,
+    pub diff_fg: PackedRgba,
+    pub diff_changed_bg: PackedRgba,
+    pub diff_added_bg: PackedRgba,
+    pub diff_removed_bg: PackedRgba,
 }
 
 impl ThemeColors {
@@ -82,6 +121,12 @@ impl ThemeColors {
             status_fg: PackedRgba::rgb(255, 255, 255),
             dialog_bg: PackedRgba::rgb(170, 170, 170),    // light gray
             dialog_fg: PackedRgba::rgb(0, 0, 0),
+            match_bg: PackedRgba::rgb(0, 170, 170),       // cyan
+            match_fg: PackedRgba::rgb(0, 0, 0),
+            diff_fg: PackedRgba::rgb(255, 255, 255),
+            diff_changed_bg: PackedRgba::rgb(170, 0, 170), // magenta
+            diff_added_bg: PackedRgba::rgb(0, 170, 0),     // green
+            diff_removed_bg: PackedRgba::rgb(170, 0, 0),   // red
         }
     }
 }
@@ -89,6 +134,7 @@ impl ThemeColors {
 #[derive(Debug, Clone)]
 pub enum Msg {
     Event(Event),
+    Job(JobUpdate),
     Quit,
 }
 
@@ -105,6 +151,10 @@ pub struct App {
     active: ActivePane,
     status: String,
     viewer: Option<Viewer>,
+    editor: Option<Editor>,
+    compare: Option<Compare>,
+    /// The archive entry being edited through a temp copy, if any.
+    archive_edit: Option<ArchiveEdit>,
     layout: RefCell<Option<LayoutCache>>,
     last_click: Option<ClickInfo>,
     theme: ThemeColors,
@@ -112,22 +162,40 @@ pub struct App {
     log: Option<std::fs::File>,
     force_clear_frames: RefCell<u8>,
     show_hidden: bool,
+    confirm_delete: bool,
+    confirm_overwrite: bool,
+    confirm_delete_dirs: bool,
+    confirm_quit: bool,
+    auto_save: bool,
+    internal_editor: bool,
     hide_left: bool,
     hide_right: bool,
     hide_all: bool,
     cmdline: String,
     cmd_cursor: usize,
+    cmd_history: Vec<String>,
+    cmd_history_pos: Option<usize>,
+    prev_cwd: Option<PathBuf>,
     quick_search: Option<String>,
     quick_search_time: Option<Instant>,
+    jobs: JobManager,
+    ticking: bool,
+    /// Prefills the viewer's search dialog.
+    last_search: ViewerSearch,
 }
 
 impl App {
     pub fn new() -> io::Result<Self> {
         let cwd = std::env::current_dir()?;
-        let mut left = Pane::new(cwd.clone());
-        let mut right = Pane::new(cwd);
-        left.refresh(RefreshMode::Reset, false)?;
-        right.refresh(RefreshMode::Reset, false)?;
+        let setup = load_setup(&config_path());
+        let left = pane_from_setup(&setup.left, &cwd, setup.show_hidden)?;
+        let right = pane_from_setup(&setup.right, &cwd, setup.show_hidden)?;
+        let mut hide_left = setup.left.hidden;
+        let hide_right = setup.right.hidden;
+        if hide_left && hide_right {
+            hide_left = false;
+        }
+        let active = if hide_left { ActivePane::Right } else { ActivePane::Left };
 
         let log = match std::env::var("FC_DEBUG_LOG") {
             Ok(_) => std::fs::OpenOptions::new()
@@ -140,23 +208,38 @@ impl App {
         Ok(Self {
             left,
             right,
-            active: ActivePane::Left,
+            active,
             status: String::from("Ready"),
             viewer: None,
+            editor: None,
+            compare: None,
+            archive_edit: None,
             layout: RefCell::new(None),
             last_click: None,
             theme: ThemeColors::classic(),
             modal: None,
             log,
             force_clear_frames: RefCell::new(0),
-            show_hidden: false,
-            hide_left: false,
-            hide_right: false,
+            show_hidden: setup.show_hidden,
+            confirm_delete: setup.confirm_delete,
+            confirm_overwrite: setup.confirm_overwrite,
+            confirm_delete_dirs: setup.confirm_delete_dirs,
+            confirm_quit: setup.confirm_quit,
+            auto_save: setup.auto_save,
+            internal_editor: setup.internal_editor,
+            hide_left,
+            hide_right,
             hide_all: false,
             cmdline: String::new(),
             cmd_cursor: 0,
+            cmd_history: load_history(&history_path()),
+            cmd_history_pos: None,
+            prev_cwd: None,
             quick_search: None,
             quick_search_time: None,
+            jobs: JobManager::new(),
+            ticking: false,
+            last_search: ViewerSearch::default(),
         })
     }
 
@@ -191,6 +274,138 @@ impl App {
         program.run()
     }
 
+    fn current_setup(&self) -> Setup {
+        let panel = |pane: &Pane, hidden: bool| PanelSetup {
+            cwd: Some(pane.cwd.clone()),
+            sort_mode: pane.sort_mode,
+            dirs_first: pane.dirs_first,
+            mode: pane.mode,
+            hidden,
+            filter: pane.filter.clone(),
+        };
+        Setup {
+            show_hidden: self.show_hidden,
+            confirm_delete: self.confirm_delete,
+            confirm_overwrite: self.confirm_overwrite,
+            confirm_delete_dirs: self.confirm_delete_dirs,
+            confirm_quit: self.confirm_quit,
+            auto_save: self.auto_save,
+            internal_editor: self.internal_editor,
+            left: panel(&self.left, self.hide_left),
+            right: panel(&self.right, self.hide_right),
+        }
+    }
+
+    fn save_setup(&mut self) {
+        match save_setup(&config_path(), &self.current_setup()) {
+            Ok(()) => self.status = "Setup saved".to_string(),
+            Err(err) => self.status = format!("Save setup failed: {err}"),
+        }
+    }
+
+    fn quit(&mut self) -> Cmd<Msg> {
+        self.jobs.cancel_all();
+        if self.auto_save {
+            if let Err(err) = save_setup(&config_path(), &self.current_setup()) {
+                self.log_event(&format!("auto save failed: {err}"));
+            }
+        }
+        Cmd::quit()
+    }
+
+    /// F10 and friends: ask first unless exit confirmation is turned off.
+    fn request_quit(&mut self) -> Cmd<Msg> {
+        let running = self.jobs.jobs().len();
+        let message = if running > 0 {
+            format!("{} operation(s) still running. Cancel them and quit?", running)
+        } else if self.confirm_quit {
+            "Do you want to quit FrankenCommander?".to_string()
+        } else {
+            return self.quit();
+        };
+        self.modal = Some(Modal::Confirm {
+            title: "Quit".to_string(),
+            message,
+            action: PendingConfirm::Quit,
+        });
+        Cmd::none()
+    }
+
+    /// Hand `task` to a worker thread and show its progress dialog.
+    fn start_job(&mut self, task: JobTask) {
+        let verb = task.verb();
+        let job_id = self.jobs.spawn(task);
+        self.status = format!("{} started", verb);
+        self.modal = Some(Modal::Progress { job_id, focus: 0 });
+    }
+
+    /// Drain worker reports and feed them back through `Msg::Job`.
+    fn poll_jobs(&mut self) -> Cmd<Msg> {
+        self.ticking = false;
+        self.show_pending_conflict();
+        let updates = self.jobs.poll();
+        if updates.is_empty() {
+            return Cmd::none();
+        }
+        Cmd::batch(updates.into_iter().map(|update| Cmd::msg(Msg::Job(update))).collect())
+    }
+
+    fn apply_job_update(&mut self, update: JobUpdate) {
+        let Some((job, result)) = self.jobs.apply(update) else {
+            self.show_pending_conflict();
+            return;
+        };
+        let verb = job.task.verb();
+        self.status = match result {
+            Ok(()) => format!("{} complete", verb),
+            Err(_) if job.is_cancelled() => format!("{} cancelled", verb),
+            Err(err) => format!("{} failed: {err}", verb),
+        };
+        if matches!(self.modal, Some(Modal::Progress { job_id, .. } | Modal::Conflict { job_id, .. }) if job_id == job.id) {
+            self.modal = None;
+        }
+        let show_hidden = self.show_hidden;
+        let _ = self.left.refresh(RefreshMode::Keep, show_hidden);
+        let _ = self.right.refresh(RefreshMode::Keep, show_hidden);
+    }
+
+    /// Bring up the conflict dialog for a blocked worker unless the user is busy
+    /// with another dialog; the job's own progress dialog gives way.
+    fn show_pending_conflict(&mut self) {
+        let Some(job_id) = self.jobs.pending_conflict() else { return };
+        if self.viewer.is_none() && self.editor.is_none() && self.compare.is_none() && matches!(self.modal, None | Some(Modal::Progress { .. })) {
+            self.modal = Some(Modal::Conflict { job_id, focus: 0 });
+        } else if !self.status.starts_with("Job #") {
+            self.status = format!("Job #{} is waiting for an answer (Alt+J)", job_id);
+        }
+    }
+
+    fn answer_conflict(&mut self, job_id: usize, answer: ConflictAnswer) {
+        if let Some(job) = self.jobs.get_mut(job_id) {
+            job.answer_conflict(answer);
+        }
+        self.modal = Some(Modal::Progress { job_id, focus: 0 });
+    }
+
+    /// Keep ticks coming while any worker is still running or the viewer is
+    /// still indexing lines or following a file.
+    fn schedule_tick(&mut self, cmd: Cmd<Msg>) -> Cmd<Msg> {
+        let viewing = self.viewer.as_ref().is_some_and(|viewer| viewer.index_more() || viewer.follow);
+        if (self.jobs.is_empty() && !viewing) || self.ticking {
+            return cmd;
+        }
+        self.ticking = true;
+        Cmd::batch(vec![cmd, Cmd::tick(Duration::from_millis(JOB_TICK_MS))])
+    }
+
+    fn open_jobs(&mut self) {
+        if self.jobs.is_empty() {
+            self.status = "No background jobs".to_string();
+            return;
+        }
+        self.modal = Some(Modal::Jobs { selected: 0 });
+    }
+
     fn log_event(&mut self, msg: &str) {
         if let Some(file) = self.log.as_mut() {
             let _ = writeln!(file, "{} {}", OffsetDateTime::now_utc(), msg);
@@ -237,35 +452,71 @@ impl App {
             self.status = "Cannot view directory".to_string();
             return;
         }
-        if let Some(vfs) = self.active_pane().vfs.clone() {
-            let path = entry.path.clone();
-            self.open_zip_viewer(&vfs, &path);
-            return;
-        }
         let path = entry.path.clone();
-        self.open_viewer_path(&path);
+        self.open_pane_file(&path);
     }
 
-    fn open_viewer_path(&mut self, path: &Path) {
-        match read_file_lines(path) {
-            Ok(lines) => {
-                self.viewer = Some(Viewer { path: path.to_path_buf(), lines, scroll: 0 });
-            }
-            Err(err) => {
-                self.status = format!("View failed: {err}");
+    /// View a file of the active pane, which may be inside an archive.
+    fn open_pane_file(&mut self, path: &Path) {
+        let pane = self.active_pane();
+        let opened = pane.fs().view_source(path).and_then(|source| Viewer::new(pane.display_path(path), source));
+        self.show_viewer(opened);
+    }
+
+    /// Show a newly opened file. When the viewer is already open (stepping
+    /// through the panel) it keeps its wrap setting, and errors go to its own
+    /// status line.
+    fn show_viewer(&mut self, opened: io::Result<Viewer>) {
+        match opened {
+            Ok(mut viewer) => {
+                if let Some(current) = &self.viewer {
+                    viewer.wrap = current.wrap;
+                }
+                self.viewer = Some(viewer);
             }
+            Err(err) => match self.viewer.as_mut() {
+                Some(current) => current.message = Some(format!("View failed: {err}")),
+                None => self.status = format!("View failed: {err}"),
+            },
         }
     }
 
-    fn open_zip_viewer(&mut self, vfs: &VfsState, entry_path: &Path) {
-        match read_zip_file_lines(vfs, entry_path) {
-            Ok(lines) => {
-                self.viewer = Some(Viewer { path: entry_path.to_path_buf(), lines, scroll: 0 });
-            }
-            Err(err) => {
-                self.status = format!("View failed: {err}");
+    /// Load the next (or previous) file of the active panel into the viewer,
+    /// moving the panel cursor along. Directories are skipped.
+    fn view_adjacent(&mut self, forward: bool) {
+        let view_height = self.list_height(self.active);
+        let pane = self.active_pane();
+        let current = pane.state.borrow().selected.unwrap_or(0);
+        let is_file = |idx: &usize| !pane.entries[*idx].is_dir;
+        let found = if forward {
+            (current + 1..pane.entries.len()).find(is_file)
+        } else {
+            (0..current.min(pane.entries.len())).rev().find(is_file)
+        };
+        let Some(idx) = found else {
+            if let Some(viewer) = self.viewer.as_mut() {
+                viewer.message = Some(if forward { "No next file" } else { "No previous file" }.to_string());
             }
-        }
+            return;
+        };
+        self.active_pane_mut().move_selection(idx as i32 - current as i32, view_height);
+        self.open_viewer();
+    }
+
+    fn open_viewer_search(&mut self) {
+        let search = self
+            .viewer
+            .as_ref()
+            .and_then(|viewer| viewer.finder.as_ref())
+            .map(|finder| finder.search.clone())
+            .unwrap_or_else(|| self.last_search.clone());
+        self.modal = Some(Modal::ViewerSearch { cursor: search.query.len(), search, focus: 0 });
+    }
+
+    fn run_viewer_search(&mut self, finder: Finder) {
+        let Some(viewer) = self.viewer.as_mut() else { return };
+        let query = finder.search.query.clone();
+        viewer.message = if viewer.find(finder) { None } else { Some(format!("\"{query}\" not found")) };
     }
 
     fn open_editor(&mut self) {
@@ -277,31 +528,210 @@ impl App {
             self.status = "Cannot edit directory".to_string();
             return;
         }
+        let mut path = entry.path.clone();
+        if self.active_pane().in_archive() {
+            if let Err(err) = self.active_pane().fs().check_writable() {
+                self.status = format!("Cannot edit {}: {err}", entry.name);
+                return;
+            }
+            let Some(edit) = self.extract_archive_entry(&path) else { return };
+            path = edit.temp_path.clone();
+            self.archive_edit = Some(edit);
+        }
+        if self.internal_editor {
+            match Editor::open(path) {
+                Ok(editor) => self.editor = Some(editor),
+                Err(err) => {
+                    self.status = format!("Edit failed: {err}");
+                    self.archive_edit = None;
+                }
+            }
+            return;
+        }
         let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-        let result = run_external_editor(&editor, &entry.path);
+        let result = run_external_editor(&editor, &path);
         *self.force_clear_frames.borrow_mut() = 3;
         match result {
             Ok(()) => {
                 self.status = "Editor closed".to_string();
                 let show_hidden = self.show_hidden;
                 let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
+                self.finish_archive_edit();
             }
             Err(err) => {
                 self.status = format!("Editor failed: {err}");
+                self.archive_edit = None;
             }
         }
     }
 
-    fn begin_copy(&mut self) {
-        if self.active_pane().vfs.is_some() {
-            self.status = "Copy from archive not supported".to_string();
+    /// Compare the current file of the left panel with that of the right one.
+    fn open_compare(&mut self) {
+        let Some((left_path, left)) = self.compare_source(ActivePane::Left) else { return };
+        let Some((right_path, right)) = self.compare_source(ActivePane::Right) else { return };
+        match Compare::new(left_path, left, right_path, right) {
+            Ok(compare) => self.compare = Some(compare),
+            Err(err) => self.status = format!("Compare failed: {err}"),
+        }
+    }
+
+    fn compare_source(&mut self, side: ActivePane) -> Option<(PathBuf, ViewSource)> {
+        let pane = match side {
+            ActivePane::Left => &self.left,
+            ActivePane::Right => &self.right,
+        };
+        let Some(entry) = pane.selected_entry() else {
+            self.status = "Select a file in each panel to compare".to_string();
+            return None;
+        };
+        if entry.is_dir {
+            self.status = format!("Cannot compare directory {}", entry.name);
+            return None;
+        }
+        match pane.fs().view_source(&entry.path) {
+            Ok(source) => Some((pane.display_path(&entry.path), source)),
+            Err(err) => {
+                self.status = format!("Cannot open {}: {err}", entry.name);
+                None
+            }
+        }
+    }
+
+    /// Copy an entry of the archive shown in the active pane out to the
+    /// pane's private temp dir.
+    fn extract_archive_entry(&mut self, entry_path: &Path) -> Option<ArchiveEdit> {
+        let mount = self.active_pane_mut().mounts.last_mut()?;
+        match mount.extract(entry_path).and_then(|temp_path| Ok((file_digest(&temp_path)?, temp_path))) {
+            Ok((original, temp_path)) => Some(ArchiveEdit {
+                archive: Arc::clone(&mount.fs),
+                entry: entry_path.to_path_buf(),
+                temp_path,
+                original,
+            }),
+            Err(err) => {
+                self.status = format!("Extract failed: {err}");
+                None
+            }
+        }
+    }
+
+    /// Enter on a file inside an archive: open the extracted copy the way
+    /// Enter opens a plain file.
+    fn open_archive_entry(&mut self) {
+        let Some(entry) = self.active_pane().selected_entry() else { return };
+        let path = entry.path.clone();
+        if let Some(extracted) = self.extract_archive_entry(&path) {
+            let title = self.active_pane().display_path(&path);
+            self.open_file(&extracted.temp_path, title);
+        }
+    }
+
+    /// Enter on a plain file, local or an extracted archive entry; `title`
+    /// is the name shown for it. Files open in the viewer.
+    fn open_file(&mut self, path: &Path, title: PathBuf) {
+        self.show_viewer(fs::File::open(path).and_then(|file| Viewer::new(title, ViewSource::File(file))));
+    }
+
+    /// Once an extracted archive entry has been edited, offer to store it
+    /// back if it changed.
+    fn finish_archive_edit(&mut self) {
+        let Some(edit) = self.archive_edit.take() else { return };
+        match file_digest(&edit.temp_path) {
+            Ok(digest) if digest != edit.original => {
+                let archive = edit.archive.name();
+                let archive = Path::new(&archive).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                self.modal = Some(Modal::Confirm {
+                    title: "Archive".to_string(),
+                    message: format!("{} was changed. Update it in {}?", edit.entry.display(), archive),
+                    action: PendingConfirm::UpdateArchive {
+                        archive: edit.archive,
+                        entry: edit.entry,
+                        temp_path: edit.temp_path,
+                    },
+                });
+            }
+            Ok(_) => {}
+            Err(err) => self.status = format!("Cannot read edited file: {err}"),
+        }
+    }
+
+    fn leave_editor(&mut self) {
+        self.editor = None;
+        self.refresh_after_edit();
+        self.finish_archive_edit();
+    }
+
+    /// Leave the editor, asking first when there are unsaved changes.
+    fn close_editor(&mut self) {
+        let Some(editor) = self.editor.as_ref() else { return };
+        if !editor.modified {
+            self.leave_editor();
             return;
         }
+        let name = editor.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        self.modal = Some(Modal::Confirm {
+            title: "Edit".to_string(),
+            message: format!("Save changes to {name}?"),
+            action: PendingConfirm::CloseEditor,
+        });
+    }
+
+    /// Write the editor buffer out; the outcome shows on the editor status line.
+    fn save_editor(&mut self) -> bool {
+        let Some(editor) = self.editor.as_mut() else { return false };
+        let saved = match editor.save() {
+            Ok(()) => {
+                editor.message = Some("Saved".to_string());
+                true
+            }
+            Err(err) => {
+                editor.message = Some(format!("Save failed: {err}"));
+                false
+            }
+        };
+        self.refresh_after_edit();
+        saved
+    }
+
+    fn refresh_after_edit(&mut self) {
+        let show_hidden = self.show_hidden;
+        let _ = self.left.refresh(RefreshMode::Keep, show_hidden);
+        let _ = self.right.refresh(RefreshMode::Keep, show_hidden);
+    }
+
+    fn open_editor_search(&mut self) {
+        let value = self.editor.as_ref().and_then(|editor| editor.search.clone()).unwrap_or_default();
+        self.modal = Some(Modal::Prompt {
+            title: "Search".to_string(),
+            label: "Search for:".to_string(),
+            cursor: value.len(),
+            value,
+            action: PendingPrompt::EditorSearch,
+        });
+    }
+
+    fn run_editor_search(&mut self, query: &str) {
+        let Some(editor) = self.editor.as_mut() else { return };
+        if !editor.find(query) {
+            editor.message = Some(format!("\"{query}\" not found"));
+        }
+        editor.scroll_into_view();
+    }
+
+    fn begin_copy(&mut self) {
         let sources = selected_paths(self.active_pane());
         if sources.is_empty() {
             self.status = "No file selected".to_string();
             return;
         }
+        if self.active_pane().in_archive() {
+            self.begin_extract(sources);
+            return;
+        }
+        if self.inactive_pane_mut().in_archive() {
+            self.begin_add_to_archive(sources, false);
+            return;
+        }
         let source_name = if sources.len() == 1 {
             self.active_pane()
                 .selected_entry()
@@ -324,24 +754,184 @@ impl App {
             source_name,
             dest: dest.clone(),
             cursor: dest.len(),
-            include_subdirs: false,
+            include_subdirs: true,
             copy_newer_only: false,
             use_filters: false,
             check_target_space: false,
+            filter: FileFilter::parse("*"),
             focus: CopyDialogFocus::Input,
         }));
     }
 
-    fn begin_move(&mut self) {
-        if self.active_pane().vfs.is_some() {
-            self.status = "Move in archive not supported".to_string();
+    /// F5 inside an archive: ask where to extract the selected entries.
+    fn begin_extract(&mut self, sources: Vec<PathBuf>) {
+        if self.inactive_pane_mut().in_archive() {
+            self.status = "Copy between archives not supported".to_string();
+            return;
+        }
+        let label = match sources.as_slice() {
+            [single] => format!("Extract {} to:", single.file_name().unwrap_or_default().to_string_lossy()),
+            _ => format!("Extract {} files to:", sources.len()),
+        };
+        let dest = self.inactive_pane_mut().cwd.display().to_string();
+        self.modal = Some(Modal::Prompt {
+            title: "Copy".to_string(),
+            label,
+            cursor: dest.len(),
+            value: dest,
+            action: PendingPrompt::CopyTo { sources },
+        });
+    }
+
+    /// Alt+F9: extract the archive under the cursor, or the one the panel is
+    /// browsing, into the other panel's directory.
+    fn begin_extract_all(&mut self) {
+        let show_hidden = self.show_hidden;
+        let pane = self.active_pane_mut();
+        let selected = pane.selected_entry().filter(|entry| !entry.is_dir && is_archive(&entry.name)).cloned();
+        let archive = match selected {
+            Some(entry) => match pane.open_archive_at(&entry.path) {
+                Ok(archive) => archive,
+                Err(err) => {
+                    self.status = format!("Cannot open {}: {err}", entry.name);
+                    return;
+                }
+            },
+            None => match pane.mounts.first() {
+                Some(mount) => Arc::clone(&mount.fs),
+                None => {
+                    self.status = "No archive selected".to_string();
+                    return;
+                }
+            },
+        };
+        // Extracting an archive entry may have created the temp dir.
+        let _ = pane.refresh(RefreshMode::Keep, show_hidden);
+        if self.inactive_pane_mut().in_archive() {
+            self.status = "Extract into archive not supported".to_string();
+            return;
+        }
+        let name = archive.name();
+        let name = Path::new(&name).file_name().unwrap_or_default().to_string_lossy().to_string();
+        let dest = self.inactive_pane_mut().cwd.display().to_string();
+        self.modal = Some(Modal::Prompt {
+            title: "Extract".to_string(),
+            label: format!("Extract all of {name} to:"),
+            cursor: dest.len(),
+            value: dest,
+            action: PendingPrompt::ExtractAll { archive },
+        });
+    }
+
+    /// F5/F6 with the other panel inside an archive: confirm storing the
+    /// selection in the directory it shows.
+    fn begin_add_to_archive(&mut self, sources: Vec<PathBuf>, remove_sources: bool) {
+        let target = self.inactive_pane_mut();
+        let Some(mount) = target.mounts.last() else { return };
+        let (archive, dest) = (Arc::clone(&mount.fs), mount.dir.clone());
+        let location = target.display_path(&dest).display().to_string();
+        let what = match sources.as_slice() {
+            [single] => format!("\"{}\"", single.file_name().unwrap_or_default().to_string_lossy()),
+            _ => format!("{} files", sources.len()),
+        };
+        let verb = if remove_sources { "Move" } else { "Copy" };
+        self.modal = Some(Modal::Confirm {
+            title: verb.to_string(),
+            message: format!("{verb} {what} into {location}?\nEntries already there are asked about first."),
+            action: PendingConfirm::AddToArchive { archive, sources, dest, remove_sources },
+        });
+    }
+
+    /// Alt+F5: set up packing the selection into a new archive, by default in
+    /// the other panel's directory.
+    fn begin_pack(&mut self) {
+        let sources = selected_paths(self.active_pane());
+        if sources.is_empty() {
+            self.status = "No file selected".to_string();
             return;
         }
+        if self.active_pane().in_archive() {
+            self.status = "Pack inside archive not supported".to_string();
+            return;
+        }
+        let (source_name, stem) = match sources.as_slice() {
+            [single] => {
+                let name = single.file_name().unwrap_or_default().to_string_lossy().to_string();
+                let stem = if single.is_dir() { single.file_name() } else { single.file_stem() };
+                (name, stem.map(|stem| stem.to_string_lossy().to_string()))
+            }
+            _ => {
+                let dir = self.active_pane().cwd.file_name();
+                (format!("{} files", sources.len()), dir.map(|dir| dir.to_string_lossy().to_string()))
+            }
+        };
+        let format = PackFormat::Zip;
+        let name = format!("{}{}", stem.unwrap_or_else(|| "archive".to_string()), format.extension());
+        let dest = self.inactive_pane_mut().cwd.join(name).display().to_string();
+        let filter = self.active_pane().filter.clone();
+        self.modal = Some(Modal::PackDialog(PackDialogState {
+            sources,
+            source_name,
+            cursor: dest.len(),
+            dest,
+            format,
+            level: format.default_level(),
+            relative_paths: true,
+            use_filters: filter.is_some(),
+            filter: filter.unwrap_or_else(|| FileFilter::parse("*")),
+            delete_sources: false,
+            focus: PackDialogFocus::Input,
+        }));
+    }
+
+    /// Start the job set up in the pack dialog. Returns false when the dialog
+    /// should stay open.
+    fn submit_pack_dialog(&mut self, state: &PackDialogState) -> bool {
+        if state.dest.trim().is_empty() {
+            self.status = "No archive name given".to_string();
+            return false;
+        }
+        let dest = self.active_pane().cwd.join(expand_tilde(state.dest.trim()));
+        if fs::symlink_metadata(&dest).is_ok() {
+            self.status = format!("{} already exists", dest.display());
+            return false;
+        }
+        let options = PackOptions {
+            format: state.format,
+            level: state.level,
+            relative_paths: state.relative_paths,
+            filter: state.use_filters.then(|| state.filter.clone()),
+            delete_sources: state.delete_sources,
+        };
+        self.start_job(JobTask::Pack { sources: state.sources.clone(), dest, options });
+        true
+    }
+
+    fn begin_move(&mut self) {
         let sources = selected_paths(self.active_pane());
         if sources.is_empty() {
             self.status = "No file selected".to_string();
             return;
         }
+        if self.active_pane().in_archive() {
+            let [from] = sources.as_slice() else {
+                self.status = "Move in archive not supported".to_string();
+                return;
+            };
+            let name = from.file_name().unwrap_or_default().to_string_lossy().to_string();
+            self.modal = Some(Modal::Prompt {
+                title: "Rename".to_string(),
+                label: "New name:".to_string(),
+                cursor: name.len(),
+                value: name,
+                action: PendingPrompt::Rename { from: from.clone() },
+            });
+            return;
+        }
+        if self.inactive_pane_mut().in_archive() {
+            self.begin_add_to_archive(sources, true);
+            return;
+        }
         let source_name = if sources.len() == 1 {
             sources[0].file_name().unwrap_or_default().to_string_lossy().to_string()
         } else {
@@ -361,20 +951,17 @@ impl App {
             source_name,
             dest: dest.clone(),
             cursor: dest.len(),
-            include_subdirs: false,
+            include_subdirs: true,
             copy_newer_only: false,
             use_filters: false,
             check_target_space: false,
+            filter: FileFilter::parse("*"),
             focus: CopyDialogFocus::Input,
         }));
     }
 
     fn begin_mkdir(&mut self) {
-        if self.active_pane().vfs.is_some() {
-            self.status = "Mkdir in archive not supported".to_string();
-            return;
-        }
-        let base = self.active_pane().cwd.clone();
+        let base = self.active_pane().dir().to_path_buf();
         let default = "new_folder".to_string();
         self.modal = Some(Modal::Prompt {
             title: "Make directory".to_string(),
@@ -386,10 +973,6 @@ impl App {
     }
 
     fn begin_delete(&mut self) {
-        if self.active_pane().vfs.is_some() {
-            self.status = "Delete in archive not supported".to_string();
-            return;
-        }
         let sources = selected_paths(self.active_pane());
         if sources.is_empty() {
             self.status = "No file selected".to_string();
@@ -403,20 +986,130 @@ impl App {
         } else {
             format!("{} files", sources.len())
         };
+        if !self.confirm_delete {
+            self.delete_sources(sources, None);
+            return;
+        }
         self.modal = Some(Modal::DeleteDialog {
             sources,
             source_name,
             use_filters: false,
+            filter: FileFilter::parse("*"),
             focus: 1, // Focus on Delete button
         });
     }
 
-    fn begin_find(&mut self) {
-        if self.active_pane().vfs.is_some() {
-            self.status = "Find in archive not supported".to_string();
-            return;
+    /// Delete `sources`, stopping first to ask about non-empty directories when
+    /// that confirmation is enabled.
+    fn delete_sources(&mut self, sources: Vec<PathBuf>, filter: Option<FileFilter>) {
+        if self.confirm_delete_dirs && !self.active_pane().in_archive() {
+            let non_empty: Vec<&PathBuf> = sources
+                .iter()
+                .filter(|path| {
+                    path.is_dir()
+                        && !path.is_symlink()
+                        && fs::read_dir(path).map(|mut it| it.next().is_some()).unwrap_or(false)
+                })
+                .collect();
+            if !non_empty.is_empty() {
+                let message = if non_empty.len() == 1 {
+                    let name = non_empty[0].file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    format!("Directory \"{}\" is not empty. Delete it?", name)
+                } else {
+                    format!("{} directories are not empty. Delete them?", non_empty.len())
+                };
+                self.modal = Some(Modal::Confirm {
+                    title: "Delete".to_string(),
+                    message,
+                    action: PendingConfirm::Delete { sources, filter },
+                });
+                return;
+            }
+        }
+        self.execute_confirm(PendingConfirm::Delete { sources, filter });
+    }
+
+    /// Remove entries of the archive shown in the active pane, stopping at the
+    /// first failure.
+    fn delete_in_archive(&mut self, sources: &[PathBuf]) {
+        let show_hidden = self.show_hidden;
+        let pane = self.active_pane_mut();
+        let removed = pane.fs().remove_all(sources);
+        pane.selected.clear();
+        let _ = pane.refresh(RefreshMode::Keep, show_hidden);
+        self.status = match removed {
+            Ok(()) => format!("Deleted {} item(s)", sources.len()),
+            Err(err) => format!("Delete failed: {err}"),
+        };
+    }
+
+    /// Copy or move `sources` into `dest`. Existing targets are asked about one
+    /// by one unless overwrite confirmation is off.
+    fn start_transfer(&mut self, kind: OverwriteKind, sources: Vec<PathBuf>, dest: PathBuf, options: TransferOptions) {
+        let overwrite = !self.confirm_overwrite;
+        let task = match kind {
+            OverwriteKind::Copy => JobTask::Copy { sources, dest, overwrite, options },
+            OverwriteKind::Move => JobTask::Move { sources, dest, overwrite, options },
+        };
+        self.start_job(task);
+    }
+
+    /// Start the transfer set up in the copy/move dialog. Returns false when the
+    /// dialog should stay open.
+    fn submit_copy_dialog(&mut self, state: &CopyDialogState, is_copy: bool) -> bool {
+        let dest = PathBuf::from(&state.dest);
+        let options = TransferOptions {
+            include_subdirs: state.include_subdirs,
+            newer_only: state.copy_newer_only,
+            filter: state.use_filters.then(|| state.filter.clone()),
+        };
+        let same_fs = state.sources.iter().all(|src| same_filesystem(src, &dest));
+        if state.check_target_space && (is_copy || !same_fs) {
+            let needed = measure_transfer(&state.sources, &options);
+            match available_space(&dest) {
+                Ok(free) if needed > free => {
+                    self.status = format!(
+                        "Not enough space on target: need {}, free {}",
+                        format_size(needed),
+                        format_size(free)
+                    );
+                    return false;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    self.status = format!("Space check failed: {err}");
+                    return false;
+                }
+            }
         }
-        let base = self.active_pane().cwd.clone();
+        let kind = if is_copy { OverwriteKind::Copy } else { OverwriteKind::Move };
+        self.start_transfer(kind, state.sources.clone(), dest, options);
+        true
+    }
+
+    /// Open the directory tree to pick a destination for the copy/move `dialog`.
+    fn open_destination_tree(&mut self, dialog: Modal) {
+        let dest = match &dialog {
+            Modal::CopyDialog(state) | Modal::MoveDialog(state) => PathBuf::from(&state.dest),
+            _ => self.inactive_pane_mut().cwd.clone(),
+        };
+        let base = dest
+            .ancestors()
+            .find(|dir| dir.is_dir())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| self.inactive_pane_mut().cwd.clone());
+        let items = build_tree(&base, 2, self.show_hidden);
+        self.modal = Some(Modal::Tree {
+            pane: self.active,
+            items,
+            selected: 0,
+            scroll: 0,
+            pick_for: Some(Box::new(dialog)),
+        });
+    }
+
+    fn begin_find(&mut self) {
+        let base = self.active_pane().dir().to_path_buf();
         self.modal = Some(Modal::Prompt {
             title: "Find file".to_string(),
             label: "Search:".to_string(),
@@ -433,7 +1126,90 @@ impl App {
             ActivePane::Right => &self.right.cwd,
         };
         let items = build_tree(base, 2, self.show_hidden);
-        self.modal = Some(Modal::Tree { pane, items, selected: 0, scroll: 0 });
+        self.modal = Some(Modal::Tree { pane, items, selected: 0, scroll: 0, pick_for: None });
+    }
+
+    /// Open the filter dialog for `target`, starting from the `current` filter.
+    fn open_filter_dialog(&mut self, target: FilterTarget, current: Option<FileFilter>) {
+        let masks = current.as_ref().map(FileFilter::spec).unwrap_or_default();
+        self.modal = Some(Modal::Filter {
+            target,
+            saved: load_filters(&filters_path()),
+            selected: 0,
+            cursor: masks.len(),
+            masks,
+            case_sensitive: current.is_some_and(|filter| filter.case_sensitive),
+            focus: 0,
+        });
+    }
+
+    /// Hand the filter chosen in the filter dialog to its target; `None` clears it.
+    fn apply_filter(&mut self, target: FilterTarget, filter: Option<FileFilter>) {
+        match target {
+            FilterTarget::Panel(pane) => {
+                self.status = match &filter {
+                    Some(filter) => format!("Filter: {}", filter.spec()),
+                    None => "Filter cleared".to_string(),
+                };
+                let show_hidden = self.show_hidden;
+                let pane = match pane {
+                    ActivePane::Left => &mut self.left,
+                    ActivePane::Right => &mut self.right,
+                };
+                pane.filter = filter;
+                let _ = pane.refresh(RefreshMode::Reset, show_hidden);
+                self.modal = None;
+            }
+            FilterTarget::Dialog(dialog) => {
+                let mut dialog = *dialog;
+                match &mut dialog {
+                    Modal::CopyDialog(state) | Modal::MoveDialog(state) => {
+                        state.use_filters = filter.is_some();
+                        if let Some(filter) = filter {
+                            state.filter = filter;
+                        }
+                    }
+                    Modal::DeleteDialog { use_filters, filter: current, .. } => {
+                        *use_filters = filter.is_some();
+                        if let Some(filter) = filter {
+                            *current = filter;
+                        }
+                    }
+                    Modal::PackDialog(state) => {
+                        state.use_filters = filter.is_some();
+                        if let Some(filter) = filter {
+                            state.filter = filter;
+                        }
+                    }
+                    _ => {}
+                }
+                self.modal = Some(dialog);
+            }
+        }
+    }
+
+    /// Store the masks of the filter `dialog` as `name`, replacing a saved filter
+    /// of the same name, and reopen the dialog on it.
+    fn save_named_filter(&mut self, mut dialog: Modal, name: String) {
+        if let Modal::Filter { saved, selected, masks, case_sensitive, focus, .. } = &mut dialog {
+            let filter = FileFilter { case_sensitive: *case_sensitive, ..FileFilter::parse(masks) };
+            match saved.iter().position(|item| item.name == name) {
+                Some(idx) => {
+                    saved[idx].filter = filter;
+                    *selected = idx;
+                }
+                None => {
+                    saved.push(SavedFilter { name, filter });
+                    *selected = saved.len() - 1;
+                }
+            }
+            *focus = 2;
+            match save_filters(&filters_path(), saved) {
+                Ok(()) => self.status = "Filter saved".to_string(),
+                Err(err) => self.status = format!("Save filter failed: {err}"),
+            }
+        }
+        self.modal = Some(dialog);
     }
 
     fn open_drive_menu(&mut self, pane: ActivePane) {
@@ -442,19 +1218,53 @@ impl App {
     }
 
     fn open_user_menu(&mut self) {
-        let config_path = user_menu_path();
-        let _ = ensure_user_menu_file(&config_path);
+        let local = self.active_pane().cwd.join(LOCAL_MENU_NAME);
+        let (config_path, title) = if local.is_file() {
+            (local, "Local user menu")
+        } else {
+            let global = user_menu_path();
+            let _ = ensure_user_menu_file(&global);
+            (global, "User menu")
+        };
+        self.show_user_menu(config_path, title.to_string());
+    }
+
+    fn show_user_menu(&mut self, config_path: PathBuf, title: String) {
         let items = load_user_menu(&config_path);
+        let items = filter_user_menu(&items, self.active_pane().selected_entry());
         self.modal = Some(Modal::UserMenu {
+            title,
             items,
             selected: 0,
             scroll: 0,
             config_path,
+            stack: Vec::new(),
         });
     }
 
+    /// Open a submenu or run the command of a user menu entry.
+    fn activate_user_menu_item(&mut self, mut modal: Modal, item: UserMenuItem) {
+        let Modal::UserMenu { title, items, selected, scroll, stack, .. } = &mut modal else {
+            return;
+        };
+        if item.is_submenu() {
+            stack.push(UserMenuLevel {
+                title: std::mem::replace(title, item.label.clone()),
+                items: std::mem::replace(items, item.children),
+                selected: *selected,
+                scroll: *scroll,
+            });
+            *selected = 0;
+            *scroll = 0;
+            self.modal = Some(modal);
+            return;
+        }
+        self.modal = None;
+        self.run_user_menu_item(item.macros, Vec::new());
+    }
+
     fn begin_sync_dirs(&mut self) {
-        if self.left.vfs.is_some() || self.right.vfs.is_some() {
+        if self.left.in_archive() || self.right.in_archive() {
             self.status = "Sync in archive not supported".to_string();
             return;
         }
@@ -476,7 +1286,7 @@ impl App {
     }
 
     fn begin_chmod(&mut self) {
-        if self.active_pane().vfs.is_some() {
+        if self.active_pane().in_archive() {
             self.status = "Attributes in archive not supported".to_string();
             return;
         }
@@ -506,39 +1316,196 @@ impl App {
             KeyCode::Char('o') if key.modifiers.contains(Modifiers::CTRL) => {
                 self.hide_all = !self.hide_all;
             }
-            KeyCode::Char(ch) => {
-                self.cmdline.insert(self.cmd_cursor, ch);
-                self.cmd_cursor += 1;
-            }
-            KeyCode::Backspace => {
-                if self.cmd_cursor > 0 {
-                    self.cmd_cursor -= 1;
-                    self.cmdline.remove(self.cmd_cursor);
-                }
-            }
+            KeyCode::Char('e') if key.modifiers.contains(Modifiers::CTRL) => self.history_prev(),
+            KeyCode::Char('x') if key.modifiers.contains(Modifiers::CTRL) => self.history_next(),
+            KeyCode::Up => self.history_prev(),
+            KeyCode::Down => self.history_next(),
+            KeyCode::F(8) if key.modifiers.contains(Modifiers::ALT) => self.open_history(),
+            KeyCode::Char(ch) => self.cmdline_insert(ch),
+            KeyCode::Backspace => self.cmdline_backspace(),
             KeyCode::Delete => {
                 if self.cmd_cursor < self.cmdline.len() {
                     self.cmdline.remove(self.cmd_cursor);
                 }
             }
-            KeyCode::Left => {
-                if self.cmd_cursor > 0 {
-                    self.cmd_cursor -= 1;
-                }
+            KeyCode::Left => self.cmd_cursor = prev_char_boundary(&self.cmdline, self.cmd_cursor),
+            KeyCode::Right => self.cmd_cursor = next_char_boundary(&self.cmdline, self.cmd_cursor),
+            KeyCode::Enter => self.execute_cmdline(),
+            _ => {}
+        }
+        Cmd::none()
+    }
+
+    fn execute_cmdline(&mut self) {
+        let command = self.cmdline.trim().to_string();
+        self.run_command(command);
+    }
+
+    fn run_command(&mut self, command: String) {
+        self.cmdline.clear();
+        self.cmd_cursor = 0;
+        self.cmd_history_pos = None;
+        if command.is_empty() {
+            return;
+        }
+        self.push_history(&command);
+        if command == "cd" {
+            self.change_dir_from_cmdline("");
+            return;
+        }
+        // `cd src && make` and the like are left to the shell.
+        match command.strip_prefix("cd ") {
+            Some(arg) if !arg.contains(SHELL_METACHARACTERS) => {
+                self.change_dir_from_cmdline(arg);
+                return;
             }
-            KeyCode::Right => {
-                if self.cmd_cursor < self.cmdline.len() {
-                    self.cmd_cursor += 1;
+            _ => {}
+        }
+        let cwd = self.active_pane().cwd.clone();
+        self.run_in_shell(&command, &cwd);
+    }
+
+    fn run_in_shell(&mut self, command: &str, cwd: &Path) {
+        let result = run_shell_command(command, cwd);
+        *self.force_clear_frames.borrow_mut() = 3;
+        self.status = match result {
+            Ok(status) => match status.code() {
+                Some(0) => format!("Done: {command}"),
+                Some(code) => format!("Exit code {code}: {command}"),
+                None => format!("Terminated: {command}"),
+            },
+            Err(err) => format!("Command failed: {err}"),
+        };
+        let show_hidden = self.show_hidden;
+        let _ = self.left.refresh(RefreshMode::Keep, show_hidden);
+        let _ = self.right.refresh(RefreshMode::Keep, show_hidden);
+    }
+
+    fn macro_context(&self) -> MacroContext {
+        let active = self.active_pane();
+        let other = match self.active {
+            ActivePane::Left => &self.right,
+            ActivePane::Right => &self.left,
+        };
+        MacroContext {
+            current_dir: active.cwd.clone(),
+            current_file: active.selected_entry().map(|e| macro_arg(active, &e.path)),
+            selected: selected_paths(active).iter().map(|p| macro_arg(active, p)).collect(),
+            other_dir: other.cwd.clone(),
+            other_file: other.selected_entry().map(|e| macro_arg(other, &e.path)),
+        }
+    }
+
+    fn run_user_menu_item(&mut self, macros: Vec<MenuMacro>, answers: Vec<String>) {
+        let ctx = self.macro_context();
+        match expand_macros(&macros, &ctx, &answers) {
+            Expansion::Ready(command) => {
+                if command.trim().is_empty() {
+                    self.status = "Empty command".to_string();
+                    return;
                 }
+                self.run_in_shell(&command, &ctx.current_dir);
             }
-            KeyCode::Enter => {
-                self.status = format!("Command: {}", self.cmdline);
-                self.cmdline.clear();
-                self.cmd_cursor = 0;
+            Expansion::NeedInput { prompt, default } => {
+                self.modal = Some(Modal::Prompt {
+                    title: "User menu".to_string(),
+                    label: prompt,
+                    value: default.clone(),
+                    cursor: default.len(),
+                    action: PendingPrompt::UserMenuInput { macros, answers },
+                });
             }
-            _ => {}
         }
-        Cmd::none()
+    }
+
+    fn change_dir_from_cmdline(&mut self, arg: &str) {
+        let arg = arg.trim().trim_matches(|c| c == '"' || c == '\'');
+        let cwd = self.active_pane().cwd.clone();
+        let target = match arg {
+            "" => home_dir(),
+            "-" => match self.prev_cwd.clone() {
+                Some(prev) => prev,
+                None => {
+                    self.status = "cd: no previous directory".to_string();
+                    return;
+                }
+            },
+            _ => normalize_path(&cwd.join(expand_tilde(arg))),
+        };
+        if !target.is_dir() {
+            self.status = format!("cd: {}: no such directory", target.display());
+            return;
+        }
+        let show_hidden = self.show_hidden;
+        let pane = self.active_pane_mut();
+        pane.cwd = target.clone();
+        pane.mounts.clear();
+        pane.panelized = None;
+        if let Err(err) = pane.refresh(RefreshMode::Reset, show_hidden) {
+            pane.cwd = cwd;
+            let _ = pane.refresh(RefreshMode::Reset, show_hidden);
+            self.status = format!("cd: {err}");
+            return;
+        }
+        self.prev_cwd = Some(cwd);
+        self.status = target.display().to_string();
+    }
+
+    fn push_history(&mut self, command: &str) {
+        self.cmd_history.retain(|item| item != command);
+        self.cmd_history.push(command.to_string());
+        if self.cmd_history.len() > HISTORY_LIMIT {
+            self.cmd_history.remove(0);
+        }
+        if let Err(err) = save_history(&history_path(), &self.cmd_history) {
+            self.log_event(&format!("history save failed: {err}"));
+        }
+    }
+
+    fn cmdline_insert(&mut self, ch: char) {
+        self.cmdline.insert(self.cmd_cursor, ch);
+        self.cmd_cursor += ch.len_utf8();
+    }
+
+    fn cmdline_backspace(&mut self) {
+        if self.cmd_cursor > 0 {
+            self.cmd_cursor = prev_char_boundary(&self.cmdline, self.cmd_cursor);
+            self.cmdline.remove(self.cmd_cursor);
+        }
+    }
+
+    fn history_prev(&mut self) {
+        if self.cmd_history.is_empty() {
+            return;
+        }
+        let pos = match self.cmd_history_pos {
+            Some(pos) => pos.saturating_sub(1),
+            None => self.cmd_history.len() - 1,
+        };
+        self.cmd_history_pos = Some(pos);
+        self.cmdline = self.cmd_history[pos].clone();
+        self.cmd_cursor = self.cmdline.len();
+    }
+
+    fn history_next(&mut self) {
+        let Some(pos) = self.cmd_history_pos else { return };
+        if pos + 1 < self.cmd_history.len() {
+            self.cmd_history_pos = Some(pos + 1);
+            self.cmdline = self.cmd_history[pos + 1].clone();
+        } else {
+            self.cmd_history_pos = None;
+            self.cmdline.clear();
+        }
+        self.cmd_cursor = self.cmdline.len();
+    }
+
+    fn open_history(&mut self) {
+        if self.cmd_history.is_empty() {
+            self.status = "History is empty".to_string();
+            return;
+        }
+        let items: Vec<String> = self.cmd_history.iter().rev().cloned().collect();
+        self.modal = Some(Modal::History { items, selected: 0, scroll: 0 });
     }
 
     fn handle_key(&mut self, key: KeyEvent) -> Cmd<Msg> {
@@ -546,12 +1513,12 @@ impl App {
             return Cmd::none();
         }
         self.log_event(&format!("key {:?} {:?}", key.code, key.modifiers));
-        if self.hide_all {
-            return self.handle_cmdline_key(key);
-        }
         if let Some(modal) = self.modal.take() {
             return self.handle_modal_key(key, modal);
         }
+        if self.hide_all {
+            return self.handle_cmdline_key(key);
+        }
         if self.viewer.is_some() {
             let mut action = ViewerAction::None;
             if let Some(viewer) = self.viewer.as_mut() {
@@ -560,11 +1527,84 @@ impl App {
             match action {
                 ViewerAction::None => {}
                 ViewerAction::Close => self.viewer = None,
-                ViewerAction::Quit => return Cmd::quit(),
+                ViewerAction::Quit => return self.request_quit(),
+                ViewerAction::Search => self.open_viewer_search(),
+                ViewerAction::SearchAgain => {
+                    match self.viewer.as_ref().and_then(|viewer| viewer.finder.clone()) {
+                        Some(finder) => self.run_viewer_search(finder),
+                        None => self.open_viewer_search(),
+                    }
+                }
+                ViewerAction::NextFile => self.view_adjacent(true),
+                ViewerAction::PrevFile => self.view_adjacent(false),
+                ViewerAction::Goto => {
+                    self.modal = Some(Modal::Prompt {
+                        title: "Goto".to_string(),
+                        label: "Line number or percentage:".to_string(),
+                        value: String::new(),
+                        cursor: 0,
+                        action: PendingPrompt::ViewerGoto,
+                    });
+                }
+            }
+            return Cmd::none();
+        }
+        if self.editor.is_some() {
+            let mut action = EditorAction::None;
+            if let Some(editor) = self.editor.as_mut() {
+                action = handle_editor_key(key, editor);
+            }
+            match action {
+                EditorAction::None => {}
+                EditorAction::Close => self.close_editor(),
+                EditorAction::Save => {
+                    self.save_editor();
+                }
+                EditorAction::SaveAs => {
+                    let path = self.editor.as_ref().map(|editor| editor.path.display().to_string()).unwrap_or_default();
+                    self.modal = Some(Modal::Prompt {
+                        title: "Save as".to_string(),
+                        label: "File name:".to_string(),
+                        cursor: path.len(),
+                        value: path,
+                        action: PendingPrompt::EditorSaveAs,
+                    });
+                }
+                EditorAction::Search => self.open_editor_search(),
+                EditorAction::SearchAgain => {
+                    match self.editor.as_ref().and_then(|editor| editor.search.clone()) {
+                        Some(query) => self.run_editor_search(&query),
+                        None => self.open_editor_search(),
+                    }
+                }
+                EditorAction::Replace => {
+                    let value = self.editor.as_ref().and_then(|editor| editor.search.clone()).unwrap_or_default();
+                    self.modal = Some(Modal::Prompt {
+                        title: "Replace".to_string(),
+                        label: "Search for:".to_string(),
+                        cursor: value.len(),
+                        value,
+                        action: PendingPrompt::EditorReplace,
+                    });
+                }
             }
             return Cmd::none();
         }
 
+        if let Some(compare) = self.compare.as_mut() {
+            if handle_compare_key(key, compare) {
+                self.compare = None;
+            }
+            return Cmd::none();
+        }
+
+        // Quick search ends once it times out, and on any key but typing
+        let typing = matches!(key.code, KeyCode::Char(_) | KeyCode::Backspace | KeyCode::Escape);
+        let expired = self.quick_search_time.is_some_and(|time| time.elapsed() > Duration::from_millis(QUICK_SEARCH_TIMEOUT_MS));
+        if !typing || expired {
+            self.end_quick_search();
+        }
+
         let view_height = self.list_height(self.active);
 
         match key.code {
@@ -592,6 +1632,10 @@ impl App {
             KeyCode::F(8) if key.modifiers.contains(Modifiers::CTRL) => {
                 self.begin_sync_dirs();
             }
+            KeyCode::F(8) if key.modifiers.contains(Modifiers::ALT) => self.open_history(),
+            KeyCode::Char('j') if key.modifiers.contains(Modifiers::ALT) => self.open_jobs(),
+            KeyCode::Char('e') if key.modifiers.contains(Modifiers::CTRL) => self.history_prev(),
+            KeyCode::Char('x') if key.modifiers.contains(Modifiers::CTRL) => self.history_next(),
             // Panel mode switching (Ctrl+1 Brief, Ctrl+2 Full, Ctrl+3 Info, Ctrl+4 QuickView)
             KeyCode::Char('1') if key.modifiers.contains(Modifiers::CTRL) => {
                 self.active_pane_mut().mode = PanelMode::Brief;
@@ -607,8 +1651,11 @@ impl App {
             }
             KeyCode::F(1) => self.modal = Some(Modal::Help { page: 0, scroll: 0 }),
             KeyCode::F(2) => self.open_user_menu(),
+            KeyCode::F(9) if key.modifiers.contains(Modifiers::SHIFT) => self.save_setup(),
+            KeyCode::F(9) if key.modifiers.contains(Modifiers::ALT) => self.begin_extract_all(),
+            KeyCode::F(5) if key.modifiers.contains(Modifiers::ALT) => self.begin_pack(),
             KeyCode::F(9) => self.modal = Some(Modal::PullDown { menu_idx: 0, item_idx: 0 }),
-            KeyCode::F(10) => return Cmd::quit(),
+            KeyCode::F(10) => return self.request_quit(),
             KeyCode::F(11) => self.begin_chmod(),
             KeyCode::Tab => {
                 self.active = match self.active {
@@ -627,6 +1674,7 @@ impl App {
                     self.status = format!("Up failed: {err}");
                 }
             }
+            KeyCode::Backspace if self.quick_search.is_none() && !self.cmdline.is_empty() => self.cmdline_backspace(),
             KeyCode::Backspace => {
                 // If quick search is active, remove last character
                 if let Some(ref mut qs) = self.quick_search {
@@ -648,43 +1696,62 @@ impl App {
                     }
                 }
             }
+            KeyCode::Enter if !self.cmdline.trim().is_empty() => self.execute_cmdline(),
             KeyCode::Right | KeyCode::Enter => {
                 let show_hidden = self.show_hidden;
                 match self.active_pane_mut().enter_selected(show_hidden) {
                     Ok(true) => {}
-                    Ok(false) => {
-                        if matches!(key.code, KeyCode::Enter) {
-                            self.open_viewer();
+                    Ok(false) if matches!(key.code, KeyCode::Enter) => {
+                        if self.active_pane().in_archive() {
+                            self.open_archive_entry();
+                        } else if let Some(entry) = self.active_pane().selected_entry() {
+                            let path = entry.path.clone();
+                            self.open_file(&path, path.clone());
                         }
                     }
+                    Ok(false) => {}
                     Err(err) => self.status = format!("Open failed: {err}"),
                 }
             }
-            KeyCode::Char(' ') | KeyCode::Insert => self.active_pane_mut().toggle_select(),
+            KeyCode::Char(' ') if self.cmdline.is_empty() => self.active_pane_mut().toggle_select(),
+            KeyCode::Insert => self.active_pane_mut().toggle_select(),
             KeyCode::F(3) => self.open_viewer(),
             KeyCode::F(4) => self.open_editor(),
             KeyCode::F(5) => self.begin_copy(),
             KeyCode::F(6) => self.begin_move(),
             KeyCode::F(7) => self.begin_mkdir(),
             KeyCode::F(8) => self.begin_delete(),
-            KeyCode::Char('q') if key.modifiers.contains(Modifiers::CTRL) => return Cmd::quit(),
-            KeyCode::Char('+') => self.active_pane_mut().select_all(),
-            KeyCode::Char('-') => self.active_pane_mut().clear_selection(),
-            KeyCode::Char('*') => self.active_pane_mut().invert_selection(),
+            KeyCode::Char('q') if key.modifiers.contains(Modifiers::CTRL) => return self.request_quit(),
+            KeyCode::Char('+') if self.cmdline.is_empty() => self.active_pane_mut().select_all(),
+            KeyCode::Char('-') if self.cmdline.is_empty() => self.active_pane_mut().clear_selection(),
+            KeyCode::Char('*') if self.cmdline.is_empty() => self.active_pane_mut().invert_selection(),
             KeyCode::Escape => {
-                // Clear quick search on Escape
+                // Clear quick search on Escape, otherwise the command line
                 if self.quick_search.is_some() {
                     self.quick_search = None;
                     self.quick_search_time = None;
                     self.status = "Ready".to_string();
+                } else {
+                    self.cmdline.clear();
+                    self.cmd_cursor = 0;
                 }
             }
             KeyCode::Char(ch) if ch.is_alphanumeric() || ch == '.' || ch == '_' => {
-                // Quick search: typing characters jumps to matching file
-                if !key.modifiers.contains(Modifiers::CTRL) && !key.modifiers.contains(Modifiers::ALT) {
+                // Quick search: Alt+letter starts it, as in NC, and plain
+                // typing continues it while it is active
+                if key.modifiers.contains(Modifiers::ALT)
+                    || (self.quick_search.is_some() && !key.modifiers.contains(Modifiers::CTRL))
+                {
                     self.handle_quick_search_char(ch);
+                } else if !key.modifiers.contains(Modifiers::CTRL) {
+                    self.cmdline_insert(ch);
                 }
             }
+            KeyCode::Char(ch) if !key.modifiers.contains(Modifiers::CTRL) && !key.modifiers.contains(Modifiers::ALT) => {
+                // Everything else printable goes to the command line
+                self.end_quick_search();
+                self.cmdline_insert(ch);
+            }
             _ => {}
         }
 
@@ -692,15 +1759,6 @@ impl App {
     }
 
     fn handle_quick_search_char(&mut self, ch: char) {
-        const QUICK_SEARCH_TIMEOUT_MS: u64 = 1500;
-
-        // Reset search if too much time has passed
-        if let Some(last_time) = self.quick_search_time {
-            if last_time.elapsed() > Duration::from_millis(QUICK_SEARCH_TIMEOUT_MS) {
-                self.quick_search = None;
-            }
-        }
-
         // Append character to search string
         let search = self.quick_search.get_or_insert_with(String::new);
         search.push(ch.to_ascii_lowercase());
@@ -710,6 +1768,13 @@ impl App {
         self.do_quick_search();
     }
 
+    fn end_quick_search(&mut self) {
+        if self.quick_search.take().is_some() {
+            self.quick_search_time = None;
+            self.status = "Ready".to_string();
+        }
+    }
+
     fn do_quick_search(&mut self) {
         let search = match &self.quick_search {
             Some(s) => s.clone(),
@@ -742,7 +1807,7 @@ impl App {
                         self.modal = Some(modal);
                     }
                     KeyCode::Right => {
-                        if *page < 3 {
+                        if *page < 5 {
                             *page += 1;
                             *scroll = 0;
                         }
@@ -767,6 +1832,83 @@ impl App {
                     _ => self.modal = Some(modal),
                 }
             }
+            Modal::ViewerSearch { search, cursor, focus } => {
+                match key.code {
+                    KeyCode::Escape | KeyCode::F(10) => self.modal = None,
+                    KeyCode::Tab => {
+                        *focus = (*focus + 1) % 6;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::BackTab => {
+                        *focus = (*focus + 5) % 6;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Char(' ') | KeyCode::Enter if *focus > 0 => {
+                        let flag = match *focus {
+                            1 => &mut search.case_sensitive,
+                            2 => &mut search.whole_word,
+                            3 => &mut search.regex,
+                            4 => &mut search.backward,
+                            _ => &mut search.hex,
+                        };
+                        *flag = !*flag;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Enter => {
+                        if search.query.is_empty() {
+                            return Cmd::none();
+                        }
+                        self.last_search = search.clone();
+                        match Finder::new(search.clone()) {
+                            Ok(finder) => self.run_viewer_search(finder),
+                            Err(err) => {
+                                if let Some(viewer) = self.viewer.as_mut() {
+                                    viewer.message = Some(format!("Invalid search: {err}"));
+                                }
+                            }
+                        }
+                    }
+                    KeyCode::Left if *focus == 0 => {
+                        if *cursor > 0 {
+                            *cursor -= 1;
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Right if *focus == 0 => {
+                        if *cursor < search.query.len() {
+                            *cursor += 1;
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Home if *focus == 0 => {
+                        *cursor = 0;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::End if *focus == 0 => {
+                        *cursor = search.query.len();
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Backspace if *focus == 0 => {
+                        if *cursor > 0 {
+                            *cursor -= 1;
+                            search.query.remove(*cursor);
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Delete if *focus == 0 => {
+                        if *cursor < search.query.len() {
+                            search.query.remove(*cursor);
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Char(ch) if *focus == 0 => {
+                        search.query.insert(*cursor, ch);
+                        *cursor += 1;
+                        self.modal = Some(modal);
+                    }
+                    _ => self.modal = Some(modal),
+                }
+            }
             Modal::About => {
                 if matches!(key.code, KeyCode::Escape | KeyCode::Enter | KeyCode::F(10)) {
                     self.modal = None;
@@ -774,11 +1916,21 @@ impl App {
                     self.modal = Some(modal);
                 }
             }
-            Modal::Config { page, selected, show_hidden, auto_save, confirm_delete, confirm_overwrite } => {
+            Modal::Config {
+                page,
+                selected,
+                show_hidden,
+                auto_save,
+                internal_editor,
+                confirm_delete,
+                confirm_overwrite,
+                confirm_delete_dirs,
+                confirm_quit,
+            } => {
                 let items_per_page = match *page {
                     0 => 1,  // Screen page: show_hidden
-                    1 => 2,  // Confirmations: confirm_delete, confirm_overwrite
-                    _ => 1,
+                    1 => 4,  // Confirmations: delete, overwrite, non-empty dirs, quit
+                    _ => 2,  // Other: auto save, internal editor
                 };
                 match key.code {
                     KeyCode::Escape | KeyCode::F(10) => self.modal = None,
@@ -816,9 +1968,30 @@ impl App {
                                 let _ = self.left.refresh(RefreshMode::Keep, self.show_hidden);
                                 let _ = self.right.refresh(RefreshMode::Keep, self.show_hidden);
                             }
-                            (1, 0) => *confirm_delete = !*confirm_delete,
-                            (1, 1) => *confirm_overwrite = !*confirm_overwrite,
-                            (2, 0) => *auto_save = !*auto_save,
+                            (1, 0) => {
+                                self.confirm_delete = !self.confirm_delete;
+                                *confirm_delete = self.confirm_delete;
+                            }
+                            (1, 1) => {
+                                self.confirm_overwrite = !self.confirm_overwrite;
+                                *confirm_overwrite = self.confirm_overwrite;
+                            }
+                            (1, 2) => {
+                                self.confirm_delete_dirs = !self.confirm_delete_dirs;
+                                *confirm_delete_dirs = self.confirm_delete_dirs;
+                            }
+                            (1, 3) => {
+                                self.confirm_quit = !self.confirm_quit;
+                                *confirm_quit = self.confirm_quit;
+                            }
+                            (2, 0) => {
+                                self.auto_save = !self.auto_save;
+                                *auto_save = self.auto_save;
+                            }
+                            (2, 1) => {
+                                self.internal_editor = !self.internal_editor;
+                                *internal_editor = self.internal_editor;
+                            }
                             _ => {}
                         }
                         self.modal = Some(modal);
@@ -868,16 +2041,202 @@ impl App {
                             }
                             _ => {}
                         }
-                        let _ = target.refresh(RefreshMode::Keep, self.show_hidden);
+                        let _ = target.refresh(RefreshMode::Keep, self.show_hidden);
+                        self.modal = Some(modal);
+                    }
+                    _ => self.modal = Some(modal),
+                }
+            }
+            Modal::Progress { job_id, focus } => {
+                let job_id = *job_id;
+                let Some(job) = self.jobs.get_mut(job_id) else {
+                    // Finished while the dialog was up.
+                    return Cmd::none();
+                };
+                let action = match key.code {
+                    KeyCode::Tab | KeyCode::Right => {
+                        *focus = (*focus + 1) % 3;
+                        None
+                    }
+                    KeyCode::BackTab | KeyCode::Left => {
+                        *focus = if *focus == 0 { 2 } else { *focus - 1 };
+                        None
+                    }
+                    KeyCode::Enter => Some(*focus),
+                    KeyCode::Escape | KeyCode::Char('c') => Some(0),
+                    KeyCode::Char('p') | KeyCode::Char(' ') => Some(1),
+                    KeyCode::Char('b') => Some(2),
+                    _ => None,
+                };
+                match action {
+                    Some(0) => {
+                        job.cancel();
+                        job.set_paused(false);
+                        self.status = "Cancelling...".to_string();
+                        self.modal = Some(modal);
+                    }
+                    Some(1) => {
+                        let paused = !job.is_paused();
+                        job.set_paused(paused);
+                        self.modal = Some(modal);
+                    }
+                    Some(_) => {
+                        self.status = format!("{} continues in background (Alt+J: jobs)", job.task.verb());
+                    }
+                    None => self.modal = Some(modal),
+                }
+            }
+            Modal::Conflict { job_id, focus } => {
+                let job_id = *job_id;
+                let Some(target) = self.jobs.get(job_id).and_then(|job| job.conflict.as_ref()).map(|c| c.target.clone())
+                else {
+                    return Cmd::none();
+                };
+                // Buttons, two rows of four:
+                // Overwrite, Skip, Overwrite all, Skip all / Overwrite older, Rename, Append, Cancel
+                match key.code {
+                    KeyCode::Tab | KeyCode::Right => {
+                        *focus = (*focus + 1) % 8;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::BackTab | KeyCode::Left => {
+                        *focus = (*focus + 7) % 8;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Up | KeyCode::Down => {
+                        *focus = (*focus + 4) % 8;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Escape => self.answer_conflict(job_id, ConflictAnswer::Cancel),
+                    KeyCode::Enter => {
+                        let answer = match *focus {
+                            0 => ConflictAnswer::Overwrite,
+                            1 => ConflictAnswer::Skip,
+                            2 => ConflictAnswer::OverwriteAll,
+                            3 => ConflictAnswer::SkipAll,
+                            4 => ConflictAnswer::OverwriteOlder,
+                            6 => ConflictAnswer::Append,
+                            7 => ConflictAnswer::Cancel,
+                            _ => {
+                                let suggested = unique_name(&target);
+                                let value = suggested
+                                    .file_name()
+                                    .map(|name| name.to_string_lossy().to_string())
+                                    .unwrap_or_default();
+                                self.modal = Some(Modal::Prompt {
+                                    title: "Rename".to_string(),
+                                    label: format!("New name for {}:", target.display()),
+                                    cursor: value.len(),
+                                    value,
+                                    action: PendingPrompt::ConflictRename { job_id, target },
+                                });
+                                return Cmd::none();
+                            }
+                        };
+                        self.answer_conflict(job_id, answer);
+                    }
+                    _ => self.modal = Some(modal),
+                }
+            }
+            Modal::Jobs { selected } => {
+                let count = self.jobs.jobs().len();
+                if count == 0 {
+                    return Cmd::none();
+                }
+                *selected = (*selected).min(count - 1);
+                let job_id = self.jobs.jobs()[*selected].id;
+                match key.code {
+                    KeyCode::Escape | KeyCode::F(10) => self.modal = None,
+                    KeyCode::Up => {
+                        *selected = selected.saturating_sub(1);
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Down => {
+                        if *selected + 1 < count {
+                            *selected += 1;
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Enter => {
+                        self.modal = if self.jobs.get(job_id).is_some_and(|job| job.conflict.is_some()) {
+                            Some(Modal::Conflict { job_id, focus: 0 })
+                        } else {
+                            Some(Modal::Progress { job_id, focus: 0 })
+                        };
+                    }
+                    KeyCode::Char('p') | KeyCode::Char(' ') => {
+                        if let Some(job) = self.jobs.get_mut(job_id) {
+                            let paused = !job.is_paused();
+                            job.set_paused(paused);
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Delete | KeyCode::Char('c') => {
+                        if let Some(job) = self.jobs.get_mut(job_id) {
+                            job.cancel();
+                            job.set_paused(false);
+                        }
+                        self.modal = Some(modal);
+                    }
+                    _ => self.modal = Some(modal),
+                }
+            }
+            Modal::History { items, selected, scroll } => {
+                let view_height = 8usize;
+                match key.code {
+                    KeyCode::Escape | KeyCode::F(10) => self.modal = None,
+                    KeyCode::Up => {
+                        if *selected > 0 {
+                            *selected -= 1;
+                        }
+                        if *selected < *scroll {
+                            *scroll = *selected;
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Down => {
+                        if *selected + 1 < items.len() {
+                            *selected += 1;
+                        }
+                        if *selected >= *scroll + view_height {
+                            *scroll = selected.saturating_sub(view_height - 1);
+                        }
                         self.modal = Some(modal);
                     }
+                    KeyCode::Enter => {
+                        let command = items.get(*selected).cloned();
+                        self.modal = None;
+                        if let Some(command) = command {
+                            self.run_command(command);
+                        }
+                    }
+                    KeyCode::F(4) => {
+                        if let Some(command) = items.get(*selected) {
+                            self.cmdline = command.clone();
+                            self.cmd_cursor = self.cmdline.len();
+                            self.cmd_history_pos = None;
+                        }
+                        self.modal = None;
+                    }
                     _ => self.modal = Some(modal),
                 }
             }
-            Modal::UserMenu { items, selected, scroll, config_path } => {
+            Modal::UserMenu { title, items, selected, scroll, config_path, stack } => {
                 let view_height = 6usize;
                 match key.code {
-                    KeyCode::Escape | KeyCode::F(10) => self.modal = None,
+                    KeyCode::F(10) => self.modal = None,
+                    KeyCode::Escape | KeyCode::Left | KeyCode::Backspace => {
+                        // Back out of a submenu, or close at the top level
+                        if let Some(parent) = stack.pop() {
+                            *title = parent.title;
+                            *items = parent.items;
+                            *selected = parent.selected;
+                            *scroll = parent.scroll;
+                            self.modal = Some(modal);
+                        } else {
+                            self.modal = None;
+                        }
+                    }
                     KeyCode::F(4) => {
                         let _ = ensure_user_menu_file(config_path);
                         let _ = run_external_editor(
@@ -885,13 +2244,8 @@ impl App {
                             config_path,
                         );
                         *self.force_clear_frames.borrow_mut() = 3;
-                        let refreshed = load_user_menu(config_path);
-                        self.modal = Some(Modal::UserMenu {
-                            items: refreshed,
-                            selected: 0,
-                            scroll: 0,
-                            config_path: config_path.clone(),
-                        });
+                        let root_title = stack.first().map(|level| level.title.clone()).unwrap_or(title.clone());
+                        self.show_user_menu(config_path.clone(), root_title);
                     }
                     KeyCode::Up => {
                         if *selected > 0 {
@@ -911,11 +2265,21 @@ impl App {
                         }
                         self.modal = Some(modal);
                     }
-                    KeyCode::Enter => {
-                        if let Some(item) = items.get(*selected) {
-                            self.status = format!("Run: {}", item.command);
+                    KeyCode::Enter | KeyCode::Right => {
+                        match items.get(*selected).cloned() {
+                            Some(item) => self.activate_user_menu_item(modal, item),
+                            None => self.modal = Some(modal),
+                        }
+                    }
+                    KeyCode::Char(ch) => {
+                        let hit = items
+                            .iter()
+                            .find(|item| item.hotkey.is_some_and(|key| key.eq_ignore_ascii_case(&ch)))
+                            .cloned();
+                        match hit {
+                            Some(item) => self.activate_user_menu_item(modal, item),
+                            None => self.modal = Some(modal),
                         }
-                        self.modal = None;
                     }
                     _ => self.modal = Some(modal),
                 }
@@ -956,9 +2320,21 @@ impl App {
                         let items = menu_items(*menu_idx);
                         if let Some(item) = items.get(*item_idx) {
                             match item.action {
-                                MenuAction::Quit => return Cmd::quit(),
+                                MenuAction::Quit => return self.request_quit(),
                                 MenuAction::View => self.open_viewer(),
-                                MenuAction::Edit => self.open_editor(),
+                                MenuAction::Edit => {
+                                    self.open_editor();
+                                    return Cmd::none();
+                                }
+                                MenuAction::Compare => self.open_compare(),
+                                MenuAction::ExtractArchive => {
+                                    self.begin_extract_all();
+                                    return Cmd::none();
+                                }
+                                MenuAction::Pack => {
+                                    self.begin_pack();
+                                    return Cmd::none();
+                                }
                                 MenuAction::Copy => {
                                     self.begin_copy();
                                     return Cmd::none();
@@ -980,12 +2356,20 @@ impl App {
                                         page: 0,
                                         selected: 0,
                                         show_hidden: self.show_hidden,
-                                        auto_save: false,
-                                        confirm_delete: true,
-                                        confirm_overwrite: true,
+                                        auto_save: self.auto_save,
+                                        internal_editor: self.internal_editor,
+                                        confirm_delete: self.confirm_delete,
+                                        confirm_overwrite: self.confirm_overwrite,
+                                        confirm_delete_dirs: self.confirm_delete_dirs,
+                                        confirm_quit: self.confirm_quit,
                                     });
                                     return Cmd::none();
                                 }
+                                MenuAction::SaveSetup => self.save_setup(),
+                                MenuAction::Jobs => {
+                                    self.open_jobs();
+                                    return Cmd::none();
+                                }
                                 MenuAction::PanelOptions => {
                                     self.modal = Some(Modal::PanelOptions {
                                         pane: self.active,
@@ -1043,7 +2427,9 @@ impl App {
                                     let _ = self.left.refresh(RefreshMode::Keep, self.show_hidden);
                                 }
                                 MenuAction::LeftFilter => {
-                                    self.status = "Filters not implemented".to_string();
+                                    let current = self.left.filter.clone();
+                                    self.open_filter_dialog(FilterTarget::Panel(ActivePane::Left), current);
+                                    return Cmd::none();
                                 }
                                 MenuAction::LeftDrive => {
                                     self.open_drive_menu(ActivePane::Left);
@@ -1097,7 +2483,9 @@ impl App {
                                     let _ = self.right.refresh(RefreshMode::Keep, self.show_hidden);
                                 }
                                 MenuAction::RightFilter => {
-                                    self.status = "Filters not implemented".to_string();
+                                    let current = self.right.filter.clone();
+                                    self.open_filter_dialog(FilterTarget::Panel(ActivePane::Right), current);
+                                    return Cmd::none();
                                 }
                                 MenuAction::RightDrive => {
                                     self.open_drive_menu(ActivePane::Right);
@@ -1122,8 +2510,17 @@ impl App {
             Modal::Confirm { action, .. } => {
                 match key.code {
                     KeyCode::Char('y') | KeyCode::Enter => {
-                        self.execute_confirm(action.clone());
+                        if matches!(action, PendingConfirm::Quit) {
+                            return self.quit();
+                        }
+                        let action = action.clone();
+                        // Cleared first so the action may open a follow-up dialog.
+                        self.modal = None;
+                        self.execute_confirm(action);
+                    }
+                    KeyCode::Char('n') if matches!(action, PendingConfirm::CloseEditor) => {
                         self.modal = None;
+                        self.leave_editor();
                     }
                     KeyCode::Char('n') | KeyCode::Escape => {
                         self.modal = None;
@@ -1134,11 +2531,19 @@ impl App {
             Modal::Prompt { value, cursor, action, .. } => {
                 match key.code {
                     KeyCode::Escape => {
-                        self.modal = None;
+                        self.modal = match action {
+                            PendingPrompt::SaveFilter { dialog } => Some((**dialog).clone()),
+                            _ => None,
+                        };
                     }
                     KeyCode::Enter => {
-                        let input = value.trim().to_string();
-                        if !input.is_empty() {
+                        // Search and replace text is taken as typed, and replacing with nothing is allowed.
+                        let verbatim = matches!(
+                            action,
+                            PendingPrompt::EditorSearch | PendingPrompt::EditorReplace | PendingPrompt::EditorReplaceWith { .. }
+                        );
+                        let input = if verbatim { value.clone() } else { value.trim().to_string() };
+                        if !input.is_empty() || matches!(action, PendingPrompt::EditorReplaceWith { .. }) {
                             self.execute_prompt(action.clone(), input);
                         }
                     }
@@ -1208,14 +2613,14 @@ impl App {
                     KeyCode::Enter => {
                         let show_hidden = self.show_hidden;
                         if let Some(path) = items.get(*selected) {
-                            if path.is_dir() {
+                            if self.active_pane().fs().stat(path).is_ok_and(|entry| entry.is_dir) {
                                 let target = self.active_pane_mut();
-                                target.cwd = path.clone();
+                                target.set_dir(path.clone());
                                 let _ = target.refresh(RefreshMode::Reset, show_hidden);
                                 self.modal = None;
                             } else {
                                 self.modal = None;
-                                self.open_viewer_path(path);
+                                self.open_pane_file(path);
                             }
                         } else {
                             self.modal = Some(modal);
@@ -1224,10 +2629,19 @@ impl App {
                     _ => self.modal = Some(modal),
                 }
             }
-            Modal::Tree { pane, items, selected, scroll } => {
+            Modal::Tree { pane, items, selected, scroll, pick_for } => {
                 let view_height = 8usize;
                 match key.code {
-                    KeyCode::Escape | KeyCode::F(10) => self.modal = None,
+                    KeyCode::Escape | KeyCode::F(10) => self.modal = pick_for.take().map(|dialog| *dialog),
+                    KeyCode::Backspace => {
+                        // Re-root the tree one level up.
+                        if let Some(parent) = items.first().and_then(|item| item.path.parent()) {
+                            *items = build_tree(parent, 2, self.show_hidden);
+                            *selected = 0;
+                            *scroll = 0;
+                        }
+                        self.modal = Some(modal);
+                    }
                     KeyCode::Up => {
                         if *selected > 0 {
                             *selected -= 1;
@@ -1246,6 +2660,17 @@ impl App {
                         }
                         self.modal = Some(modal);
                     }
+                    KeyCode::Enter if pick_for.is_some() => {
+                        let mut dialog = pick_for.take().map(|dialog| *dialog);
+                        if let (Some(Modal::CopyDialog(state) | Modal::MoveDialog(state)), Some(item)) =
+                            (&mut dialog, items.get(*selected))
+                        {
+                            state.dest = item.path.display().to_string();
+                            state.cursor = state.dest.len();
+                            state.focus = CopyDialogFocus::Input;
+                        }
+                        self.modal = dialog;
+                    }
                     KeyCode::Enter => {
                         if let Some(item) = items.get(*selected) {
                             match pane {
@@ -1291,13 +2716,13 @@ impl App {
                             match pane {
                                 ActivePane::Left => {
                                     self.left.cwd = path.clone();
-                                    self.left.vfs = None;
+                                    self.left.mounts.clear();
                                     self.left.panelized = None;
                                     let _ = self.left.refresh(RefreshMode::Reset, self.show_hidden);
                                 }
                                 ActivePane::Right => {
                                     self.right.cwd = path.clone();
-                                    self.right.vfs = None;
+                                    self.right.mounts.clear();
                                     self.right.panelized = None;
                                     let _ = self.right.refresh(RefreshMode::Reset, self.show_hidden);
                                 }
@@ -1314,7 +2739,10 @@ impl App {
             Modal::MoveDialog(_) => {
                 return self.handle_copy_move_dialog_key(key, modal, false);
             }
-            Modal::DeleteDialog { sources, use_filters, focus, .. } => {
+            Modal::PackDialog(_) => {
+                return self.handle_pack_dialog_key(key, modal);
+            }
+            Modal::DeleteDialog { sources, use_filters, filter, focus, .. } => {
                 match key.code {
                     KeyCode::Escape => self.modal = None,
                     KeyCode::Tab => {
@@ -1340,13 +2768,13 @@ impl App {
                             1 => {
                                 // Delete button
                                 let sources_clone = sources.clone();
+                                let filter = use_filters.then(|| filter.clone());
                                 self.modal = None;
-                                self.execute_confirm(PendingConfirm::Delete { sources: sources_clone });
+                                self.delete_sources(sources_clone, filter);
                             }
                             2 => {
-                                // Filters button (not implemented yet)
-                                self.status = "Filters not implemented".to_string();
-                                self.modal = Some(modal);
+                                let current = filter.clone();
+                                self.open_filter_dialog(FilterTarget::Dialog(Box::new(modal)), Some(current));
                             }
                             3 => {
                                 // Cancel
@@ -1358,6 +2786,110 @@ impl App {
                     _ => self.modal = Some(modal),
                 }
             }
+            Modal::Filter { target, saved, selected, masks, cursor, case_sensitive, focus } => {
+                match key.code {
+                    KeyCode::Escape => {
+                        self.modal = match target {
+                            FilterTarget::Dialog(dialog) => Some((**dialog).clone()),
+                            FilterTarget::Panel(_) => None,
+                        };
+                    }
+                    KeyCode::Tab => {
+                        *focus = (*focus + 1) % 3;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::BackTab => {
+                        *focus = (*focus + 2) % 3;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Char(' ') | KeyCode::Enter if *focus == 1 => {
+                        *case_sensitive = !*case_sensitive;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Enter => {
+                        let masks = masks.trim();
+                        let filter = (!masks.is_empty())
+                            .then(|| FileFilter { case_sensitive: *case_sensitive, ..FileFilter::parse(masks) });
+                        let target = target.clone();
+                        self.apply_filter(target, filter);
+                    }
+                    KeyCode::Insert => {
+                        if masks.trim().is_empty() {
+                            self.status = "Nothing to save".to_string();
+                            self.modal = Some(modal);
+                        } else {
+                            let value = saved.get(*selected).map(|item| item.name.clone()).unwrap_or_default();
+                            self.modal = Some(Modal::Prompt {
+                                title: "Save filter".to_string(),
+                                label: "Name:".to_string(),
+                                cursor: value.len(),
+                                value,
+                                action: PendingPrompt::SaveFilter { dialog: Box::new(modal) },
+                            });
+                        }
+                    }
+                    KeyCode::Up | KeyCode::Down if *focus == 2 => {
+                        if key.code == KeyCode::Up {
+                            *selected = selected.saturating_sub(1);
+                        } else if *selected + 1 < saved.len() {
+                            *selected += 1;
+                        }
+                        if let Some(item) = saved.get(*selected) {
+                            *masks = item.filter.spec();
+                            *cursor = masks.len();
+                            *case_sensitive = item.filter.case_sensitive;
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Delete if *focus == 2 => {
+                        if *selected < saved.len() {
+                            saved.remove(*selected);
+                            *selected = (*selected).min(saved.len().saturating_sub(1));
+                            if let Err(err) = save_filters(&filters_path(), saved) {
+                                self.status = format!("Save filter failed: {err}");
+                            }
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Left if *focus == 0 => {
+                        *cursor = cursor.saturating_sub(1);
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Right if *focus == 0 => {
+                        if *cursor < masks.len() {
+                            *cursor += 1;
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Home if *focus == 0 => {
+                        *cursor = 0;
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::End if *focus == 0 => {
+                        *cursor = masks.len();
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Backspace if *focus == 0 => {
+                        if *cursor > 0 {
+                            *cursor -= 1;
+                            masks.remove(*cursor);
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Delete if *focus == 0 => {
+                        if *cursor < masks.len() {
+                            masks.remove(*cursor);
+                        }
+                        self.modal = Some(modal);
+                    }
+                    KeyCode::Char(ch) if *focus == 0 => {
+                        masks.insert(*cursor, ch);
+                        *cursor += 1;
+                        self.modal = Some(modal);
+                    }
+                    _ => self.modal = Some(modal),
+                }
+            }
         }
         Cmd::none()
     }
@@ -1375,6 +2907,7 @@ impl App {
             KeyCode::Escape => {
                 self.modal = None;
             }
+            KeyCode::F(10) => self.open_destination_tree(modal),
             KeyCode::Tab => {
                 // Cycle through focus elements
                 state.focus = match state.focus {
@@ -1422,58 +2955,9 @@ impl App {
             KeyCode::Enter => {
                 match state.focus {
                     CopyDialogFocus::Input | CopyDialogFocus::BtnCopy => {
-                        // Execute copy/move
-                        let sources = state.sources.clone();
-                        let dest = PathBuf::from(&state.dest);
-                        self.modal = None;
-
-                        if is_copy {
-                            if let Some(conflicts) = find_conflicts(&sources, &dest) {
-                                self.modal = Some(Modal::Confirm {
-                                    title: "Overwrite".to_string(),
-                                    message: format!("Overwrite {} item(s)?", conflicts),
-                                    action: PendingConfirm::Overwrite {
-                                        kind: OverwriteKind::Copy,
-                                        sources,
-                                        dest,
-                                    },
-                                });
-                                return Cmd::none();
-                            }
-                            let show_hidden = self.show_hidden;
-                            match copy_sources(&sources, &dest, false) {
-                                Ok(()) => {
-                                    self.status = "Copy complete".to_string();
-                                    let _ = self.inactive_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                                }
-                                Err(err) => {
-                                    self.status = format!("Copy failed: {err}");
-                                }
-                            }
-                        } else {
-                            if let Some(conflicts) = find_conflicts(&sources, &dest) {
-                                self.modal = Some(Modal::Confirm {
-                                    title: "Overwrite".to_string(),
-                                    message: format!("Overwrite {} item(s)?", conflicts),
-                                    action: PendingConfirm::Overwrite {
-                                        kind: OverwriteKind::Move,
-                                        sources,
-                                        dest,
-                                    },
-                                });
-                                return Cmd::none();
-                            }
-                            let show_hidden = self.show_hidden;
-                            match move_sources(&sources, &dest, false) {
-                                Ok(()) => {
-                                    self.status = "Move complete".to_string();
-                                    let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                                    let _ = self.inactive_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                                }
-                                Err(err) => {
-                                    self.status = format!("Move failed: {err}");
-                                }
-                            }
+                        let state = state.clone();
+                        if self.submit_copy_dialog(&state, is_copy) {
+                            self.modal = None;
                         }
                     }
                     CopyDialogFocus::IncludeSubdirs => state.include_subdirs = !state.include_subdirs,
@@ -1481,13 +2965,12 @@ impl App {
                     CopyDialogFocus::UseFilters => state.use_filters = !state.use_filters,
                     CopyDialogFocus::CheckTargetSpace => state.check_target_space = !state.check_target_space,
                     CopyDialogFocus::BtnTree => {
-                        self.status = "Tree browser not implemented".to_string();
-                        self.modal = Some(modal);
+                        self.open_destination_tree(modal);
                         return Cmd::none();
                     }
                     CopyDialogFocus::BtnFilters => {
-                        self.status = "Filters not implemented".to_string();
-                        self.modal = Some(modal);
+                        let current = state.filter.clone();
+                        self.open_filter_dialog(FilterTarget::Dialog(Box::new(modal)), Some(current));
                         return Cmd::none();
                     }
                     CopyDialogFocus::BtnCancel => {
@@ -1541,69 +3024,118 @@ impl App {
         Cmd::none()
     }
 
+    fn handle_pack_dialog_key(&mut self, key: KeyEvent, mut modal: Modal) -> Cmd<Msg> {
+        let Modal::PackDialog(state) = &mut modal else {
+            self.modal = Some(modal);
+            return Cmd::none();
+        };
+        let on_input = state.focus == PackDialogFocus::Input;
+        match key.code {
+            KeyCode::Escape => return Cmd::none(),
+            KeyCode::Tab | KeyCode::BackTab => {
+                let idx = PACK_DIALOG_FOCUS.iter().position(|focus| *focus == state.focus).unwrap_or(0);
+                let step = if key.code == KeyCode::Tab { 1 } else { PACK_DIALOG_FOCUS.len() - 1 };
+                state.focus = PACK_DIALOG_FOCUS[(idx + step) % PACK_DIALOG_FOCUS.len()];
+            }
+            KeyCode::Enter => match state.focus {
+                PackDialogFocus::Input | PackDialogFocus::BtnPack => {
+                    let state = state.clone();
+                    if self.submit_pack_dialog(&state) {
+                        return Cmd::none();
+                    }
+                }
+                PackDialogFocus::BtnFilters => {
+                    let current = state.filter.clone();
+                    self.open_filter_dialog(FilterTarget::Dialog(Box::new(modal)), Some(current));
+                    return Cmd::none();
+                }
+                PackDialogFocus::BtnCancel => return Cmd::none(),
+                _ => toggle_pack_option(state),
+            },
+            KeyCode::Char(' ') if !on_input => toggle_pack_option(state),
+            KeyCode::Left | KeyCode::Right if state.focus == PackDialogFocus::Format => {
+                let format = if key.code == KeyCode::Left { state.format.prev() } else { state.format.next() };
+                set_pack_format(state, format);
+            }
+            KeyCode::Left | KeyCode::Right if state.focus == PackDialogFocus::Level => {
+                let levels = state.format.levels();
+                state.level = match key.code {
+                    KeyCode::Left => state.level.saturating_sub(1).max(*levels.start()),
+                    _ => (state.level + 1).min(*levels.end()),
+                };
+            }
+            KeyCode::Left if on_input => state.cursor = prev_char_boundary(&state.dest, state.cursor),
+            KeyCode::Right if on_input => state.cursor = next_char_boundary(&state.dest, state.cursor),
+            KeyCode::Home if on_input => state.cursor = 0,
+            KeyCode::End if on_input => state.cursor = state.dest.len(),
+            KeyCode::Backspace if on_input && state.cursor > 0 => {
+                state.cursor = prev_char_boundary(&state.dest, state.cursor);
+                state.dest.remove(state.cursor);
+            }
+            KeyCode::Delete if on_input && state.cursor < state.dest.len() => {
+                state.dest.remove(state.cursor);
+            }
+            KeyCode::Char(ch) if on_input => {
+                state.dest.insert(state.cursor, ch);
+                state.cursor += ch.len_utf8();
+            }
+            _ => {}
+        }
+        self.modal = Some(modal);
+        Cmd::none()
+    }
+
     fn execute_prompt(&mut self, action: PendingPrompt, input: String) {
         let show_hidden = self.show_hidden;
         match action {
             PendingPrompt::CopyTo { sources } => {
-                let dest = PathBuf::from(input);
-                if let Some(conflicts) = find_conflicts(&sources, &dest) {
-                    self.modal = Some(Modal::Confirm {
-                        title: "Overwrite".to_string(),
-                        message: format!("Overwrite {} item(s)?", conflicts),
-                        action: PendingConfirm::Overwrite {
-                            kind: OverwriteKind::Copy,
-                            sources,
-                            dest,
-                        },
-                    });
-                    return;
-                }
-                match copy_sources(&sources, &dest, false) {
-                    Ok(()) => {
-                        self.status = "Copy complete".to_string();
-                        let _ = self.inactive_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                    }
-                    Err(err) => {
-                        self.status = format!("Copy failed: {err}");
+                self.modal = None;
+                let dest = expand_tilde(&input);
+                match self.active_pane().mounts.last() {
+                    Some(mount) => {
+                        let archive = Arc::clone(&mount.fs);
+                        self.start_job(JobTask::Extract { archive, sources, dest });
                     }
+                    None => self.start_transfer(OverwriteKind::Copy, sources, dest, TransferOptions::default()),
                 }
+                return;
             }
-            PendingPrompt::MoveTo { sources } => {
-                let dest = PathBuf::from(input);
-                if let Some(conflicts) = find_conflicts(&sources, &dest) {
-                    self.modal = Some(Modal::Confirm {
-                        title: "Overwrite".to_string(),
-                        message: format!("Overwrite {} item(s)?", conflicts),
-                        action: PendingConfirm::Overwrite {
-                            kind: OverwriteKind::Move,
-                            sources,
-                            dest,
-                        },
-                    });
-                    return;
-                }
-                match move_sources(&sources, &dest, false) {
-                    Ok(()) => {
-                        self.status = "Move complete".to_string();
-                        let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                        let _ = self.inactive_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                    }
-                    Err(err) => {
-                        self.status = format!("Move failed: {err}");
+            PendingPrompt::ExtractAll { archive } => {
+                self.modal = None;
+                match archive.list(Path::new("")) {
+                    Ok(entries) => {
+                        let sources = entries.into_iter().map(|entry| entry.path).collect();
+                        self.start_job(JobTask::Extract { archive, sources, dest: expand_tilde(&input) });
                     }
+                    Err(err) => self.status = format!("Extract failed: {err}"),
                 }
+                return;
+            }
+            PendingPrompt::MoveTo { sources } => {
+                self.modal = None;
+                self.start_transfer(OverwriteKind::Move, sources, PathBuf::from(input), TransferOptions::default());
+                return;
             }
             PendingPrompt::Mkdir { base } => {
                 let path = base.join(input);
-                if let Err(err) = fs::create_dir_all(&path) {
+                if let Err(err) = self.active_pane().fs().mkdir(&path) {
                     self.status = format!("Mkdir failed: {err}");
                     return;
                 }
-                self.status = format!("Created {}", path.display());
+                self.status = format!("Created {}", self.active_pane().display_path(&path).display());
+                let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
+            }
+            PendingPrompt::Rename { from } => {
+                let to = from.with_file_name(&input);
+                if let Err(err) = self.active_pane().fs().rename(&from, &to) {
+                    self.status = format!("Rename failed: {err}");
+                    return;
+                }
+                self.status = format!("Renamed to {input}");
                 let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
             }
             PendingPrompt::Find { base } => {
-                let results = find_matches(&base, &input, show_hidden);
+                let results = find_matches(self.active_pane().fs(), &base, &find_filter(&input), show_hidden);
                 if results.is_empty() {
                     self.status = "No matches".to_string();
                     self.modal = None;
@@ -1628,61 +3160,99 @@ impl App {
                     let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
                 }
             }
+            PendingPrompt::ConflictRename { job_id, target } => {
+                if input.is_empty() {
+                    self.modal = Some(Modal::Conflict { job_id, focus: 5 });
+                } else {
+                    let renamed = target.parent().map(|dir| dir.join(&input)).unwrap_or_else(|| PathBuf::from(&input));
+                    self.answer_conflict(job_id, ConflictAnswer::Rename(renamed));
+                }
+                return;
+            }
+            PendingPrompt::SaveFilter { dialog } => {
+                self.save_named_filter(*dialog, input);
+                return;
+            }
+            PendingPrompt::ViewerGoto => {
+                if let Some(viewer) = self.viewer.as_mut() {
+                    if let Err(err) = viewer.goto(&input) {
+                        viewer.message = Some(err);
+                    }
+                }
+            }
+            PendingPrompt::EditorSaveAs => {
+                let path = self.active_pane().cwd.join(expand_tilde(&input));
+                if let Some(editor) = self.editor.as_mut() {
+                    editor.message = Some(match editor.save_as(path) {
+                        Ok(()) => "Saved".to_string(),
+                        Err(err) => format!("Save failed: {err}"),
+                    });
+                }
+                self.refresh_after_edit();
+            }
+            PendingPrompt::EditorSearch => self.run_editor_search(&input),
+            PendingPrompt::EditorReplace => {
+                self.modal = Some(Modal::Prompt {
+                    title: "Replace".to_string(),
+                    label: "Replace with:".to_string(),
+                    value: String::new(),
+                    cursor: 0,
+                    action: PendingPrompt::EditorReplaceWith { find: input },
+                });
+                return;
+            }
+            PendingPrompt::EditorReplaceWith { find } => {
+                if let Some(editor) = self.editor.as_mut() {
+                    let count = editor.replace_all(&find, &input);
+                    editor.message = Some(format!("Replaced {count} occurrence(s)"));
+                    editor.scroll_into_view();
+                }
+            }
+            PendingPrompt::UserMenuInput { macros, mut answers } => {
+                self.modal = None;
+                answers.push(input);
+                self.run_user_menu_item(macros, answers);
+                return;
+            }
         }
         self.modal = None;
     }
 
     fn execute_confirm(&mut self, action: PendingConfirm) {
-        let show_hidden = self.show_hidden;
         match action {
-            PendingConfirm::Delete { sources } => {
-                for path in sources {
-                    let result = if path.is_dir() {
-                        fs::remove_dir_all(&path)
-                    } else {
-                        fs::remove_file(&path)
-                    };
-                    if let Err(err) = result {
-                        self.status = format!("Delete failed: {err}");
-                        return;
-                    }
+            PendingConfirm::Delete { sources, .. } if self.active_pane().in_archive() => {
+                self.delete_in_archive(&sources);
+            }
+            PendingConfirm::Delete { sources, filter } => self.start_job(JobTask::Delete { sources, filter }),
+            PendingConfirm::Quit => {}
+            PendingConfirm::CloseEditor => {
+                if self.save_editor() {
+                    self.leave_editor();
                 }
-                self.status = "Deleted".to_string();
-                let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
             }
-            PendingConfirm::Overwrite { kind, sources, dest } => {
-                let result = match kind {
-                    OverwriteKind::Copy => copy_sources(&sources, &dest, true),
-                    OverwriteKind::Move => move_sources(&sources, &dest, true),
-                };
-                match result {
-                    Ok(()) => {
-                        self.status = "Operation complete".to_string();
-                        let _ = self.active_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                        let _ = self.inactive_pane_mut().refresh(RefreshMode::Keep, show_hidden);
-                    }
-                    Err(err) => {
-                        self.status = format!("Overwrite failed: {err}");
-                    }
+            PendingConfirm::UpdateArchive { archive, entry, temp_path } => {
+                let stored = archive.open_write(&entry).and_then(|mut writer| {
+                    io::copy(&mut fs::File::open(&temp_path)?, &mut writer)?;
+                    writer.finish()
+                });
+                match stored {
+                    Ok(()) => self.status = format!("Updated {} in {}", entry.display(), archive.name()),
+                    Err(err) => self.status = format!("Update failed: {err}"),
                 }
+                self.refresh_after_edit();
             }
             PendingConfirm::Sync { ops, src_root, dst_root } => {
-                match sync_execute(&ops, &src_root, &dst_root) {
-                    Ok(count) => {
-                        self.status = format!("Synchronized {}", count);
-                        let _ = self.left.refresh(RefreshMode::Keep, show_hidden);
-                        let _ = self.right.refresh(RefreshMode::Keep, show_hidden);
-                    }
-                    Err(err) => {
-                        self.status = format!("Sync failed: {err}");
-                    }
-                }
+                self.start_job(JobTask::Sync { ops, src_root, dst_root });
+            }
+            PendingConfirm::AddToArchive { archive, sources, dest, remove_sources } => {
+                self.start_job(JobTask::AddToArchive { archive, sources, dest, remove_sources });
             }
         }
     }
 
     fn handle_mouse(&mut self, mouse: MouseEvent) {
-        if self.viewer.is_some() || self.modal.is_some() || self.hide_all {
+        if self.viewer.is_some() || self.editor.is_some() || self.compare.is_some() || self.modal.is_some() || self.hide_all
+        {
             return;
         }
         self.log_event(&format!("mouse {:?} @({}, {})", mouse.kind, mouse.x, mouse.y));
@@ -1759,8 +3329,25 @@ impl App {
 
         render_background(frame, self.theme);
 
+        if let Some(editor) = &self.editor {
+            render_editor(editor, frame, self.theme);
+            if let Some(modal) = &self.modal {
+                render_modal_wrapper(frame, modal, self.theme, &self.left, &self.right, &self.jobs);
+            }
+            return;
+        }
+        if let Some(compare) = &self.compare {
+            render_compare(compare, frame, self.theme);
+            if let Some(modal) = &self.modal {
+                render_modal_wrapper(frame, modal, self.theme, &self.left, &self.right, &self.jobs);
+            }
+            return;
+        }
         if let Some(viewer) = &self.viewer {
             render_viewer(viewer, frame, self.theme);
+            if let Some(modal) = &self.modal {
+                render_modal_wrapper(frame, modal, self.theme, &self.left, &self.right, &self.jobs);
+            }
             return;
         }
 
@@ -1793,7 +3380,7 @@ impl App {
         );
 
         if let Some(modal) = &self.modal {
-            render_modal_wrapper(frame, modal, self.theme, &self.left, &self.right);
+            render_modal_wrapper(frame, modal, self.theme, &self.left, &self.right, &self.jobs);
         }
     }
 }
@@ -1802,15 +3389,26 @@ impl Model for App {
     type Message = Msg;
 
     fn update(&mut self, msg: Msg) -> Cmd<Msg> {
-        match msg {
+        let cmd = match msg {
             Msg::Event(Event::Key(key)) => self.handle_key(key),
             Msg::Event(Event::Mouse(mouse)) => {
                 self.handle_mouse(mouse);
                 Cmd::none()
             }
+            Msg::Event(Event::Tick) => {
+                if let Some(viewer) = self.viewer.as_mut() {
+                    viewer.poll_follow();
+                }
+                self.poll_jobs()
+            }
             Msg::Event(_) => Cmd::none(),
-            Msg::Quit => Cmd::quit(),
-        }
+            Msg::Job(update) => {
+                self.apply_job_update(update);
+                Cmd::none()
+            }
+            Msg::Quit => self.quit(),
+        };
+        self.schedule_tick(cmd)
     }
 
     fn view(&self, frame: &mut Frame) {
@@ -1818,6 +3416,22 @@ impl Model for App {
     }
 }
 
+/// Build a pane from its saved setup, falling back to `fallback` when the
+/// saved directory is gone or unreadable.
+fn pane_from_setup(panel: &PanelSetup, fallback: &Path, show_hidden: bool) -> io::Result<Pane> {
+    let cwd = panel.cwd.clone().filter(|dir| dir.is_dir()).unwrap_or_else(|| fallback.to_path_buf());
+    let mut pane = Pane::new(cwd);
+    pane.sort_mode = panel.sort_mode;
+    pane.dirs_first = panel.dirs_first;
+    pane.mode = panel.mode;
+    pane.filter = panel.filter.clone();
+    if pane.refresh(RefreshMode::Reset, show_hidden).is_err() {
+        pane.cwd = fallback.to_path_buf();
+        pane.refresh(RefreshMode::Reset, show_hidden)?;
+    }
+    Ok(pane)
+}
+
 pub fn ensure_visible(state: &mut ftui::widgets::table::TableState, view_height: usize) {
     if view_height == 0 {
         return;
@@ -1832,6 +3446,43 @@ pub fn ensure_visible(state: &mut ftui::widgets::table::TableState, view_height:
     }
 }
 
+/// Space or Enter on a pack dialog option: flip a checkbox, or step the
+/// format or level to the next value.
+fn toggle_pack_option(state: &mut PackDialogState) {
+    match state.focus {
+        PackDialogFocus::Format => set_pack_format(state, state.format.next()),
+        PackDialogFocus::Level => {
+            let levels = state.format.levels();
+            state.level = if state.level >= *levels.end() { *levels.start() } else { state.level + 1 };
+        }
+        PackDialogFocus::RelativePaths => state.relative_paths = !state.relative_paths,
+        PackDialogFocus::UseFilters => state.use_filters = !state.use_filters,
+        PackDialogFocus::DeleteSources => state.delete_sources = !state.delete_sources,
+        _ => {}
+    }
+}
+
+/// Switch the pack dialog to `format`, renaming the archive to match and
+/// resetting the level to the format's default.
+fn set_pack_format(state: &mut PackDialogState, format: PackFormat) {
+    if let Some(stem) = state.dest.strip_suffix(state.format.extension()) {
+        state.dest = format!("{stem}{}", format.extension());
+        state.cursor = state.dest.len();
+    }
+    state.format = format;
+    state.level = format.default_level();
+}
+
+/// Byte index of the character before the one at `idx` in `text`.
+fn prev_char_boundary(text: &str, idx: usize) -> usize {
+    text[..idx].char_indices().next_back().map_or(0, |(pos, _)| pos)
+}
+
+/// Byte index of the character after the one at `idx` in `text`.
+fn next_char_boundary(text: &str, idx: usize) -> usize {
+    text[idx..].chars().next().map_or(idx, |ch| idx + ch.len_utf8())
+}
+
 pub fn selected_paths(pane: &Pane) -> Vec<PathBuf> {
     if pane.selected.is_empty() {
         return pane.selected_entry().map(|e| e.path.clone()).into_iter().collect();
@@ -1844,19 +3495,127 @@ pub fn selected_paths(pane: &Pane) -> Vec<PathBuf> {
         .collect()
 }
 
+/// How a panel entry is passed to user menu commands: plain names for a
+/// directory listing, full paths for panelized search results.
+fn macro_arg(pane: &Pane, path: &Path) -> String {
+    if pane.panelized.is_some() {
+        path.display().to_string()
+    } else {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string())
+    }
+}
+
 pub fn handle_viewer_key(key: KeyEvent, viewer: &mut Viewer) -> ViewerAction {
+    viewer.message = None;
     match key.code {
         KeyCode::Escape => return ViewerAction::Close,
-        KeyCode::Up => viewer.scroll = viewer.scroll.saturating_sub(1),
-        KeyCode::Down => viewer.scroll = viewer.scroll.saturating_add(1),
-        KeyCode::PageUp => viewer.scroll = viewer.scroll.saturating_sub(10),
-        KeyCode::PageDown => viewer.scroll = viewer.scroll.saturating_add(10),
+        KeyCode::Up => viewer.scroll_by(-1),
+        KeyCode::Down => viewer.scroll_by(1),
+        KeyCode::PageUp => viewer.scroll_by(-(viewer.view_height.get().max(2) as isize - 1)),
+        KeyCode::PageDown => viewer.scroll_by(viewer.view_height.get().max(2) as isize - 1),
+        KeyCode::Right if key.modifiers.contains(Modifiers::CTRL) => return ViewerAction::NextFile,
+        KeyCode::Left if key.modifiers.contains(Modifiers::CTRL) => return ViewerAction::PrevFile,
+        KeyCode::Char('+') => return ViewerAction::NextFile,
+        KeyCode::Char('-') => return ViewerAction::PrevFile,
+        KeyCode::Left => viewer.scroll_sideways(-1),
+        KeyCode::Right => viewer.scroll_sideways(1),
+        KeyCode::Home => viewer.scroll_home(),
+        KeyCode::End => viewer.scroll_end(),
+        KeyCode::F(2) => viewer.toggle_wrap(),
+        KeyCode::F(8) => viewer.cycle_encoding(),
+        KeyCode::F(5) => return ViewerAction::Goto,
+        KeyCode::Char('f') if key.modifiers.contains(Modifiers::CTRL) => viewer.toggle_follow(),
+        KeyCode::Char(digit @ '0'..='9') => {
+            let slot = digit as usize - '0' as usize;
+            if key.modifiers.contains(Modifiers::CTRL) {
+                viewer.set_bookmark(slot);
+            } else {
+                viewer.goto_bookmark(slot);
+            }
+        }
         KeyCode::F(10) => return ViewerAction::Quit,
+        KeyCode::F(7) if key.modifiers.contains(Modifiers::SHIFT) => return ViewerAction::SearchAgain,
+        KeyCode::F(7) => return ViewerAction::Search,
+        KeyCode::F(4) => viewer.toggle_mode(),
         _ => {}
     }
     ViewerAction::None
 }
 
+pub fn handle_editor_key(key: KeyEvent, editor: &mut Editor) -> EditorAction {
+    editor.message = None;
+    let ctrl = key.modifiers.contains(Modifiers::CTRL);
+    let shift = key.modifiers.contains(Modifiers::SHIFT);
+    let motion = match key.code {
+        KeyCode::Left if ctrl => Some(Motion::WordLeft),
+        KeyCode::Right if ctrl => Some(Motion::WordRight),
+        KeyCode::Home if ctrl => Some(Motion::FileStart),
+        KeyCode::End if ctrl => Some(Motion::FileEnd),
+        KeyCode::Left => Some(Motion::Left),
+        KeyCode::Right => Some(Motion::Right),
+        KeyCode::Up => Some(Motion::Up),
+        KeyCode::Down => Some(Motion::Down),
+        KeyCode::Home => Some(Motion::LineStart),
+        KeyCode::End => Some(Motion::LineEnd),
+        KeyCode::PageUp => Some(Motion::PageUp),
+        KeyCode::PageDown => Some(Motion::PageDown),
+        _ => None,
+    };
+    if let Some(motion) = motion {
+        editor.move_cursor(motion, shift);
+        editor.scroll_into_view();
+        return EditorAction::None;
+    }
+    match key.code {
+        KeyCode::Escape | KeyCode::F(10) => return EditorAction::Close,
+        KeyCode::F(2) if shift => return EditorAction::SaveAs,
+        KeyCode::F(2) => return EditorAction::Save,
+        KeyCode::F(7) if shift => return EditorAction::SearchAgain,
+        KeyCode::F(7) => return EditorAction::Search,
+        KeyCode::F(4) => return EditorAction::Replace,
+        KeyCode::Insert if ctrl => editor.copy(),
+        KeyCode::Insert if shift => editor.paste(),
+        KeyCode::Insert => editor.toggle_overwrite(),
+        KeyCode::Delete if shift => editor.cut(),
+        KeyCode::Delete => editor.delete(),
+        KeyCode::Backspace => editor.backspace(),
+        KeyCode::Enter => editor.newline(),
+        KeyCode::Tab => editor.insert_char('\t'),
+        KeyCode::Char('a') if ctrl => editor.select_all(),
+        KeyCode::Char('c') if ctrl => editor.copy(),
+        KeyCode::Char('x') if ctrl => editor.cut(),
+        KeyCode::Char('v') if ctrl => editor.paste(),
+        KeyCode::Char('z') if ctrl => editor.undo(),
+        KeyCode::Char('y') if ctrl => editor.redo(),
+        KeyCode::Char(ch) if !ctrl => editor.insert_char(ch),
+        _ => {}
+    }
+    editor.scroll_into_view();
+    EditorAction::None
+}
+
+/// Keys of the compare view; returns true when it should close.
+pub fn handle_compare_key(key: KeyEvent, compare: &mut Compare) -> bool {
+    compare.message = None;
+    match key.code {
+        KeyCode::Escape | KeyCode::F(10) => return true,
+        KeyCode::Up => compare.scroll_by(-1),
+        KeyCode::Down => compare.scroll_by(1),
+        KeyCode::PageUp => compare.scroll_by(-(compare.page().max(2) as isize - 1)),
+        KeyCode::PageDown => compare.scroll_by(compare.page().max(2) as isize - 1),
+        KeyCode::Left => compare.scroll_sideways(-1),
+        KeyCode::Right => compare.scroll_sideways(1),
+        KeyCode::Home => compare.scroll_home(),
+        KeyCode::End => compare.scroll_end(),
+        KeyCode::Char('n') | KeyCode::Tab => compare.goto_difference(true),
+        KeyCode::Char('p') | KeyCode::BackTab => compare.goto_difference(false),
+        _ => {}
+    }
+    false
+}
+
 pub fn hit_test_rows(x: u16, y: u16, layout: &LayoutCache) -> Option<(ActivePane, usize)> {
     if layout.left_table.contains(x, y) {
         let row = (y - layout.left_table.y) as usize;
@@ -1870,10 +3629,31 @@ pub fn hit_test_rows(x: u16, y: u16, layout: &LayoutCache) -> Option<(ActivePane
 }
 
 pub fn run_external_editor(editor: &str, path: &Path) -> io::Result<()> {
+    with_suspended_terminal(false, || std::process::Command::new(editor).arg(path).status()).map(|_| ())
+}
+
+pub fn run_shell_command(command: &str, cwd: &Path) -> io::Result<ExitStatus> {
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    with_suspended_terminal(true, || {
+        println!("{}> {}", cwd.display(), command);
+        std::process::Command::new(&shell)
+            .arg("-c")
+            .arg(command)
+            .current_dir(cwd)
+            .status()
+    })
+}
+
+/// Leave the alternate screen, run `run` on the plain terminal, then restore the UI.
+/// With `pause` set the user gets to read the output before the panels come back.
+fn with_suspended_terminal<T>(pause: bool, run: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
     let mut stdout = std::io::stdout();
     crossterm::terminal::disable_raw_mode().ok();
     execute!(stdout, LeaveAlternateScreen, DisableMouseCapture)?;
-    let status = std::process::Command::new(editor).arg(path).status();
+    let result = run();
+    if pause {
+        wait_for_keypress(&mut stdout);
+    }
     execute!(
         stdout,
         EnterAlternateScreen,
@@ -1885,5 +3665,20 @@ pub fn run_external_editor(editor: &str, path: &Path) -> io::Result<()> {
     while event::poll(Duration::from_millis(0))? {
         let _ = event::read();
     }
-    status.map(|_| ())
+    result
+}
+
+fn wait_for_keypress(stdout: &mut io::Stdout) {
+    let _ = write!(stdout, "\r\nPress any key to return to FrankenCommander...");
+    let _ = stdout.flush();
+    crossterm::terminal::enable_raw_mode().ok();
+    loop {
+        match event::read() {
+            Ok(event::Event::Key(key)) if key.kind == event::KeyEventKind::Press => break,
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    crossterm::terminal::disable_raw_mode().ok();
+    let _ = write!(stdout, "\r\n");
 }
@@ -0,0 +1,213 @@
+#![forbid(unsafe_code)]
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::filter::FileFilter;
+use crate::model::{PanelMode, SortMode};
+
+/// Per-panel part of the saved setup.
+#[derive(Debug, Clone)]
+pub struct PanelSetup {
+    pub cwd: Option<PathBuf>,
+    pub sort_mode: SortMode,
+    pub dirs_first: bool,
+    pub mode: PanelMode,
+    pub hidden: bool,
+    pub filter: Option<FileFilter>,
+}
+
+impl Default for PanelSetup {
+    fn default() -> Self {
+        Self {
+            cwd: None,
+            sort_mode: SortMode::NameAsc,
+            dirs_first: true,
+            mode: PanelMode::default(),
+            hidden: false,
+            filter: None,
+        }
+    }
+}
+
+/// Everything written to `~/.frankencommander/config` by "Save setup".
+#[derive(Debug, Clone)]
+pub struct Setup {
+    pub show_hidden: bool,
+    pub confirm_delete: bool,
+    pub confirm_overwrite: bool,
+    pub confirm_delete_dirs: bool,
+    pub confirm_quit: bool,
+    pub auto_save: bool,
+    pub internal_editor: bool,
+    pub left: PanelSetup,
+    pub right: PanelSetup,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Self {
+            show_hidden: false,
+            confirm_delete: true,
+            confirm_overwrite: true,
+            confirm_delete_dirs: true,
+            confirm_quit: true,
+            auto_save: false,
+            internal_editor: true,
+            left: PanelSetup::default(),
+            right: PanelSetup::default(),
+        }
+    }
+}
+
+/// Read the INI-style setup file. Missing files and unknown keys fall back to defaults.
+pub fn load_setup(path: &Path) -> Setup {
+    let mut setup = Setup::default();
+    let Ok(content) = fs::read_to_string(path) else { return setup };
+    let mut section = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = name.trim().to_lowercase();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let (key, value) = (key.trim(), value.trim());
+        match section.as_str() {
+            "screen" => {
+                if key == "show_hidden" {
+                    setup.show_hidden = parse_bool(value, setup.show_hidden);
+                }
+            }
+            "confirmations" => match key {
+                "delete" => setup.confirm_delete = parse_bool(value, setup.confirm_delete),
+                "overwrite" => setup.confirm_overwrite = parse_bool(value, setup.confirm_overwrite),
+                "delete_dirs" => setup.confirm_delete_dirs = parse_bool(value, setup.confirm_delete_dirs),
+                "quit" => setup.confirm_quit = parse_bool(value, setup.confirm_quit),
+                _ => {}
+            },
+            "other" => match key {
+                "auto_save" => setup.auto_save = parse_bool(value, setup.auto_save),
+                "internal_editor" => setup.internal_editor = parse_bool(value, setup.internal_editor),
+                _ => {}
+            },
+            "left" => apply_panel_key(&mut setup.left, key, value),
+            "right" => apply_panel_key(&mut setup.right, key, value),
+            _ => {}
+        }
+    }
+    setup
+}
+
+pub fn save_setup(path: &Path, setup: &Setup) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = String::from("# FrankenCommander setup\n");
+    out.push_str(&format!("\n[screen]\nshow_hidden = {}\n", setup.show_hidden));
+    out.push_str(&format!(
+        "\n[confirmations]\ndelete = {}\noverwrite = {}\ndelete_dirs = {}\nquit = {}\n",
+        setup.confirm_delete, setup.confirm_overwrite, setup.confirm_delete_dirs, setup.confirm_quit
+    ));
+    out.push_str(&format!(
+        "\n[other]\nauto_save = {}\ninternal_editor = {}\n",
+        setup.auto_save, setup.internal_editor
+    ));
+    for (name, panel) in [("left", &setup.left), ("right", &setup.right)] {
+        out.push_str(&format!("\n[{}]\n", name));
+        if let Some(cwd) = &panel.cwd {
+            out.push_str(&format!("cwd = {}\n", cwd.display()));
+        }
+        out.push_str(&format!("sort = {}\n", sort_mode_key(panel.sort_mode)));
+        out.push_str(&format!("dirs_first = {}\n", panel.dirs_first));
+        out.push_str(&format!("mode = {}\n", panel_mode_key(panel.mode)));
+        out.push_str(&format!("hidden = {}\n", panel.hidden));
+        if let Some(filter) = &panel.filter {
+            out.push_str(&format!("filter = {}\n", filter.spec()));
+            out.push_str(&format!("filter_case_sensitive = {}\n", filter.case_sensitive));
+        }
+    }
+    fs::write(path, out)
+}
+
+fn apply_panel_key(panel: &mut PanelSetup, key: &str, value: &str) {
+    match key {
+        "cwd" if !value.is_empty() => panel.cwd = Some(PathBuf::from(value)),
+        "sort" => panel.sort_mode = parse_sort_mode(value).unwrap_or(panel.sort_mode),
+        "dirs_first" => panel.dirs_first = parse_bool(value, panel.dirs_first),
+        "mode" => panel.mode = parse_panel_mode(value).unwrap_or(panel.mode),
+        "hidden" => panel.hidden = parse_bool(value, panel.hidden),
+        "filter" if !value.is_empty() => {
+            let case_sensitive = panel.filter.as_ref().is_some_and(|filter| filter.case_sensitive);
+            panel.filter = Some(FileFilter { case_sensitive, ..FileFilter::parse(value) });
+        }
+        "filter_case_sensitive" => {
+            if let Some(filter) = &mut panel.filter {
+                filter.case_sensitive = parse_bool(value, filter.case_sensitive);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_bool(value: &str, default: bool) -> bool {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => true,
+        "false" | "no" | "off" | "0" => false,
+        _ => default,
+    }
+}
+
+fn sort_mode_key(mode: SortMode) -> &'static str {
+    match mode {
+        SortMode::NameAsc => "name_asc",
+        SortMode::NameDesc => "name_desc",
+        SortMode::ExtAsc => "ext_asc",
+        SortMode::ExtDesc => "ext_desc",
+        SortMode::TimeAsc => "time_asc",
+        SortMode::TimeDesc => "time_desc",
+        SortMode::SizeAsc => "size_asc",
+        SortMode::SizeDesc => "size_desc",
+        SortMode::Unsorted => "unsorted",
+    }
+}
+
+fn parse_sort_mode(value: &str) -> Option<SortMode> {
+    Some(match value {
+        "name_asc" => SortMode::NameAsc,
+        "name_desc" => SortMode::NameDesc,
+        "ext_asc" => SortMode::ExtAsc,
+        "ext_desc" => SortMode::ExtDesc,
+        "time_asc" => SortMode::TimeAsc,
+        "time_desc" => SortMode::TimeDesc,
+        "size_asc" => SortMode::SizeAsc,
+        "size_desc" => SortMode::SizeDesc,
+        "unsorted" => SortMode::Unsorted,
+        _ => return None,
+    })
+}
+
+fn panel_mode_key(mode: PanelMode) -> &'static str {
+    match mode {
+        PanelMode::Brief => "brief",
+        PanelMode::Full => "full",
+        PanelMode::Info => "info",
+        PanelMode::Tree => "tree",
+        PanelMode::QuickView => "quick_view",
+    }
+}
+
+fn parse_panel_mode(value: &str) -> Option<PanelMode> {
+    Some(match value {
+        "brief" => PanelMode::Brief,
+        "full" => PanelMode::Full,
+        "info" => PanelMode::Info,
+        "tree" => PanelMode::Tree,
+        "quick_view" => PanelMode::QuickView,
+        _ => return None,
+    })
+}
@@ -0,0 +1,360 @@
+#![forbid(unsafe_code)]
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+use crate::encoding::Encoding;
+use crate::model::{Compare, CompareContent};
+use crate::viewer::{looks_binary, ViewSource, HEX_ROW};
+
+/// Beyond this many inserted plus deleted lines the rest is shown as one
+/// changed block; aligning it would take too much memory.
+const MAX_EDITS: usize = 2000;
+
+/// Bytes compared per read when looking for differing bytes.
+const CHUNK: usize = 64 * 1024;
+
+/// Larger files are compared byte by byte, as a line compare holds both
+/// files in memory.
+const MAX_TEXT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// How a row of the compare view differs between the two files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,
+    Changed,
+    /// Only in the left file.
+    Deleted,
+    /// Only in the right file.
+    Inserted,
+}
+
+/// One aligned row: line indices into each side, `None` for the gap
+/// opposite an inserted or deleted line.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffRow {
+    pub kind: DiffKind,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Same,
+    Delete,
+    Insert,
+}
+
+fn split_lines(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let body = text.strip_suffix('\n').unwrap_or(text);
+    body.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line).to_string()).collect()
+}
+
+/// Myers' O(ND) diff of two sequences of line ids. Returns `None` when they
+/// need more than `MAX_EDITS` edits.
+fn myers(a: &[u32], b: &[u32]) -> Option<Vec<Op>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    // trace[d] holds v for diagonals -d..=d after round d.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    for d in 0..=max as isize {
+        if d as usize > MAX_EDITS {
+            return None;
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        if v[(offset + n - m) as usize] >= n && (n - m).abs() <= d {
+            return Some(backtrack(&trace, n, m));
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let prev = &trace[d as usize - 1];
+        let at = |k: isize| prev[(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Same);
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            ops.push(Op::Insert);
+        } else {
+            ops.push(Op::Delete);
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.extend(std::iter::repeat_n(Op::Same, x as usize));
+    ops.reverse();
+    ops
+}
+
+/// Number lines so equal lines get equal ids and compare cheaply.
+fn line_ids<'a>(lines: &'a [String], ids: &mut HashMap<&'a str, u32>) -> Vec<u32> {
+    lines
+        .iter()
+        .map(|line| {
+            let next = ids.len() as u32;
+            *ids.entry(line.as_str()).or_insert(next)
+        })
+        .collect()
+}
+
+/// Align `left` and `right` line by line. Returns the rows, the first row
+/// of every block of differences, and whether alignment was given up on.
+pub fn diff_lines(left: &[String], right: &[String]) -> (Vec<DiffRow>, Vec<usize>, bool) {
+    let mut ids = HashMap::new();
+    let a = line_ids(left, &mut ids);
+    let b = line_ids(right, &mut ids);
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (middle_a, middle_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let (middle, gave_up) = match myers(middle_a, middle_b) {
+        Some(ops) => (ops, false),
+        None => {
+            let mut ops = vec![Op::Delete; middle_a.len()];
+            ops.extend(std::iter::repeat_n(Op::Insert, middle_b.len()));
+            (ops, true)
+        }
+    };
+    let ops = std::iter::repeat_n(Op::Same, prefix).chain(middle).chain(std::iter::repeat_n(Op::Same, suffix));
+
+    let mut rows = Vec::new();
+    let mut hunks = Vec::new();
+    let (mut deleted, mut inserted) = (Vec::new(), Vec::new());
+    let (mut x, mut y) = (0, 0);
+    let mut flush = |rows: &mut Vec<DiffRow>, deleted: &mut Vec<usize>, inserted: &mut Vec<usize>| {
+        if deleted.is_empty() && inserted.is_empty() {
+            return;
+        }
+        hunks.push(rows.len());
+        for idx in 0..deleted.len().max(inserted.len()) {
+            let (left, right) = (deleted.get(idx).copied(), inserted.get(idx).copied());
+            let kind = match (left, right) {
+                (Some(_), Some(_)) => DiffKind::Changed,
+                (Some(_), None) => DiffKind::Deleted,
+                _ => DiffKind::Inserted,
+            };
+            rows.push(DiffRow { kind, left, right });
+        }
+        deleted.clear();
+        inserted.clear();
+    };
+    for op in ops {
+        match op {
+            Op::Same => {
+                flush(&mut rows, &mut deleted, &mut inserted);
+                rows.push(DiffRow { kind: DiffKind::Same, left: Some(x), right: Some(y) });
+                x += 1;
+                y += 1;
+            }
+            Op::Delete => {
+                deleted.push(x);
+                x += 1;
+            }
+            Op::Insert => {
+                inserted.push(y);
+                y += 1;
+            }
+        }
+    }
+    flush(&mut rows, &mut deleted, &mut inserted);
+    (rows, hunks, gave_up)
+}
+
+/// Offset of the first byte at or after `from` where the two sources
+/// differ, counting a length difference as one.
+pub fn first_difference(left: &ViewSource, right: &ViewSource, from: u64) -> io::Result<Option<u64>> {
+    let (left_len, right_len) = (left.size()?, right.size()?);
+    let common = left_len.min(right_len);
+    let mut offset = from;
+    while offset < common {
+        let len = CHUNK.min((common - offset) as usize);
+        let (a, b) = (left.read_at(offset, len), right.read_at(offset, len));
+        if let Some(idx) = a.iter().zip(&b).position(|(x, y)| x != y) {
+            return Ok(Some(offset + idx as u64));
+        }
+        offset += len as u64;
+    }
+    Ok((from < left_len.max(right_len) && left_len != right_len).then_some(common.max(from)))
+}
+
+impl Compare {
+    /// Compare two files: line by line when both are text, byte by byte
+    /// when either looks binary or is larger than `MAX_TEXT_SIZE`.
+    pub fn new(left_path: PathBuf, left: ViewSource, right_path: PathBuf, right: ViewSource) -> io::Result<Self> {
+        let (left_head, right_head) = (left.read_at(0, 8192), right.read_at(0, 8192));
+        let (left_encoding, right_encoding) = (Encoding::detect(&left_head), Encoding::detect(&right_head));
+        let binary = |encoding: Encoding, head: &[u8]| encoding.unit() == 1 && looks_binary(head);
+        let too_large = left.size()? > MAX_TEXT_SIZE || right.size()? > MAX_TEXT_SIZE;
+        let by_bytes = too_large || binary(left_encoding, &left_head) || binary(right_encoding, &right_head);
+        let (content, message, top) = if by_bytes {
+            let difference = first_difference(&left, &right, 0)?;
+            let message = match difference {
+                Some(offset) => format!("First difference at offset {offset:08X} ({offset})"),
+                None => "Files are identical".to_string(),
+            };
+            let message = if too_large { format!("Too large to compare by line. {message}") } else { message };
+            let top = difference.unwrap_or(0) / HEX_ROW as u64 * HEX_ROW as u64;
+            (CompareContent::Binary { left, right }, message, top)
+        } else {
+            let left_lines = split_lines(&left_encoding.decode(&left.read_at(0, left.size()? as usize)));
+            let right_lines = split_lines(&right_encoding.decode(&right.read_at(0, right.size()? as usize)));
+            let (rows, hunks, gave_up) = diff_lines(&left_lines, &right_lines);
+            let message = if gave_up {
+                "Too many differences to align; showing them as one block".to_string()
+            } else if hunks.is_empty() {
+                "Files are identical".to_string()
+            } else {
+                format!("{} difference(s)", hunks.len())
+            };
+            let top = hunks.first().map_or(0, |&first| first as u64);
+            (CompareContent::Text { left: left_lines, right: right_lines, rows, hunks }, message, top)
+        };
+        Ok(Self {
+            left_path,
+            right_path,
+            content,
+            top,
+            left: 0,
+            message: Some(message),
+            view_height: Cell::new(0),
+            view_width: Cell::new(0),
+        })
+    }
+
+    /// Rows in text mode; bytes in binary mode, where `top` is an offset.
+    fn extent(&self) -> u64 {
+        match &self.content {
+            CompareContent::Text { rows, .. } => rows.len() as u64,
+            CompareContent::Binary { left, right, .. } => {
+                left.size().unwrap_or(0).max(right.size().unwrap_or(0))
+            }
+        }
+    }
+
+    /// Screen rows one step of `scroll_by` moves: a line, or a hex row.
+    fn step(&self) -> u64 {
+        match self.content {
+            CompareContent::Text { .. } => 1,
+            CompareContent::Binary { .. } => HEX_ROW as u64,
+        }
+    }
+
+    /// Visible rows per side; binary mode shows the two dumps stacked.
+    pub fn page(&self) -> usize {
+        match self.content {
+            CompareContent::Text { .. } => self.view_height.get().max(1),
+            CompareContent::Binary { .. } => (self.view_height.get().saturating_sub(1) / 2).max(1),
+        }
+    }
+
+    fn bottom(&self) -> u64 {
+        let last = self.extent().saturating_sub(1) / self.step() * self.step();
+        last.saturating_sub((self.page() as u64 - 1) * self.step())
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        let moved = self.step() * delta.unsigned_abs() as u64;
+        // A jump to a difference near the end may have gone past the bottom.
+        let bottom = self.bottom().max(self.top);
+        self.top = if delta < 0 { self.top.saturating_sub(moved) } else { (self.top + moved).min(bottom) };
+    }
+
+    pub fn scroll_sideways(&mut self, delta: isize) {
+        if matches!(self.content, CompareContent::Text { .. }) {
+            self.left = self.left.saturating_add_signed(delta * 8);
+        }
+    }
+
+    pub fn scroll_home(&mut self) {
+        self.top = 0;
+        self.left = 0;
+    }
+
+    pub fn scroll_end(&mut self) {
+        self.top = self.bottom();
+    }
+
+    /// Move to the next (or previous) block of differences.
+    pub fn goto_difference(&mut self, forward: bool) {
+        let target = match &self.content {
+            CompareContent::Text { hunks, .. } => {
+                let top = self.top as usize;
+                let found = if forward {
+                    hunks.iter().find(|&&row| row > top)
+                } else {
+                    hunks.iter().rev().find(|&&row| row < top)
+                };
+                found.map(|&row| row as u64)
+            }
+            CompareContent::Binary { left, right, .. } => {
+                let row = HEX_ROW as u64;
+                if forward {
+                    first_difference(left, right, self.top + row).ok().flatten().map(|offset| offset / row * row)
+                } else {
+                    self.previous_difference(left, right)
+                }
+            }
+        };
+        match target {
+            Some(top) => self.top = top,
+            None => self.message = Some("No more differences".to_string()),
+        }
+    }
+
+    /// The last hex row above `top` holding a differing byte.
+    fn previous_difference(&self, left: &ViewSource, right: &ViewSource) -> Option<u64> {
+        let row = HEX_ROW as u64;
+        let common = left.size().ok()?.min(right.size().ok()?);
+        if self.top > common {
+            return Some(common / row * row);
+        }
+        let mut end = self.top;
+        while end > 0 {
+            let start = end.saturating_sub(CHUNK as u64);
+            let len = (end - start) as usize;
+            let (a, b) = (left.read_at(start, len), right.read_at(start, len));
+            if let Some(idx) = a.iter().zip(&b).rposition(|(x, y)| x != y) {
+                return Some((start + idx as u64) / row * row);
+            }
+            end = start;
+        }
+        None
+    }
+
+    /// Which difference block the top row is in or past, for the status line.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        let CompareContent::Text { hunks, .. } = &self.content else { return None };
+        let passed = hunks.iter().take_while(|&&row| row as u64 <= self.top).count();
+        Some((passed, hunks.len()))
+    }
+}
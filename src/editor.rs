@@ -0,0 +1,497 @@
+#![forbid(unsafe_code)]
+
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::model::{Editor, ViewerSearch};
+use crate::vfs::create_temp_beside;
+use crate::viewer::{display_column, expand_tabs, looks_binary, Finder};
+
+/// Undo steps kept per editor.
+const UNDO_LIMIT: usize = 1000;
+
+/// A place in the buffer; `col` counts characters, not bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// One buffer change: `removed` was replaced by `inserted` at `start`. Edits
+/// sharing a `group` are undone together.
+#[derive(Debug, Clone)]
+pub struct Edit {
+    start: Pos,
+    removed: String,
+    inserted: String,
+    /// Cursor before the change.
+    cursor: Pos,
+    group: u64,
+}
+
+/// Cursor movements; with Shift they extend the selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    FileStart,
+    FileEnd,
+}
+
+/// One screen row of the editor: the visible part of a line and the selected
+/// columns within it.
+#[derive(Debug)]
+pub struct EditorRow {
+    pub text: String,
+    pub selected: Option<(usize, usize)>,
+}
+
+/// Byte index of character `col` in `line`.
+fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(idx, _)| idx)
+}
+
+/// Where the cursor ends up after inserting `text` at `pos`.
+fn advance(pos: Pos, text: &str) -> Pos {
+    match text.rfind('\n') {
+        Some(last) => Pos { line: pos.line + text.matches('\n').count(), col: text[last + 1..].chars().count() },
+        None => Pos { line: pos.line, col: pos.col + text.chars().count() },
+    }
+}
+
+fn is_word(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Write `text` to a temp file beside `path` and rename it over the original,
+/// so a failed save leaves the old contents intact. A symlink is followed and
+/// the file it points to replaced; an existing file keeps its permissions.
+fn write_file(path: &Path, text: &str) -> io::Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let permissions = fs::metadata(&path).map(|meta| meta.permissions()).ok();
+    let (temp_path, mut file) = create_temp_beside(&path)?;
+    let result = file
+        .write_all(text.as_bytes())
+        .and_then(|()| match permissions {
+            Some(permissions) => file.set_permissions(permissions),
+            None => Ok(()),
+        })
+        .and_then(|()| file.sync_all())
+        .and_then(|()| fs::rename(&temp_path, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+impl Editor {
+    /// Load `path` for editing. Binary and non-UTF-8 files are refused so
+    /// saving cannot mangle them; a missing file starts out empty.
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        if looks_binary(&data) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "binary file"));
+        }
+        let text = String::from_utf8(data).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not UTF-8 text"))?;
+        Ok(Self::from_text(path, &text))
+    }
+
+    pub fn from_text(path: PathBuf, text: &str) -> Self {
+        let crlf = text.contains("\r\n");
+        let final_newline = text.ends_with('\n');
+        let body = text.strip_suffix('\n').unwrap_or(text);
+        let lines = body
+            .split('\n')
+            .map(|line| if crlf { line.strip_suffix('\r').unwrap_or(line) } else { line }.to_string())
+            .collect();
+        Self {
+            path,
+            lines,
+            crlf,
+            final_newline,
+            cursor: Pos::default(),
+            anchor: None,
+            top: 0,
+            left: 0,
+            overwrite: false,
+            modified: false,
+            clipboard: String::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            group: 0,
+            typing: false,
+            search: None,
+            message: None,
+            view_height: Cell::new(0),
+            view_width: Cell::new(0),
+        }
+    }
+
+    /// The buffer as it is written to disk, with the file's own line ending.
+    pub fn text(&self) -> String {
+        let mut text = self.lines.join(if self.crlf { "\r\n" } else { "\n" });
+        if self.final_newline {
+            text.push_str(if self.crlf { "\r\n" } else { "\n" });
+        }
+        text
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        write_file(&self.path, &self.text())?;
+        self.modified = false;
+        Ok(())
+    }
+
+    pub fn save_as(&mut self, path: PathBuf) -> io::Result<()> {
+        write_file(&path, &self.text())?;
+        self.path = path;
+        self.modified = false;
+        Ok(())
+    }
+
+    fn line_len(&self, line: usize) -> usize {
+        self.lines[line].chars().count()
+    }
+
+    fn end_pos(&self) -> Pos {
+        let line = self.lines.len() - 1;
+        Pos { line, col: self.line_len(line) }
+    }
+
+    /// The selected range in buffer order, if anything is selected.
+    pub fn selection(&self) -> Option<(Pos, Pos)> {
+        let anchor = self.anchor?;
+        (anchor != self.cursor).then(|| (anchor.min(self.cursor), anchor.max(self.cursor)))
+    }
+
+    pub fn text_range(&self, start: Pos, end: Pos) -> String {
+        if start.line == end.line {
+            let line = &self.lines[start.line];
+            return line[byte_index(line, start.col)..byte_index(line, end.col)].to_string();
+        }
+        let first = &self.lines[start.line];
+        let mut text = first[byte_index(first, start.col)..].to_string();
+        for line in &self.lines[start.line + 1..end.line] {
+            text.push('\n');
+            text.push_str(line);
+        }
+        let last = &self.lines[end.line];
+        text.push('\n');
+        text.push_str(&last[..byte_index(last, end.col)]);
+        text
+    }
+
+    /// Replace `start..end` with `text` without recording it; returns the end of the new text.
+    fn splice(&mut self, start: Pos, end: Pos, text: &str) -> Pos {
+        let first = &self.lines[start.line];
+        let last = &self.lines[end.line];
+        let joined = format!("{}{}{}", &first[..byte_index(first, start.col)], text, &last[byte_index(last, end.col)..]);
+        self.lines.splice(start.line..=end.line, joined.split('\n').map(str::to_string));
+        advance(start, text)
+    }
+
+    /// Replace `start..end` with `text`, record it for undo and put the cursor after it.
+    fn replace(&mut self, start: Pos, end: Pos, text: &str) {
+        let removed = self.text_range(start, end);
+        let cursor = self.cursor;
+        self.cursor = self.splice(start, end, text);
+        self.anchor = None;
+        self.modified = true;
+        self.redo.clear();
+        // Typing continues the previous edit, so undo takes back a word at a time.
+        if let Some(last) = self.undo.last_mut() {
+            let contiguous = advance(last.start, &last.inserted) == start;
+            if self.typing && contiguous && last.removed.is_empty() && removed.is_empty() && !text.contains(['\n', ' ']) {
+                last.inserted.push_str(text);
+                return;
+            }
+        }
+        self.undo.push(Edit { start, removed, inserted: text.to_string(), cursor, group: self.group });
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Start a new undo step.
+    fn begin(&mut self) {
+        self.group += 1;
+        self.typing = false;
+        self.message = None;
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else { return false };
+        self.replace(start, end, "");
+        true
+    }
+
+    pub fn insert_char(&mut self, ch: char) {
+        let typing = self.typing;
+        self.begin();
+        let deleted = self.delete_selection();
+        self.typing = typing && !deleted;
+        let cursor = self.cursor;
+        let end = if self.overwrite && cursor.col < self.line_len(cursor.line) {
+            Pos { col: cursor.col + 1, ..cursor }
+        } else {
+            cursor
+        };
+        self.replace(cursor, end, &ch.to_string());
+        self.typing = true;
+    }
+
+    pub fn insert_text(&mut self, text: &str) {
+        self.begin();
+        self.delete_selection();
+        let cursor = self.cursor;
+        self.replace(cursor, cursor, &text.replace("\r\n", "\n"));
+    }
+
+    pub fn newline(&mut self) {
+        self.insert_text("\n");
+    }
+
+    pub fn backspace(&mut self) {
+        self.begin();
+        if self.delete_selection() {
+            return;
+        }
+        let cursor = self.cursor;
+        let start = match (cursor.col, cursor.line) {
+            (0, 0) => return,
+            (0, line) => Pos { line: line - 1, col: self.line_len(line - 1) },
+            (col, line) => Pos { line, col: col - 1 },
+        };
+        self.replace(start, cursor, "");
+    }
+
+    pub fn delete(&mut self) {
+        self.begin();
+        if self.delete_selection() {
+            return;
+        }
+        let cursor = self.cursor;
+        let end = if cursor.col < self.line_len(cursor.line) {
+            Pos { col: cursor.col + 1, ..cursor }
+        } else if cursor.line + 1 < self.lines.len() {
+            Pos { line: cursor.line + 1, col: 0 }
+        } else {
+            return;
+        };
+        self.replace(cursor, end, "");
+    }
+
+    pub fn toggle_overwrite(&mut self) {
+        self.overwrite = !self.overwrite;
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(Pos::default());
+        self.cursor = self.end_pos();
+    }
+
+    pub fn copy(&mut self) {
+        if let Some((start, end)) = self.selection() {
+            self.clipboard = self.text_range(start, end);
+        }
+    }
+
+    pub fn cut(&mut self) {
+        self.copy();
+        self.begin();
+        self.delete_selection();
+    }
+
+    pub fn paste(&mut self) {
+        if !self.clipboard.is_empty() {
+            let text = self.clipboard.clone();
+            self.insert_text(&text);
+        }
+    }
+
+    pub fn undo(&mut self) {
+        let Some(group) = self.undo.last().map(|edit| edit.group) else {
+            self.message = Some("Nothing to undo".to_string());
+            return;
+        };
+        while self.undo.last().is_some_and(|edit| edit.group == group) {
+            let edit = self.undo.pop().expect("checked above");
+            self.splice(edit.start, advance(edit.start, &edit.inserted), &edit.removed);
+            self.cursor = edit.cursor;
+            self.redo.push(edit);
+        }
+        self.anchor = None;
+        self.typing = false;
+        self.modified = true;
+    }
+
+    pub fn redo(&mut self) {
+        let Some(group) = self.redo.last().map(|edit| edit.group) else {
+            self.message = Some("Nothing to redo".to_string());
+            return;
+        };
+        while self.redo.last().is_some_and(|edit| edit.group == group) {
+            let edit = self.redo.pop().expect("checked above");
+            self.cursor = self.splice(edit.start, advance(edit.start, &edit.removed), &edit.inserted);
+            self.undo.push(edit);
+        }
+        self.anchor = None;
+        self.typing = false;
+        self.modified = true;
+    }
+
+    /// Move the cursor; with `select` the selection grows from where it was.
+    pub fn move_cursor(&mut self, motion: Motion, select: bool) {
+        self.typing = false;
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        let Pos { line, col } = self.cursor;
+        let page = self.view_height.get().max(2) - 1;
+        self.cursor = match motion {
+            Motion::Left if col > 0 => Pos { line, col: col - 1 },
+            Motion::Left if line > 0 => Pos { line: line - 1, col: self.line_len(line - 1) },
+            Motion::Right if col < self.line_len(line) => Pos { line, col: col + 1 },
+            Motion::Right if line + 1 < self.lines.len() => Pos { line: line + 1, col: 0 },
+            Motion::Left | Motion::Right => self.cursor,
+            Motion::Up => Pos { line: line.saturating_sub(1), col },
+            Motion::Down => Pos { line: (line + 1).min(self.lines.len() - 1), col },
+            Motion::PageUp => Pos { line: line.saturating_sub(page), col },
+            Motion::PageDown => Pos { line: (line + page).min(self.lines.len() - 1), col },
+            Motion::LineStart => Pos { line, col: 0 },
+            Motion::LineEnd => Pos { line, col: self.line_len(line) },
+            Motion::FileStart => Pos::default(),
+            Motion::FileEnd => self.end_pos(),
+            Motion::WordLeft => {
+                let chars: Vec<char> = self.lines[line].chars().take(col).collect();
+                let mut idx = chars.len();
+                while idx > 0 && !is_word(chars[idx - 1]) {
+                    idx -= 1;
+                }
+                while idx > 0 && is_word(chars[idx - 1]) {
+                    idx -= 1;
+                }
+                Pos { line, col: idx }
+            }
+            Motion::WordRight => {
+                let chars: Vec<char> = self.lines[line].chars().collect();
+                let mut idx = col;
+                while idx < chars.len() && is_word(chars[idx]) {
+                    idx += 1;
+                }
+                while idx < chars.len() && !is_word(chars[idx]) {
+                    idx += 1;
+                }
+                Pos { line, col: idx }
+            }
+        };
+        self.cursor.col = self.cursor.col.min(self.line_len(self.cursor.line));
+    }
+
+    /// Select the next match of `query` after the cursor, wrapping around to
+    /// the top once. Returns false when there is none.
+    pub fn find(&mut self, query: &str) -> bool {
+        self.begin();
+        self.search = Some(query.to_string());
+        let Ok(finder) = Finder::new(ViewerSearch { query: query.to_string(), ..ViewerSearch::default() }) else { return false };
+        let count = self.lines.len();
+        let origin = self.cursor.line;
+        for step in 0..=count {
+            let line = (self.cursor.line + step) % count;
+            let text = &self.lines[line];
+            let from = if step == 0 { byte_index(text, self.cursor.col) } else { 0 };
+            let found = finder.matches(text).into_iter().find(|&(start, _)| step == count || start >= from);
+            if let Some((start, end)) = found {
+                let (start, end) = (text[..start].chars().count(), text[..end].chars().count());
+                self.anchor = Some(Pos { line, col: start });
+                self.cursor = Pos { line, col: end };
+                if line < origin || step == count {
+                    self.message = Some("Search wrapped around".to_string());
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Replace every match of `query` with `with` as one undo step.
+    pub fn replace_all(&mut self, query: &str, with: &str) -> usize {
+        self.begin();
+        let Ok(finder) = Finder::new(ViewerSearch { query: query.to_string(), ..ViewerSearch::default() }) else { return 0 };
+        let mut count = 0;
+        for line in (0..self.lines.len()).rev() {
+            for (start, end) in finder.matches(&self.lines[line]).into_iter().rev() {
+                let text = &self.lines[line];
+                let (start, end) = (text[..start].chars().count(), text[..end].chars().count());
+                self.replace(Pos { line, col: start }, Pos { line, col: end }, with);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Scroll so the cursor is on screen.
+    pub fn scroll_into_view(&mut self) {
+        let (height, width) = (self.view_height.get().max(1), self.view_width.get().max(1));
+        if self.cursor.line < self.top {
+            self.top = self.cursor.line;
+        } else if self.cursor.line >= self.top + height {
+            self.top = self.cursor.line + 1 - height;
+        }
+        let column = self.cursor_column();
+        if column < self.left {
+            self.left = column;
+        } else if column >= self.left + width {
+            self.left = column + 1 - width;
+        }
+    }
+
+    /// Screen column of the cursor in its line, tabs expanded.
+    pub fn cursor_column(&self) -> usize {
+        let line = &self.lines[self.cursor.line];
+        display_column(line, byte_index(line, self.cursor.col))
+    }
+
+    /// Rows from `top` down, cut to the screen width starting at `left`.
+    pub fn rows(&self) -> Vec<EditorRow> {
+        let (height, width) = (self.view_height.get(), self.view_width.get().max(1));
+        let selection = self.selection();
+        self.lines
+            .iter()
+            .enumerate()
+            .skip(self.top)
+            .take(height)
+            .map(|(idx, line)| {
+                let expanded: Vec<char> = expand_tabs(line).chars().collect();
+                let text = expanded.iter().skip(self.left).take(width).collect();
+                let selected = selection.and_then(|(start, end)| {
+                    if idx < start.line || idx > end.line {
+                        return None;
+                    }
+                    let from = if idx == start.line { display_column(line, byte_index(line, start.col)) } else { 0 };
+                    // A selected line break shows as one extra column.
+                    let to = if idx == end.line { display_column(line, byte_index(line, end.col)) } else { expanded.len() + 1 };
+                    let (from, to) = (from.max(self.left) - self.left, to.min(self.left + width).saturating_sub(self.left));
+                    (from < to).then_some((from, to))
+                });
+                EditorRow { text, selected }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,135 @@
+#![forbid(unsafe_code)]
+
+/// Text encodings the viewer can show a file in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+    Cp437,
+    Windows1252,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// CP437 (the DOS character set) from 0x80 up.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Windows-1252 from 0x80 to 0x9F; the rest matches Latin-1. Unassigned
+/// bytes show as the replacement character.
+const WINDOWS_1252_C1: [char; 32] = [
+    '€', '\u{fffd}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{fffd}', 'Ž', '\u{fffd}', //
+    '\u{fffd}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{fffd}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "Latin-1",
+            Encoding::Cp437 => "CP437",
+            Encoding::Windows1252 => "Windows-1252",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+        }
+    }
+
+    /// The encoding after this one when cycling with the viewer key.
+    pub fn next(self) -> Self {
+        match self {
+            Encoding::Utf8 => Encoding::Latin1,
+            Encoding::Latin1 => Encoding::Cp437,
+            Encoding::Cp437 => Encoding::Windows1252,
+            Encoding::Windows1252 => Encoding::Utf16Le,
+            Encoding::Utf16Le => Encoding::Utf16Be,
+            Encoding::Utf16Be => Encoding::Utf8,
+        }
+    }
+
+    /// Guess from the first few kilobytes: a byte order mark, then the zero
+    /// bytes UTF-16 puts in every other position for Latin text, then whether
+    /// the data is valid UTF-8. Anything else is taken as Windows-1252.
+    pub fn detect(head: &[u8]) -> Self {
+        if head.starts_with(b"\xef\xbb\xbf") {
+            return Encoding::Utf8;
+        }
+        if head.starts_with(b"\xff\xfe") {
+            return Encoding::Utf16Le;
+        }
+        if head.starts_with(b"\xfe\xff") {
+            return Encoding::Utf16Be;
+        }
+        let head = &head[..head.len().min(8192)];
+        let pairs = head.len() / 2;
+        if pairs >= 2 {
+            let even = head.iter().step_by(2).filter(|&&byte| byte == 0).count();
+            let odd = head.iter().skip(1).step_by(2).filter(|&&byte| byte == 0).count();
+            if odd * 10 > pairs * 4 && even * 20 < pairs {
+                return Encoding::Utf16Le;
+            }
+            if even * 10 > pairs * 4 && odd * 20 < pairs {
+                return Encoding::Utf16Be;
+            }
+        }
+        match std::str::from_utf8(head) {
+            // A multi-byte character cut off at the end of the sample is fine.
+            Err(err) if err.error_len().is_some() => Encoding::Windows1252,
+            _ => Encoding::Utf8,
+        }
+    }
+
+    /// Bytes per code unit; line breaks are only looked for on unit boundaries.
+    pub fn unit(self) -> usize {
+        match self {
+            Encoding::Utf16Le | Encoding::Utf16Be => 2,
+            _ => 1,
+        }
+    }
+
+    /// How a line feed is encoded.
+    pub fn newline(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf16Le => b"\n\0",
+            Encoding::Utf16Be => b"\0\n",
+            _ => b"\n",
+        }
+    }
+
+    /// Decode `bytes`, dropping a leading byte order mark.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes)).to_string(),
+            Encoding::Latin1 => bytes.iter().map(|&byte| byte as char).collect(),
+            Encoding::Cp437 => bytes
+                .iter()
+                .map(|&byte| if byte < 0x80 { byte as char } else { CP437_HIGH[byte as usize - 0x80] })
+                .collect(),
+            Encoding::Windows1252 => bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x80..=0x9f => WINDOWS_1252_C1[byte as usize - 0x80],
+                    _ => byte as char,
+                })
+                .collect(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks_exact(2).map(|pair| {
+                    if self == Encoding::Utf16Le {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    }
+                });
+                let text: String = char::decode_utf16(units).map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER)).collect();
+                text.strip_prefix('\u{feff}').map(str::to_string).unwrap_or(text)
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+#![forbid(unsafe_code)]
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::fs_ops::wildcard_match_case;
+
+/// Wildcard masks in the NC form `*.rs;*.toml|target/*`: masks before the `|`
+/// select files, masks after it exclude them. Masks containing `/` are matched
+/// against the trailing components of the path relative to where the operation
+/// started, the rest against the file name alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub case_sensitive: bool,
+}
+
+impl FileFilter {
+    pub fn parse(spec: &str) -> Self {
+        let (include, exclude) = spec.split_once('|').unwrap_or((spec, ""));
+        Self { include: split_masks(include), exclude: split_masks(exclude), case_sensitive: false }
+    }
+
+    pub fn spec(&self) -> String {
+        let mut spec = self.include.join(";");
+        if !self.exclude.is_empty() {
+            spec.push('|');
+            spec.push_str(&self.exclude.join(";"));
+        }
+        spec
+    }
+
+    /// Whether a file at `rel` passes the filter.
+    pub fn matches_file(&self, rel: &Path) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|mask| self.mask_matches(mask, rel, false));
+        included && !self.exclude.iter().any(|mask| self.mask_matches(mask, rel, false))
+    }
+
+    /// Directories are never selected by the include masks, only pruned by
+    /// exclusions (`target/*` excludes the `target` directory as a whole).
+    pub fn excludes_dir(&self, rel: &Path) -> bool {
+        self.exclude.iter().any(|mask| self.mask_matches(mask, rel, true))
+    }
+
+    fn mask_matches(&self, mask: &str, rel: &Path, is_dir: bool) -> bool {
+        if mask.contains('/') {
+            let mut text = rel.to_string_lossy().to_string();
+            if is_dir {
+                text.push('/');
+            }
+            let mask = mask.trim_start_matches('/');
+            // `target/*` should also hit `project/target/...`.
+            std::iter::once(0)
+                .chain(text.match_indices('/').map(|(idx, _)| idx + 1))
+                .any(|start| wildcard_match_case(mask, &text[start..], self.case_sensitive))
+        } else {
+            let name = rel.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            wildcard_match_case(mask, &name, self.case_sensitive)
+        }
+    }
+}
+
+fn split_masks(masks: &str) -> Vec<String> {
+    masks
+        .split([';', ','])
+        .map(str::trim)
+        .filter(|mask| !mask.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// A filter stored under a name in `~/.frankencommander/filters`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedFilter {
+    pub name: String,
+    pub filter: FileFilter,
+}
+
+/// Read the saved filters: one `[name]` section per filter with `masks` and
+/// `case_sensitive` keys. A missing file means no saved filters.
+pub fn load_filters(path: &Path) -> Vec<SavedFilter> {
+    let Ok(content) = fs::read_to_string(path) else { return Vec::new() };
+    let mut filters: Vec<SavedFilter> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            filters.push(SavedFilter { name: name.trim().to_string(), filter: FileFilter::parse("") });
+            continue;
+        }
+        let (Some(current), Some((key, value))) = (filters.last_mut(), line.split_once('=')) else { continue };
+        match key.trim() {
+            "masks" => {
+                let case_sensitive = current.filter.case_sensitive;
+                current.filter = FileFilter { case_sensitive, ..FileFilter::parse(value.trim()) };
+            }
+            "case_sensitive" => current.filter.case_sensitive = value.trim() == "true",
+            _ => {}
+        }
+    }
+    filters
+}
+
+pub fn save_filters(path: &Path, filters: &[SavedFilter]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = String::from("# FrankenCommander filters\n");
+    for saved in filters {
+        out.push_str(&format!(
+            "\n[{}]\nmasks = {}\ncase_sensitive = {}\n",
+            saved.name,
+            saved.filter.spec(),
+            saved.filter.case_sensitive
+        ));
+    }
+    fs::write(path, out)
+}
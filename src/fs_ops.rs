@@ -1,41 +1,35 @@
 #![forbid(unsafe_code)]
 
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
 use std::fs;
-use std::io;
-use std::path::{Path, PathBuf};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
 use std::time::SystemTime;
 
-use crate::model::{Entry, SortMode, TreeItem, UserMenuItem};
+use crate::filter::FileFilter;
+use crate::model::{Entry, SortMode, TreeItem};
+use crate::vfs::{create_temp_beside, Vfs};
 
-pub fn read_entries(
-    dir: &Path,
+/// Drop what the pane hides (dot files unless `show_hidden`, names the
+/// filter rejects) and sort the rest.
+pub fn arrange_entries(
+    entries: &mut Vec<Entry>,
     sort_mode: SortMode,
     dirs_first: bool,
     show_hidden: bool,
-) -> io::Result<Vec<Entry>> {
-    let mut entries = Vec::new();
-    for item in fs::read_dir(dir)? {
-        let item = item?;
-        let path = item.path();
-        let metadata = item.metadata()?;
-        let is_dir = metadata.is_dir();
-        let size = metadata.len();
-        let modified = metadata.modified().ok();
-        let name = item.file_name().to_string_lossy().to_string();
-        if !show_hidden && name.starts_with('.') {
-            continue;
+    filter: Option<&FileFilter>,
+) {
+    entries.retain(|entry| {
+        if !show_hidden && entry.name.starts_with('.') {
+            return false;
         }
-        let is_system = name.starts_with('.');
-        entries.push(Entry {
-            name,
-            path,
-            is_dir,
-            size,
-            modified,
-            is_system,
-        });
-    }
+        let Some(filter) = filter else { return true };
+        let rel = Path::new(&entry.name);
+        if entry.is_dir { !filter.excludes_dir(rel) } else { filter.matches_file(rel) }
+    });
 
     entries.sort_by(|a, b| {
         if dirs_first && a.is_dir != b.is_dir {
@@ -53,27 +47,14 @@ pub fn read_entries(
             SortMode::Unsorted => Ordering::Equal,
         }
     });
-
-    Ok(entries)
 }
 
-pub fn read_panelized(paths: &[PathBuf]) -> io::Result<Vec<Entry>> {
+pub fn read_panelized(vfs: &dyn Vfs, paths: &[PathBuf]) -> io::Result<Vec<Entry>> {
     let mut entries = Vec::new();
     for path in paths {
-        let Ok(metadata) = fs::metadata(path) else { continue };
-        let is_dir = metadata.is_dir();
-        let size = metadata.len();
-        let modified = metadata.modified().ok();
-        let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("").to_string();
-        let is_system = name.starts_with('.');
-        entries.push(Entry {
-            name,
-            path: path.clone(),
-            is_dir,
-            size,
-            modified,
-            is_system,
-        });
+        if let Ok(entry) = vfs.stat(path) {
+            entries.push(entry);
+        }
     }
     Ok(entries)
 }
@@ -165,86 +146,643 @@ pub fn format_time(time: Option<SystemTime>) -> (String, String) {
     (date, clock)
 }
 
-pub fn read_file_lines(path: &Path) -> io::Result<Vec<String>> {
-    let data = fs::read(path)?;
-    let content = String::from_utf8_lossy(&data);
-    Ok(content.lines().map(|line| line.to_string()).collect())
+/// `ls -l` style type and permissions, e.g. `drwxr-xr-x` or `lrwxrwxrwx`.
+pub fn format_mode(entry: &Entry) -> Option<String> {
+    let mode = entry.mode?;
+    let kind = if entry.link_target.is_some() {
+        'l'
+    } else if entry.is_dir {
+        'd'
+    } else {
+        '-'
+    };
+    let mut text = String::from(kind);
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        text.push(if bits & 4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 2 != 0 { 'w' } else { '-' });
+        text.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    Some(text)
 }
 
-pub fn find_conflicts(sources: &[PathBuf], dest: &Path) -> Option<usize> {
-    let dest_is_dir = dest.is_dir() || sources.len() > 1;
-    let mut conflicts = 0;
-    for src in sources {
-        let target = if dest_is_dir {
-            dest.join(src.file_name().unwrap_or_default())
+/// Human-readable size with one decimal: `512 B`, `1.5 KB`, `3.2 GB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Hooks long-running file operations report to. Returning an error from any
+/// of them aborts the operation; `()` ignores everything.
+pub trait Progress {
+    /// About to copy, move or delete the file at `path`.
+    fn file(&mut self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+    /// `count` more bytes of the current file were written.
+    fn bytes(&mut self, _count: u64) -> io::Result<()> {
+        Ok(())
+    }
+    /// Work finished without touching the data, e.g. a directory moved by rename.
+    fn skip(&mut self, _files: u64, _bytes: u64) -> io::Result<()> {
+        Ok(())
+    }
+    /// `target` already exists; decide what happens to `src`. Defaults to overwriting.
+    fn conflict(&mut self, _src: &Path, _target: &Path) -> io::Result<ConflictChoice> {
+        Ok(ConflictChoice::Overwrite)
+    }
+    /// Like `conflict`, for a source inside an archive.
+    fn archive_conflict(&mut self, src: &Entry, target: &Path) -> io::Result<ConflictChoice> {
+        self.conflict(&src.path, target)
+    }
+    /// Like `conflict`, for a local source whose target is inside an archive.
+    fn conflict_in_archive(&mut self, src: &Path, target: &Entry) -> io::Result<ConflictChoice> {
+        self.conflict(src, &target.path)
+    }
+}
+
+/// What to do with a source whose target already exists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictChoice {
+    Overwrite,
+    Skip,
+    /// Write to this path instead (asked again if it exists too).
+    Rename(PathBuf),
+    /// Append the source to the target file; directories are skipped.
+    Append,
+}
+
+impl Progress for () {}
+
+const COPY_BUFFER: usize = 256 * 1024;
+
+/// The copy/move dialog checkboxes.
+#[derive(Debug, Clone)]
+pub struct TransferOptions {
+    /// Off: directories given as sources contribute only their top-level files.
+    pub include_subdirs: bool,
+    /// Leave targets alone that are as new as or newer than the source.
+    pub newer_only: bool,
+    pub filter: Option<FileFilter>,
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        Self { include_subdirs: true, newer_only: false, filter: None }
+    }
+}
+
+impl TransferOptions {
+    /// Whether some entries below a directory may be left out, which rules out
+    /// moving it with a single rename.
+    fn is_selective(&self) -> bool {
+        !self.include_subdirs || self.newer_only || self.filter.is_some()
+    }
+
+    /// Whether `src`, found at `rel` below the operation root, stays out of a
+    /// copy or move to `target`.
+    fn excludes(&self, src: &Path, rel: &Path, target: &Path) -> bool {
+        if src.is_dir() {
+            let nested = rel.components().count() > 1;
+            return (nested && !self.include_subdirs)
+                || self.filter.as_ref().is_some_and(|filter| filter.excludes_dir(rel));
+        }
+        if self.filter.as_ref().is_some_and(|filter| !filter.matches_file(rel)) {
+            return true;
+        }
+        self.newer_only && !is_newer(src, target)
+    }
+}
+
+/// True when `src` was modified after `target`, or `target` is missing.
+fn is_newer(src: &Path, target: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    match (modified(src), modified(target)) {
+        (Some(src), Some(target)) => src > target,
+        (_, None) => true,
+        (None, Some(_)) => false,
+    }
+}
+
+/// Number of files and total bytes below `paths`, following the same rules as
+/// the copy (directories count only through their contents).
+pub fn measure_sources(paths: &[PathBuf]) -> (u64, u64) {
+    let mut files = 0;
+    let mut bytes = 0;
+    let mut stack: Vec<PathBuf> = paths.to_vec();
+    while let Some(path) = stack.pop() {
+        let Ok(meta) = fs::symlink_metadata(&path) else { continue };
+        if meta.is_dir() {
+            if let Ok(read) = fs::read_dir(&path) {
+                stack.extend(read.flatten().map(|entry| entry.path()));
+            }
         } else {
-            dest.to_path_buf()
-        };
-        if target.exists() {
-            conflicts += 1;
+            files += 1;
+            bytes += meta.len();
         }
     }
-    if conflicts > 0 { Some(conflicts) } else { None }
+    (files, bytes)
 }
 
-pub fn copy_sources(sources: &[PathBuf], dest: &Path, overwrite: bool) -> io::Result<()> {
+fn target_for(src: &Path, dest: &Path, dest_is_dir: bool) -> PathBuf {
+    if dest_is_dir {
+        dest.join(src.file_name().unwrap_or_default())
+    } else {
+        dest.to_path_buf()
+    }
+}
+
+/// Bytes a copy of `sources` will write, honouring the subdirectory and filter
+/// options (the newer-only check needs the targets and is left out).
+pub fn measure_transfer(sources: &[PathBuf], options: &TransferOptions) -> u64 {
+    let mut bytes = 0;
+    let mut stack: Vec<(PathBuf, PathBuf)> =
+        sources.iter().map(|src| (src.clone(), PathBuf::from(src.file_name().unwrap_or_default()))).collect();
+    while let Some((path, rel)) = stack.pop() {
+        let Ok(meta) = fs::symlink_metadata(&path) else { continue };
+        if meta.is_dir() {
+            if options.excludes(&path, &rel, Path::new("")) {
+                continue;
+            }
+            if let Ok(read) = fs::read_dir(&path) {
+                stack.extend(read.flatten().map(|entry| (entry.path(), rel.join(entry.file_name()))));
+            }
+        } else if options.filter.as_ref().is_none_or(|filter| filter.matches_file(&rel)) {
+            bytes += meta.len();
+        }
+    }
+    bytes
+}
+
+/// Free bytes for unprivileged users on the filesystem holding `path` (or its
+/// nearest existing ancestor).
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let existing = path.ancestors().find(|dir| dir.exists()).unwrap_or(Path::new("/"));
+    let stats = nix::sys::statvfs::statvfs(existing).map_err(io::Error::from)?;
+    Ok(stats.blocks_available() as u64 * stats.fragment_size() as u64)
+}
+
+/// Whether `a` and `b` (or their nearest existing ancestors) are on the same filesystem.
+pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+    let dev = |path: &Path| {
+        path.ancestors()
+            .find_map(|dir| fs::metadata(dir).ok())
+            .map(|meta| meta.dev())
+    };
+    dev(a).is_some() && dev(a) == dev(b)
+}
+
+pub fn copy_sources(
+    sources: &[PathBuf],
+    dest: &Path,
+    options: &TransferOptions,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
     let dest_is_dir = dest.is_dir() || sources.len() > 1;
     for src in sources {
-        let target = if dest_is_dir {
-            dest.join(src.file_name().unwrap_or_default())
-        } else {
-            dest.to_path_buf()
-        };
-        if overwrite && target.exists() {
-            remove_path(&target)?;
-        }
-        copy_entry(src, &target)?;
+        let rel = PathBuf::from(src.file_name().unwrap_or_default());
+        copy_entry(src, &target_for(src, dest, dest_is_dir), &rel, options, progress)?;
     }
     Ok(())
 }
 
-pub fn move_sources(sources: &[PathBuf], dest: &Path, overwrite: bool) -> io::Result<()> {
+pub fn move_sources(
+    sources: &[PathBuf],
+    dest: &Path,
+    options: &TransferOptions,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
     let dest_is_dir = dest.is_dir() || sources.len() > 1;
     for src in sources {
-        let target = if dest_is_dir {
-            dest.join(src.file_name().unwrap_or_default())
-        } else {
-            dest.to_path_buf()
-        };
-        if overwrite && target.exists() {
-            remove_path(&target)?;
+        let rel = PathBuf::from(src.file_name().unwrap_or_default());
+        move_entry(src, &target_for(src, dest, dest_is_dir), &rel, options, progress)?;
+    }
+    Ok(())
+}
+
+/// Report an entry left out by the options as done.
+fn skip_entry(src: &Path, progress: &mut dyn Progress) -> io::Result<()> {
+    let (files, bytes) = measure_sources(&[src.to_path_buf()]);
+    progress.skip(files, bytes)
+}
+
+/// Delete `sources`. With a filter only matching files go, and directories are
+/// removed only once nothing is left in them.
+pub fn delete_sources(sources: &[PathBuf], filter: Option<&FileFilter>, progress: &mut dyn Progress) -> io::Result<()> {
+    for path in sources {
+        match filter {
+            Some(filter) => {
+                let rel = PathBuf::from(path.file_name().unwrap_or_default());
+                remove_filtered(path, &rel, filter, progress)?;
+            }
+            None => remove_tree(path, progress)?,
         }
-        move_entry(src, &target)?;
     }
     Ok(())
 }
 
-pub fn copy_entry(src: &Path, dest: &Path) -> io::Result<()> {
+fn remove_filtered(path: &Path, rel: &Path, filter: &FileFilter, progress: &mut dyn Progress) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        if filter.excludes_dir(rel) {
+            return skip_entry(path, progress);
+        }
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            remove_filtered(&entry.path(), &rel.join(entry.file_name()), filter, progress)?;
+        }
+        // Fails when something was kept, which is what we want.
+        let _ = fs::remove_dir(path);
+        Ok(())
+    } else if filter.matches_file(rel) {
+        progress.file(path)?;
+        fs::remove_file(path)?;
+        progress.bytes(meta.len())
+    } else {
+        progress.skip(1, meta.len())
+    }
+}
+
+/// Settle where `src` goes when `target` may already exist. Directories merge
+/// into existing directories; everything else goes through `Progress::conflict`.
+/// Returns the final target and whether to append, or `None` when skipped.
+/// An overwritten file is left in place for `copy_file` to replace.
+fn resolve_target(src: &Path, target: &Path, progress: &mut dyn Progress) -> io::Result<Option<(PathBuf, bool)>> {
+    let mut target = target.to_path_buf();
+    loop {
+        let Ok(meta) = fs::symlink_metadata(&target) else { return Ok(Some((target, false))) };
+        if is_same_file(src, &meta) {
+            let message = format!("{}: source and target are the same file", src.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        if src.is_dir() && meta.is_dir() {
+            return Ok(Some((target, false)));
+        }
+        match progress.conflict(src, &target)? {
+            ConflictChoice::Overwrite => {
+                if src.is_dir() || meta.is_dir() {
+                    remove_path(&target)?;
+                }
+                return Ok(Some((target, false)));
+            }
+            ConflictChoice::Rename(renamed) => target = renamed,
+            ConflictChoice::Append if meta.is_file() && !src.is_dir() => return Ok(Some((target, true))),
+            ConflictChoice::Skip | ConflictChoice::Append => {
+                skip_entry(src, progress)?;
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Copy `src` to `dest`; `rel` is its path below the operation root, which the
+/// options' filters are matched against.
+pub fn copy_entry(
+    src: &Path,
+    dest: &Path,
+    rel: &Path,
+    options: &TransferOptions,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
+    if options.excludes(src, rel, dest) {
+        return skip_entry(src, progress);
+    }
+    let Some((dest, append)) = resolve_target(src, dest, progress)? else { return Ok(()) };
     if src.is_dir() {
-        copy_dir_recursive(src, dest)
+        copy_dir_recursive(src, &dest, rel, options, progress)
     } else {
         if let Some(parent) = dest.parent() {
             fs::create_dir_all(parent)?;
         }
-        fs::copy(src, dest)?;
-        Ok(())
+        copy_file(src, &dest, append, progress)
+    }
+}
+
+/// Whether `path` names the file `target` (not following a final symlink).
+fn is_same_file(path: &Path, target: &fs::Metadata) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.dev() == target.dev() && meta.ino() == target.ino())
+}
+
+/// Copy one file in chunks so `progress` sees the bytes as they are written.
+/// Unless appending, the data goes to a temp file beside `dest` that is
+/// renamed over it once complete, so a failed copy never loses the old file.
+fn copy_file(src: &Path, dest: &Path, append: bool, progress: &mut dyn Progress) -> io::Result<()> {
+    progress.file(src)?;
+    let mut input = fs::File::open(src)?;
+    if append {
+        let mut output = fs::OpenOptions::new().append(true).open(dest)?;
+        return copy_data(&mut input, &mut output, progress);
+    }
+    let replacing = fs::symlink_metadata(dest).is_ok();
+    let (temp_path, mut output) = create_temp_beside(dest)?;
+    let result = copy_data(&mut input, &mut output, progress)
+        .and_then(|()| output.set_permissions(input.metadata()?.permissions()))
+        .and_then(|()| if replacing { output.sync_all() } else { Ok(()) })
+        .and_then(|()| fs::rename(&temp_path, dest));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn copy_data(input: &mut dyn Read, output: &mut fs::File, progress: &mut dyn Progress) -> io::Result<()> {
+    let mut buf = vec![0u8; COPY_BUFFER];
+    loop {
+        let read = match input.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        output.write_all(&buf[..read])?;
+        progress.bytes(read as u64)?;
+    }
+}
+
+/// Rename when possible, otherwise copy and delete. Directories that merge into
+/// an existing one (or cross filesystems) are moved entry by entry, so anything
+/// skipped on a conflict stays behind in the source.
+pub fn move_entry(
+    src: &Path,
+    dest: &Path,
+    rel: &Path,
+    options: &TransferOptions,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
+    if options.excludes(src, rel, dest) {
+        return skip_entry(src, progress);
+    }
+    let Some((dest, append)) = resolve_target(src, dest, progress)? else { return Ok(()) };
+    if append {
+        copy_file(src, &dest, true, progress)?;
+        return fs::remove_file(src);
+    }
+    if !src.is_dir() || (!dest.is_dir() && !options.is_selective()) {
+        let (files, bytes) = measure_sources(&[src.to_path_buf()]);
+        match fs::rename(src, &dest) {
+            Ok(()) => return progress.skip(files, bytes),
+            Err(_) if !src.is_dir() => {
+                copy_file(src, &dest, false, progress)?;
+                return fs::remove_file(src);
+            }
+            Err(_) => fs::create_dir_all(&dest)?,
+        }
+    }
+    fs::create_dir_all(&dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        move_entry(&entry.path(), &dest.join(&name), &rel.join(&name), options, progress)?;
+    }
+    // Fails when something was skipped, which is what we want.
+    let _ = fs::remove_dir(src);
+    Ok(())
+}
+
+/// Number of files and total bytes below `sources` inside `archive`.
+pub fn measure_archive_sources(archive: &dyn Vfs, sources: &[PathBuf]) -> (u64, u64) {
+    let mut files = 0;
+    let mut bytes = 0;
+    let mut stack: Vec<Entry> = sources.iter().filter_map(|path| archive.stat(path).ok()).collect();
+    while let Some(entry) = stack.pop() {
+        if entry.is_dir {
+            stack.extend(archive.list(&entry.path).unwrap_or_default());
+        } else {
+            files += 1;
+            bytes += entry.size;
+        }
+    }
+    (files, bytes)
+}
+
+/// Copy `sources` out of `archive` into the directory `dest`, with their
+/// stored permissions and modification times. Entries whose names would
+/// land outside `dest` (`..` and the like) are left out and reported once
+/// everything else is done.
+pub fn extract_sources(archive: &dyn Vfs, sources: &[PathBuf], dest: &Path, progress: &mut dyn Progress) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    let mut refused = Vec::new();
+    for src in sources {
+        let entry = archive.stat(src)?;
+        extract_entry(archive, &entry, dest, &mut refused, progress)?;
+    }
+    match refused.as_slice() {
+        [] => Ok(()),
+        [path] => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("refused unsafe path in archive: {}", path.display()),
+        )),
+        [path, ..] => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("refused {} unsafe paths in archive, e.g. {}", refused.len(), path.display()),
+        )),
+    }
+}
+
+fn extract_entry(
+    archive: &dyn Vfs,
+    entry: &Entry,
+    dest_dir: &Path,
+    refused: &mut Vec<PathBuf>,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
+    let mut parts = Path::new(&entry.name).components();
+    if !matches!((parts.next(), parts.next()), (Some(Component::Normal(_)), None)) {
+        refused.push(entry.path.clone());
+        let (files, bytes) = measure_archive_sources(archive, std::slice::from_ref(&entry.path));
+        return progress.skip(files, bytes);
+    }
+    let Some((target, append)) = resolve_extract_target(archive, entry, &dest_dir.join(&entry.name), progress)? else {
+        return Ok(());
+    };
+    if entry.is_dir {
+        fs::create_dir_all(&target)?;
+        for child in archive.list(&entry.path)? {
+            extract_entry(archive, &child, &target, refused, progress)?;
+        }
+    } else if let Some(link) = &entry.link_target {
+        progress.file(&entry.path)?;
+        return std::os::unix::fs::symlink(link, &target);
+    } else {
+        progress.file(&entry.path)?;
+        write_extracted(archive, entry, &target, append, progress)?;
+        if append {
+            return Ok(());
+        }
+    }
+    if let Some(modified) = entry.modified {
+        fs::File::open(&target)?.set_times(fs::FileTimes::new().set_modified(modified))?;
+    }
+    // Set-id and sticky bits are not carried over from an archive.
+    if let Some(mode) = entry.mode {
+        fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    Ok(())
+}
+
+/// Like `copy_file`, for a file inside an archive. Reads go through
+/// `open_read`, which seeks to the member instead of decoding the archive
+/// from its start again.
+fn write_extracted(archive: &dyn Vfs, entry: &Entry, target: &Path, append: bool, progress: &mut dyn Progress) -> io::Result<()> {
+    let mut input = archive.open_read(&entry.path)?;
+    if append {
+        let mut output = fs::OpenOptions::new().append(true).open(target)?;
+        return copy_data(&mut input, &mut output, progress);
+    }
+    let replacing = fs::symlink_metadata(target).is_ok();
+    let (temp_path, mut output) = create_temp_beside(target)?;
+    let result = copy_data(&mut input, &mut output, progress)
+        .and_then(|()| if replacing { output.sync_all() } else { Ok(()) })
+        .and_then(|()| fs::rename(&temp_path, target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// `resolve_target` for an entry coming out of an archive.
+fn resolve_extract_target(
+    archive: &dyn Vfs,
+    src: &Entry,
+    target: &Path,
+    progress: &mut dyn Progress,
+) -> io::Result<Option<(PathBuf, bool)>> {
+    let mut target = target.to_path_buf();
+    loop {
+        let Ok(meta) = fs::symlink_metadata(&target) else { return Ok(Some((target, false))) };
+        if src.is_dir && meta.is_dir() {
+            return Ok(Some((target, false)));
+        }
+        match progress.archive_conflict(src, &target)? {
+            // A file replacing a file is renamed over it by `write_extracted`.
+            ConflictChoice::Overwrite => {
+                if src.is_dir || src.link_target.is_some() || meta.is_dir() {
+                    remove_path(&target)?;
+                }
+                return Ok(Some((target, false)));
+            }
+            ConflictChoice::Rename(renamed) => target = renamed,
+            ConflictChoice::Append if meta.is_file() && !src.is_dir && src.link_target.is_none() => {
+                return Ok(Some((target, true)));
+            }
+            ConflictChoice::Skip | ConflictChoice::Append => {
+                let (files, bytes) = measure_archive_sources(archive, std::slice::from_ref(&src.path));
+                progress.skip(files, bytes)?;
+                return Ok(None);
+            }
+        }
     }
 }
 
-pub fn move_entry(src: &Path, dest: &Path) -> io::Result<()> {
-    match fs::rename(src, dest) {
-        Ok(()) => Ok(()),
-        Err(_) => {
-            copy_entry(src, dest)?;
-            if src.is_dir() {
-                fs::remove_dir_all(src)
+/// Store the local `sources` in the directory `dest` of `archive`, removing
+/// them afterwards when `remove_sources` is set. Symlinked directories go in
+/// as empty directories instead of being followed.
+pub fn add_to_archive(
+    archive: &dyn Vfs,
+    sources: &[PathBuf],
+    dest: &Path,
+    remove_sources: bool,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
+    let mut files = Vec::new();
+    let mut listings = HashMap::new();
+    for src in sources {
+        let target = dest.join(src.file_name().unwrap_or_default());
+        collect_local_files(archive, src, &target, &mut listings, &mut files, progress)?;
+    }
+    archive.add_files(&files, progress)?;
+    if remove_sources {
+        // Only what went in; skipped files stay, and so do the directories holding them.
+        for (src, _) in files.iter().rev() {
+            if src.is_dir() && !src.is_symlink() {
+                let _ = fs::remove_dir(src);
             } else {
-                fs::remove_file(src)
+                fs::remove_file(src)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Pair `src` and everything below it with its path in `archive`, settling
+/// collisions with existing entries on the way. A skipped directory leaves
+/// out everything below it.
+fn collect_local_files(
+    archive: &dyn Vfs,
+    src: &Path,
+    target: &Path,
+    listings: &mut HashMap<PathBuf, Vec<Entry>>,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
+    let Some(target) = resolve_archive_target(archive, src, target, listings, progress)? else { return Ok(()) };
+    files.push((src.to_path_buf(), target.clone()));
+    if src.is_dir() && !src.is_symlink() {
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            collect_local_files(archive, &entry.path(), &target.join(entry.file_name()), listings, files, progress)?;
+        }
+    }
+    Ok(())
+}
+
+/// `resolve_target` for a local file going into `archive`. Directories are
+/// listed once and kept in `listings`, as every lookup reads the archive.
+fn resolve_archive_target(
+    archive: &dyn Vfs,
+    src: &Path,
+    target: &Path,
+    listings: &mut HashMap<PathBuf, Vec<Entry>>,
+    progress: &mut dyn Progress,
+) -> io::Result<Option<PathBuf>> {
+    let mut target = target.to_path_buf();
+    loop {
+        let dir = target.parent().unwrap_or(Path::new("")).to_path_buf();
+        let listing = listings.entry(dir).or_insert_with_key(|dir| archive.list(dir).unwrap_or_default());
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let Some(existing) = listing.iter().find(|entry| entry.name == name) else { return Ok(Some(target)) };
+        if src.is_dir() && existing.is_dir {
+            return Ok(Some(target));
+        }
+        match progress.conflict_in_archive(src, existing)? {
+            ConflictChoice::Overwrite => {
+                if src.is_dir() || existing.is_dir {
+                    archive.remove(&target)?;
+                }
+                return Ok(Some(target));
+            }
+            ConflictChoice::Rename(renamed) => target = renamed,
+            // Archives cannot append to an entry.
+            ConflictChoice::Skip | ConflictChoice::Append => {
+                skip_entry(src, progress)?;
+                return Ok(None);
             }
         }
     }
 }
 
+/// A hash of the contents of `path`, to tell whether the file changed.
+pub fn file_digest(path: &Path) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    let mut file = fs::File::open(path)?;
+    let mut buf = vec![0u8; COPY_BUFFER];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hasher.finish()),
+            read => hasher.write(&buf[..read]),
+        }
+    }
+}
+
 pub fn remove_path(path: &Path) -> io::Result<()> {
     if path.is_dir() {
         fs::remove_dir_all(path)
@@ -253,46 +791,85 @@ pub fn remove_path(path: &Path) -> io::Result<()> {
     }
 }
 
-pub fn copy_dir_recursive(src: &Path, dest: &Path) -> io::Result<()> {
+/// Like `remove_path`, but one file at a time so the deletion can be followed and cancelled.
+fn remove_tree(path: &Path, progress: &mut dyn Progress) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            remove_tree(&entry?.path(), progress)?;
+        }
+        fs::remove_dir(path)
+    } else {
+        progress.file(path)?;
+        fs::remove_file(path)?;
+        progress.bytes(meta.len())
+    }
+}
+
+pub fn copy_dir_recursive(
+    src: &Path,
+    dest: &Path,
+    rel: &Path,
+    options: &TransferOptions,
+    progress: &mut dyn Progress,
+) -> io::Result<()> {
     if !dest.exists() {
         fs::create_dir_all(dest)?;
     }
     for entry in fs::read_dir(src)? {
         let entry = entry?;
-        let path = entry.path();
-        let target = dest.join(entry.file_name());
-        if path.is_dir() {
-            copy_dir_recursive(&path, &target)?;
-        } else {
-            fs::copy(&path, &target)?;
-        }
+        let name = entry.file_name();
+        copy_entry(&entry.path(), &dest.join(&name), &rel.join(&name), options, progress)?;
     }
     Ok(())
 }
 
-pub fn find_matches(base: &Path, query: &str, show_hidden: bool) -> Vec<PathBuf> {
-    let query = query.to_lowercase();
+/// First `name (N).ext` next to `path` that doesn't exist yet.
+pub fn unique_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Find entries below `base` whose names pass `filter`. Directories excluded
+/// by the filter are not searched.
+pub fn find_matches(vfs: &dyn Vfs, base: &Path, filter: &FileFilter, show_hidden: bool) -> Vec<PathBuf> {
     let mut results = Vec::new();
     let mut stack = vec![base.to_path_buf()];
     while let Some(dir) = stack.pop() {
-        let Ok(read) = fs::read_dir(&dir) else { continue };
-        for entry in read.flatten() {
-            let path = entry.path();
-            let name = entry.file_name().to_string_lossy().to_string();
-            if !show_hidden && name.starts_with('.') {
+        let Ok(entries) = vfs.list(&dir) else { continue };
+        for entry in entries {
+            if !show_hidden && entry.name.starts_with('.') {
+                continue;
+            }
+            let rel = entry.path.strip_prefix(base).unwrap_or(&entry.path);
+            if entry.is_dir && filter.excludes_dir(rel) {
                 continue;
             }
-            if name.to_lowercase().contains(&query) {
-                results.push(path.clone());
+            if filter.matches_file(rel) {
+                results.push(entry.path.clone());
             }
-            if path.is_dir() {
-                stack.push(path);
+            if entry.is_dir {
+                stack.push(entry.path);
             }
         }
     }
     results
 }
 
+/// Turn a Find query into a filter: plain text matches anywhere in the name,
+/// anything with wildcards or `;`/`|` is taken as masks.
+pub fn find_filter(query: &str) -> FileFilter {
+    if query.contains(['*', '?', ';', '|']) {
+        FileFilter::parse(query)
+    } else {
+        FileFilter::parse(&format!("*{query}*"))
+    }
+}
+
 pub fn build_tree(base: &Path, max_depth: usize, show_hidden: bool) -> Vec<TreeItem> {
     let mut items = Vec::new();
     let mut stack = vec![(base.to_path_buf(), 0usize)];
@@ -341,38 +918,114 @@ pub fn list_drive_roots() -> Vec<PathBuf> {
     roots
 }
 
+pub fn home_dir() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/home/nuc".to_string()))
+}
+
+pub fn config_dir() -> PathBuf {
+    home_dir().join(".frankencommander")
+}
+
 pub fn user_menu_path() -> PathBuf {
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/home/nuc".to_string());
-    Path::new(&home).join(".frankencommander").join("usermenu.txt")
+    config_dir().join("usermenu.txt")
 }
 
-pub fn ensure_user_menu_file(path: &Path) -> io::Result<()> {
+/// Per-directory user menu that takes precedence over the global one.
+pub const LOCAL_MENU_NAME: &str = ".fc.menu";
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config")
+}
+
+pub fn filters_path() -> PathBuf {
+    config_dir().join("filters")
+}
+
+pub fn history_path() -> PathBuf {
+    config_dir().join("history")
+}
+
+pub const HISTORY_LIMIT: usize = 500;
+
+pub fn load_history(path: &Path) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(path) else { return Vec::new() };
+    let mut items: Vec<String> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.to_string())
+        .collect();
+    if items.len() > HISTORY_LIMIT {
+        items.drain(..items.len() - HISTORY_LIMIT);
+    }
+    items
+}
+
+pub fn save_history(path: &Path, items: &[String]) -> io::Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
-    if !path.exists() {
-        let sample = "List|ls -la\nEdit config|$EDITOR ~/.frankencommander/usermenu.txt\n";
-        fs::write(path, sample)?;
-    }
-    Ok(())
+    let mut content = items.join("\n");
+    content.push('\n');
+    fs::write(path, content)
 }
 
-pub fn load_user_menu(path: &Path) -> Vec<UserMenuItem> {
-    let mut items = Vec::new();
-    let Ok(content) = fs::read_to_string(path) else { return items };
-    for line in content.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
-            continue;
+/// Case-insensitive DOS-style wildcard match (`*` and `?`).
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    wildcard_match_case(pattern, text, false)
+}
+
+pub fn wildcard_match_case(pattern: &str, text: &str, case_sensitive: bool) -> bool {
+    let fold = |s: &str| if case_sensitive { s.to_string() } else { s.to_lowercase() };
+    let pattern: Vec<char> = fold(pattern).chars().collect();
+    let text: Vec<char> = fold(text).chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < text.len() {
+        if pi < pattern.len() && (pattern[pi] == '?' || pattern[pi] == text[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < pattern.len() && pattern[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
         }
-        let mut parts = line.splitn(2, '|');
-        let label = parts.next().unwrap_or("").trim().to_string();
-        let command = parts.next().unwrap_or("").trim().to_string();
-        if !label.is_empty() {
-            items.push(UserMenuItem { label, command });
+    }
+    pattern[pi..].iter().all(|&c| c == '*')
+}
+
+/// Expand a leading `~` to the home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    if path == "~" {
+        home_dir()
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        home_dir().join(rest)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Resolve `.` and `..` components without touching the filesystem, so symlinked
+/// directories keep the path the user typed.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
         }
     }
-    items
+    if out.as_os_str().is_empty() {
+        out.push("/");
+    }
+    out
 }
 
 pub fn sync_plan(src: &Path, dst: &Path) -> Vec<PathBuf> {
@@ -411,12 +1064,12 @@ pub fn sync_plan(src: &Path, dst: &Path) -> Vec<PathBuf> {
     ops
 }
 
-pub fn sync_execute(ops: &[PathBuf], src_root: &Path, dst_root: &Path) -> io::Result<usize> {
+pub fn sync_execute(ops: &[PathBuf], src_root: &Path, dst_root: &Path, progress: &mut dyn Progress) -> io::Result<usize> {
     let mut count = 0;
     for src in ops {
         let rel = src.strip_prefix(src_root).unwrap_or(src);
         let target = dst_root.join(rel);
-        copy_entry(src, &target)?;
+        copy_entry(src, &target, rel, &TransferOptions::default(), progress)?;
         count += 1;
     }
     Ok(count)
@@ -0,0 +1,547 @@
+#![forbid(unsafe_code)]
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::filter::FileFilter;
+use crate::fs_ops::{
+    add_to_archive, copy_sources, delete_sources, extract_sources, measure_archive_sources, measure_sources, move_sources,
+    sync_execute, ConflictChoice, Progress, TransferOptions,
+};
+use crate::model::Entry;
+use crate::pack::{measure_pack, pack_sources, PackOptions};
+use crate::vfs::Vfs;
+
+/// How often the worker sends progress while a file is being copied.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A file operation that runs on a worker thread. `overwrite` replaces existing
+/// targets without asking; a delete `filter` limits what is removed.
+#[derive(Debug, Clone)]
+pub enum JobTask {
+    Copy { sources: Vec<PathBuf>, dest: PathBuf, overwrite: bool, options: TransferOptions },
+    Move { sources: Vec<PathBuf>, dest: PathBuf, overwrite: bool, options: TransferOptions },
+    Delete { sources: Vec<PathBuf>, filter: Option<FileFilter> },
+    Sync { ops: Vec<PathBuf>, src_root: PathBuf, dst_root: PathBuf },
+    /// Copy `sources` out of `archive` into the directory `dest`.
+    Extract { archive: Arc<dyn Vfs>, sources: Vec<PathBuf>, dest: PathBuf },
+    /// Store local `sources` in the directory `dest` of `archive`; a move
+    /// deletes them afterwards.
+    AddToArchive { archive: Arc<dyn Vfs>, sources: Vec<PathBuf>, dest: PathBuf, remove_sources: bool },
+    /// Create the archive `dest` from `sources`.
+    Pack { sources: Vec<PathBuf>, dest: PathBuf, options: PackOptions },
+}
+
+impl JobTask {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            JobTask::Copy { .. } => "Copy",
+            JobTask::Move { .. } => "Move",
+            JobTask::Delete { .. } => "Delete",
+            JobTask::Sync { .. } => "Sync",
+            JobTask::Extract { .. } => "Extract",
+            JobTask::AddToArchive { remove_sources: false, .. } => "Copy",
+            JobTask::AddToArchive { remove_sources: true, .. } => "Move",
+            JobTask::Pack { .. } => "Pack",
+        }
+    }
+
+    /// One-line description for the progress dialog and the jobs list.
+    pub fn describe(&self) -> String {
+        match self {
+            JobTask::Copy { sources, dest, .. } => {
+                format!("Copying {} to {}", describe_sources(sources), dest.display())
+            }
+            JobTask::Move { sources, dest, .. } => {
+                format!("Moving {} to {}", describe_sources(sources), dest.display())
+            }
+            JobTask::Delete { sources, .. } => format!("Deleting {}", describe_sources(sources)),
+            JobTask::Sync { src_root, dst_root, .. } => {
+                format!("Synchronizing {} to {}", src_root.display(), dst_root.display())
+            }
+            JobTask::Extract { sources, dest, .. } => {
+                format!("Extracting {} to {}", describe_sources(sources), dest.display())
+            }
+            JobTask::AddToArchive { archive, sources, dest, remove_sources } => {
+                let verb = if *remove_sources { "Moving" } else { "Copying" };
+                let dest = Path::new(&archive.name()).join(dest);
+                format!("{verb} {} into {}", describe_sources(sources), dest.display())
+            }
+            JobTask::Pack { sources, dest, .. } => {
+                format!("Packing {} into {}", describe_sources(sources), dest.display())
+            }
+        }
+    }
+
+    fn sources(&self) -> &[PathBuf] {
+        match self {
+            JobTask::Copy { sources, .. }
+            | JobTask::Move { sources, .. }
+            | JobTask::Delete { sources, .. }
+            | JobTask::Extract { sources, .. }
+            | JobTask::AddToArchive { sources, .. }
+            | JobTask::Pack { sources, .. } => sources,
+            JobTask::Sync { ops, .. } => ops,
+        }
+    }
+}
+
+fn describe_sources(sources: &[PathBuf]) -> String {
+    match sources {
+        [single] => format!(
+            "\"{}\"",
+            single.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+        ),
+        _ => format!("{} items", sources.len()),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JobProgress {
+    pub files_done: u64,
+    pub files_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current: Option<PathBuf>,
+}
+
+impl JobProgress {
+    pub fn percent(&self) -> u64 {
+        (self.bytes_done * 100)
+            .checked_div(self.bytes_total)
+            .or_else(|| (self.files_done * 100).checked_div(self.files_total))
+            .unwrap_or(0)
+            .min(100)
+    }
+}
+
+/// Both sides of a copy/move collision, for the conflict dialog.
+#[derive(Debug, Clone)]
+pub struct ConflictInfo {
+    pub src: PathBuf,
+    pub target: PathBuf,
+    pub src_size: u64,
+    pub src_modified: Option<SystemTime>,
+    pub target_size: u64,
+    pub target_modified: Option<SystemTime>,
+}
+
+impl ConflictInfo {
+    fn new(src: &Path, target: &Path) -> Self {
+        let src_meta = fs::metadata(src).ok();
+        let target_meta = fs::metadata(target).ok();
+        Self {
+            src: src.to_path_buf(),
+            target: target.to_path_buf(),
+            src_size: src_meta.as_ref().map(|m| m.len()).unwrap_or(0),
+            src_modified: src_meta.and_then(|m| m.modified().ok()),
+            target_size: target_meta.as_ref().map(|m| m.len()).unwrap_or(0),
+            target_modified: target_meta.and_then(|m| m.modified().ok()),
+        }
+    }
+
+    fn for_entry(src: &Entry, target: &Path) -> Self {
+        let target_meta = fs::metadata(target).ok();
+        Self {
+            src: src.path.clone(),
+            target: target.to_path_buf(),
+            src_size: src.size,
+            src_modified: src.modified,
+            target_size: target_meta.as_ref().map(|m| m.len()).unwrap_or(0),
+            target_modified: target_meta.and_then(|m| m.modified().ok()),
+        }
+    }
+
+    fn for_archive_target(src: &Path, target: &Entry) -> Self {
+        let src_meta = fs::metadata(src).ok();
+        Self {
+            src: src.to_path_buf(),
+            target: target.path.clone(),
+            src_size: src_meta.as_ref().map(|m| m.len()).unwrap_or(0),
+            src_modified: src_meta.and_then(|m| m.modified().ok()),
+            target_size: target.size,
+            target_modified: target.modified,
+        }
+    }
+
+    fn target_is_older(&self) -> bool {
+        match (self.src_modified, self.target_modified) {
+            (Some(src), Some(target)) => target < src,
+            _ => false,
+        }
+    }
+}
+
+/// The user's pick in the conflict dialog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictAnswer {
+    Overwrite,
+    Skip,
+    OverwriteAll,
+    SkipAll,
+    OverwriteOlder,
+    Rename(PathBuf),
+    Append,
+    Cancel,
+}
+
+/// Standing decision for the rest of a job once an "all" answer was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConflictPolicy {
+    Ask,
+    OverwriteAll,
+    SkipAll,
+    OverwriteOlder,
+}
+
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    Progress(JobProgress),
+    /// The worker is blocked until `Job::answer_conflict` is called.
+    Conflict(ConflictInfo),
+    Finished(Result<(), String>),
+}
+
+/// What a worker thread sends back; the app turns these into `Msg::Job`.
+#[derive(Debug, Clone)]
+pub struct JobUpdate {
+    pub id: usize,
+    pub event: JobEvent,
+}
+
+#[derive(Debug, Default)]
+struct JobControl {
+    cancel: AtomicBool,
+    pause: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub task: JobTask,
+    pub progress: JobProgress,
+    pub conflict: Option<ConflictInfo>,
+    started: Instant,
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+    control: Arc<JobControl>,
+    answers: Sender<ConflictAnswer>,
+}
+
+impl Job {
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(Instant::now()),
+            (false, Some(at)) => {
+                self.paused_total += at.elapsed();
+                self.paused_at = None;
+            }
+            _ => {}
+        }
+        self.control.pause.store(paused, Ordering::SeqCst);
+    }
+
+    pub fn cancel(&self) {
+        self.control.cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// Unblock the worker waiting on the pending conflict.
+    pub fn answer_conflict(&mut self, answer: ConflictAnswer) {
+        if self.conflict.take().is_some() {
+            let _ = self.answers.send(answer);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.control.cancel.load(Ordering::SeqCst)
+    }
+
+    /// Running time, not counting pauses.
+    pub fn elapsed(&self) -> Duration {
+        let paused = self.paused_total + self.paused_at.map(|at| at.elapsed()).unwrap_or_default();
+        self.started.elapsed().saturating_sub(paused)
+    }
+
+    /// Bytes per second so far.
+    pub fn throughput(&self) -> u64 {
+        let secs = self.elapsed().as_secs_f64();
+        if secs < 0.5 {
+            return 0;
+        }
+        (self.progress.bytes_done as f64 / secs) as u64
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let rate = self.throughput();
+        if rate == 0 || self.progress.bytes_total == 0 {
+            return None;
+        }
+        let left = self.progress.bytes_total.saturating_sub(self.progress.bytes_done);
+        Some(Duration::from_secs(left / rate))
+    }
+}
+
+/// Owns the running jobs and the channel their workers report through.
+#[derive(Debug)]
+pub struct JobManager {
+    jobs: Vec<Job>,
+    next_id: usize,
+    tx: Sender<JobUpdate>,
+    rx: Receiver<JobUpdate>,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self { jobs: Vec::new(), next_id: 1, tx, rx }
+    }
+
+    pub fn spawn(&mut self, task: JobTask) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let control = Arc::new(JobControl::default());
+        let (answers, answer_rx) = mpsc::channel();
+        let policy = match &task {
+            JobTask::Copy { overwrite: true, .. } | JobTask::Move { overwrite: true, .. } | JobTask::Sync { .. } => {
+                ConflictPolicy::OverwriteAll
+            }
+            _ => ConflictPolicy::Ask,
+        };
+        let worker = Worker {
+            id,
+            tx: self.tx.clone(),
+            control: Arc::clone(&control),
+            progress: JobProgress::default(),
+            last_report: Instant::now(),
+            answers: answer_rx,
+            policy,
+        };
+        let worker_task = task.clone();
+        thread::spawn(move || worker.run(worker_task));
+        self.jobs.push(Job {
+            id,
+            task,
+            progress: JobProgress::default(),
+            conflict: None,
+            started: Instant::now(),
+            paused_at: None,
+            paused_total: Duration::ZERO,
+            control,
+            answers,
+        });
+        id
+    }
+
+    /// Everything the workers reported since the last call.
+    pub fn poll(&self) -> Vec<JobUpdate> {
+        self.rx.try_iter().collect()
+    }
+
+    /// Record `update`. A finished job is removed and handed back with its result.
+    pub fn apply(&mut self, update: JobUpdate) -> Option<(Job, Result<(), String>)> {
+        let idx = self.jobs.iter().position(|job| job.id == update.id)?;
+        match update.event {
+            JobEvent::Progress(progress) => {
+                self.jobs[idx].progress = progress;
+                None
+            }
+            JobEvent::Conflict(info) => {
+                self.jobs[idx].conflict = Some(info);
+                None
+            }
+            JobEvent::Finished(result) => Some((self.jobs.remove(idx), result)),
+        }
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    /// A job whose worker is waiting for a conflict answer.
+    pub fn pending_conflict(&self) -> Option<usize> {
+        self.jobs.iter().find(|job| job.conflict.is_some()).map(|job| job.id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn cancel_all(&mut self) {
+        for job in &mut self.jobs {
+            job.cancel();
+            job.set_paused(false);
+        }
+    }
+}
+
+/// The worker side of a job: runs the task and implements `Progress` so the
+/// fs_ops helpers report into the channel and honour pause/cancel.
+struct Worker {
+    id: usize,
+    tx: Sender<JobUpdate>,
+    control: Arc<JobControl>,
+    progress: JobProgress,
+    last_report: Instant,
+    answers: Receiver<ConflictAnswer>,
+    policy: ConflictPolicy,
+}
+
+impl Worker {
+    fn run(mut self, task: JobTask) {
+        let (files, bytes) = match &task {
+            JobTask::Extract { archive, sources, .. } => measure_archive_sources(archive.as_ref(), sources),
+            JobTask::Pack { sources, options, .. } => measure_pack(sources, options),
+            _ => measure_sources(task.sources()),
+        };
+        self.progress.files_total = files;
+        self.progress.bytes_total = bytes;
+        self.report();
+        let result = match &task {
+            JobTask::Copy { sources, dest, options, .. } => copy_sources(sources, dest, options, &mut self),
+            JobTask::Move { sources, dest, options, .. } => move_sources(sources, dest, options, &mut self),
+            JobTask::Delete { sources, filter } => delete_sources(sources, filter.as_ref(), &mut self),
+            JobTask::Sync { ops, src_root, dst_root } => {
+                sync_execute(ops, src_root, dst_root, &mut self).map(|_| ())
+            }
+            JobTask::Extract { archive, sources, dest } => extract_sources(archive.as_ref(), sources, dest, &mut self),
+            JobTask::AddToArchive { archive, sources, dest, remove_sources } => {
+                add_to_archive(archive.as_ref(), sources, dest, *remove_sources, &mut self)
+            }
+            JobTask::Pack { sources, dest, options } => pack_sources(sources, dest, options, &mut self),
+        };
+        self.report();
+        let _ = self.tx.send(JobUpdate {
+            id: self.id,
+            event: JobEvent::Finished(result.map_err(|err| err.to_string())),
+        });
+    }
+
+    fn report(&mut self) {
+        self.last_report = Instant::now();
+        let _ = self.tx.send(JobUpdate { id: self.id, event: JobEvent::Progress(self.progress.clone()) });
+    }
+
+    /// Block while paused; fail once cancelled.
+    fn check(&mut self) -> io::Result<()> {
+        while self.control.pause.load(Ordering::SeqCst) && !self.control.cancel.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(50));
+        }
+        if self.control.cancel.load(Ordering::SeqCst) {
+            return Err(cancelled());
+        }
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+        }
+        Ok(())
+    }
+
+    /// Send the conflict to the UI and wait for the answer.
+    fn ask(&mut self, info: ConflictInfo) -> io::Result<ConflictAnswer> {
+        self.report();
+        let _ = self.tx.send(JobUpdate { id: self.id, event: JobEvent::Conflict(info) });
+        loop {
+            match self.answers.recv_timeout(Duration::from_millis(100)) {
+                Ok(answer) => return Ok(answer),
+                Err(RecvTimeoutError::Timeout) if !self.control.cancel.load(Ordering::SeqCst) => {}
+                Err(_) => return Err(cancelled()),
+            }
+        }
+    }
+}
+
+fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "cancelled")
+}
+
+impl Progress for Worker {
+    fn file(&mut self, path: &Path) -> io::Result<()> {
+        self.progress.files_done += 1;
+        self.progress.current = Some(path.to_path_buf());
+        self.check()
+    }
+
+    fn bytes(&mut self, count: u64) -> io::Result<()> {
+        self.progress.bytes_done += count;
+        self.check()
+    }
+
+    fn skip(&mut self, files: u64, bytes: u64) -> io::Result<()> {
+        self.progress.files_done += files;
+        self.progress.bytes_done += bytes;
+        self.check()
+    }
+
+    fn conflict(&mut self, src: &Path, target: &Path) -> io::Result<ConflictChoice> {
+        self.decide(ConflictInfo::new(src, target))
+    }
+
+    fn archive_conflict(&mut self, src: &Entry, target: &Path) -> io::Result<ConflictChoice> {
+        self.decide(ConflictInfo::for_entry(src, target))
+    }
+
+    fn conflict_in_archive(&mut self, src: &Path, target: &Entry) -> io::Result<ConflictChoice> {
+        self.decide(ConflictInfo::for_archive_target(src, target))
+    }
+}
+
+impl Worker {
+    /// Answer a conflict from the standing policy, or ask the user.
+    fn decide(&mut self, info: ConflictInfo) -> io::Result<ConflictChoice> {
+        let answer = match self.policy {
+            ConflictPolicy::Ask => self.ask(info.clone())?,
+            ConflictPolicy::OverwriteAll => ConflictAnswer::Overwrite,
+            ConflictPolicy::SkipAll => ConflictAnswer::Skip,
+            ConflictPolicy::OverwriteOlder => ConflictAnswer::OverwriteOlder,
+        };
+        Ok(match answer {
+            ConflictAnswer::Overwrite => ConflictChoice::Overwrite,
+            ConflictAnswer::Skip => ConflictChoice::Skip,
+            ConflictAnswer::OverwriteAll => {
+                self.policy = ConflictPolicy::OverwriteAll;
+                ConflictChoice::Overwrite
+            }
+            ConflictAnswer::SkipAll => {
+                self.policy = ConflictPolicy::SkipAll;
+                ConflictChoice::Skip
+            }
+            ConflictAnswer::OverwriteOlder => {
+                self.policy = ConflictPolicy::OverwriteOlder;
+                if info.target_is_older() { ConflictChoice::Overwrite } else { ConflictChoice::Skip }
+            }
+            ConflictAnswer::Rename(path) => ConflictChoice::Rename(path),
+            ConflictAnswer::Append => ConflictChoice::Append,
+            ConflictAnswer::Cancel => {
+                self.control.cancel.store(true, Ordering::SeqCst);
+                return Err(cancelled());
+            }
+        })
+    }
+}
+
+/// `h:mm:ss` or `m:ss`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
@@ -1,12 +1,22 @@
 #![forbid(unsafe_code)]
 
 mod app;
+mod config;
+mod diff;
+mod editor;
+mod encoding;
+mod filter;
 mod fs_ops;
+mod jobs;
 mod menu;
 mod model;
+mod pack;
 mod pane;
+mod tar;
 mod ui;
+mod usermenu;
 mod vfs;
+mod viewer;
 
 fn main() -> std::io::Result<()> {
     app::App::run()
@@ -16,10 +16,15 @@ pub fn menu_items(menu_idx: usize) -> &'static [MenuItem] {
         1 => &[
             MenuItem { label: "Directory tree", action: MenuAction::Tree, shortcut: None, checked: None, separator_after: false },
             MenuItem { label: "Find file", action: MenuAction::Find, shortcut: Some("Alt+F7"), checked: None, separator_after: false },
+            MenuItem { label: "Compare files", action: MenuAction::Compare, shortcut: None, checked: None, separator_after: false },
+            MenuItem { label: "Pack files", action: MenuAction::Pack, shortcut: Some("Alt+F5"), checked: None, separator_after: false },
+            MenuItem { label: "Extract archive", action: MenuAction::ExtractArchive, shortcut: Some("Alt+F9"), checked: None, separator_after: false },
+            MenuItem { label: "Background jobs", action: MenuAction::Jobs, shortcut: Some("Alt+J"), checked: None, separator_after: false },
         ],
         2 => &[
             MenuItem { label: "Configuration", action: MenuAction::Config, shortcut: None, checked: None, separator_after: false },
-            MenuItem { label: "Panel options", action: MenuAction::PanelOptions, shortcut: None, checked: None, separator_after: false },
+            MenuItem { label: "Panel options", action: MenuAction::PanelOptions, shortcut: None, checked: None, separator_after: true },
+            MenuItem { label: "Save setup", action: MenuAction::SaveSetup, shortcut: Some("Shift+F9"), checked: None, separator_after: false },
         ],
         3 => &[
             // Panel view modes
@@ -1,12 +1,21 @@
 #![forbid(unsafe_code)]
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::SystemTime;
 
 use ftui::widgets::table::TableState;
 
+use crate::diff::DiffRow;
+use crate::editor::{Edit, Pos};
+use crate::encoding::Encoding;
+use crate::filter::{FileFilter, SavedFilter};
+use crate::pack::PackFormat;
+use crate::vfs::{ExtractDir, Vfs};
+use crate::viewer::{Finder, LineIndex, ViewSource};
+
 #[derive(Debug, Clone)]
 pub struct Entry {
     pub name: String,
@@ -15,6 +24,9 @@ pub struct Entry {
     pub size: u64,
     pub modified: Option<SystemTime>,
     pub is_system: bool,
+    /// Permission bits, when the filesystem records them.
+    pub mode: Option<u32>,
+    pub link_target: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,11 +59,142 @@ pub enum PanelMode {
     QuickView,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    Text,
+    Hex,
+}
+
+/// The viewer reads only the part of the file it shows, so it works the same
+/// for a few bytes and a few gigabytes.
+#[derive(Debug)]
 pub struct Viewer {
+    pub path: PathBuf,
+    pub source: ViewSource,
+    /// Size of `source` in bytes.
+    pub len: u64,
+    pub mode: ViewMode,
+    /// How text mode decodes the bytes (F8 cycles).
+    pub encoding: Encoding,
+    /// Byte offset of the first row shown: a line start in text mode, a
+    /// multiple of 16 in hex mode.
+    pub top: u64,
+    /// Long lines continue on the next rows instead of being cut off (F2).
+    pub wrap: bool,
+    /// Wrapped rows of the line at `top` scrolled above the screen.
+    pub top_row: usize,
+    /// First text column shown when not wrapping.
+    pub left: usize,
+    /// Offsets saved with Ctrl+0..9.
+    pub bookmarks: [Option<u64>; 10],
+    /// Watch the file for appended data (Ctrl+F). The end stays in view
+    /// unless the user scrolls away from it.
+    pub follow: bool,
+    /// Line numbers, built as far into the file as they were needed.
+    pub index: RefCell<LineIndex>,
+    /// Last search, kept to highlight its matches and for Shift+F7.
+    pub finder: Option<Finder>,
+    pub hit: Option<SearchHit>,
+    /// Shown in the viewer's status line until the next key.
+    pub message: Option<String>,
+    /// Text rows and columns of the last render, for paging, wrapping and
+    /// keeping hits in view.
+    pub view_height: Cell<usize>,
+    pub view_width: Cell<usize>,
+}
+
+/// The internal editor (F4): the whole file as lines, with selection, undo
+/// and a private clipboard.
+#[derive(Debug)]
+pub struct Editor {
     pub path: PathBuf,
     pub lines: Vec<String>,
-    pub scroll: usize,
+    /// The file used `\r\n`; saving writes it back the same way.
+    pub crlf: bool,
+    pub final_newline: bool,
+    pub cursor: Pos,
+    /// Other end of the selection, set while Shift is held.
+    pub anchor: Option<Pos>,
+    /// First line and screen column shown.
+    pub top: usize,
+    pub left: usize,
+    /// Typing replaces instead of inserting (Ins).
+    pub overwrite: bool,
+    pub modified: bool,
+    pub clipboard: String,
+    pub undo: Vec<Edit>,
+    pub redo: Vec<Edit>,
+    /// Undo step the next edit belongs to.
+    pub group: u64,
+    /// The last edit was typing, so the next character joins its undo step.
+    pub typing: bool,
+    /// Last search, for Shift+F7.
+    pub search: Option<String>,
+    /// Shown in the editor's status line until the next edit.
+    pub message: Option<String>,
+    pub view_height: Cell<usize>,
+    pub view_width: Cell<usize>,
+}
+
+/// The compare view: two files side by side with their differences lined up.
+#[derive(Debug)]
+pub struct Compare {
+    pub left_path: PathBuf,
+    pub right_path: PathBuf,
+    pub content: CompareContent,
+    /// First row shown; a byte offset for binary files.
+    pub top: u64,
+    /// Sideways scroll of both text columns.
+    pub left: usize,
+    pub message: Option<String>,
+    pub view_height: Cell<usize>,
+    pub view_width: Cell<usize>,
+}
+
+#[derive(Debug)]
+pub enum CompareContent {
+    Text {
+        left: Vec<String>,
+        right: Vec<String>,
+        rows: Vec<DiffRow>,
+        /// First row of every block of differences.
+        hunks: Vec<usize>,
+    },
+    Binary {
+        left: ViewSource,
+        right: ViewSource,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorAction {
+    None,
+    Close,
+    Save,
+    SaveAs,
+    Search,
+    SearchAgain,
+    Replace,
+}
+
+/// What the viewer's F7 dialog asks for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ViewerSearch {
+    pub query: String,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,
+    pub backward: bool,
+    /// The query is hex digits (`DE AD BE EF`) matched against the raw bytes.
+    pub hex: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchHit {
+    /// Byte range within the decoded line starting at file offset `line`.
+    Text { line: u64, start: usize, end: usize },
+    /// Byte range within the file.
+    Bytes { start: u64, end: u64 },
 }
 
 #[derive(Debug, Clone)]
@@ -80,21 +223,38 @@ pub enum PendingPrompt {
     Mkdir { base: PathBuf },
     Find { base: PathBuf },
     Chmod { target: PathBuf },
+    /// Where to extract the whole of `archive`.
+    ExtractAll { archive: Arc<dyn Vfs> },
+    /// New name for an entry of the archive shown in the active pane.
+    Rename { from: PathBuf },
+    UserMenuInput { macros: Vec<MenuMacro>, answers: Vec<String> },
+    ConflictRename { job_id: usize, target: PathBuf },
+    /// Name for the masks in the filter dialog, which is reopened afterwards.
+    SaveFilter { dialog: Box<Modal> },
+    /// Line number or percentage to move the viewer to.
+    ViewerGoto,
+    EditorSaveAs,
+    EditorSearch,
+    /// Text to replace, then what to replace it with.
+    EditorReplace,
+    EditorReplaceWith { find: String },
 }
 
 #[derive(Debug, Clone)]
 pub enum PendingConfirm {
-    Delete { sources: Vec<PathBuf> },
-    Overwrite {
-        kind: OverwriteKind,
-        sources: Vec<PathBuf>,
-        dest: PathBuf,
-    },
+    Delete { sources: Vec<PathBuf>, filter: Option<FileFilter> },
     Sync {
         ops: Vec<PathBuf>,
         src_root: PathBuf,
         dst_root: PathBuf,
     },
+    Quit,
+    /// Save the modified editor buffer before closing.
+    CloseEditor,
+    /// Store an edited copy of an archive entry back into the archive.
+    UpdateArchive { archive: Arc<dyn Vfs>, entry: PathBuf, temp_path: PathBuf },
+    /// Copy or move local files into the directory `dest` of `archive`.
+    AddToArchive { archive: Arc<dyn Vfs>, sources: Vec<PathBuf>, dest: PathBuf, remove_sources: bool },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -110,6 +270,14 @@ pub enum CopyDialogFocus {
     BtnCancel,
 }
 
+/// Where the filter dialog sends the chosen filter.
+#[derive(Debug, Clone)]
+pub enum FilterTarget {
+    Panel(ActivePane),
+    /// Copy, move, delete or pack dialog, reopened with the filter switched on.
+    Dialog(Box<Modal>),
+}
+
 #[derive(Debug, Clone)]
 pub struct CopyDialogState {
     pub sources: Vec<PathBuf>,
@@ -120,17 +288,52 @@ pub struct CopyDialogState {
     pub copy_newer_only: bool,
     pub use_filters: bool,
     pub check_target_space: bool,
+    /// Applied when `use_filters` is on.
+    pub filter: FileFilter,
     pub focus: CopyDialogFocus,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackDialogFocus {
+    Input,
+    Format,
+    Level,
+    RelativePaths,
+    UseFilters,
+    DeleteSources,
+    BtnPack,
+    BtnFilters,
+    BtnCancel,
+}
+
+/// The Alt+F5 dialog: what to pack, into which archive, and how.
+#[derive(Debug, Clone)]
+pub struct PackDialogState {
+    pub sources: Vec<PathBuf>,
+    pub source_name: String,
+    /// Path of the archive to create.
+    pub dest: String,
+    pub cursor: usize,
+    pub format: PackFormat,
+    pub level: u32,
+    pub relative_paths: bool,
+    pub use_filters: bool,
+    /// Applied when `use_filters` is on; starts as the panel's filter.
+    pub filter: FileFilter,
+    pub delete_sources: bool,
+    pub focus: PackDialogFocus,
+}
+
 #[derive(Debug, Clone)]
 pub enum Modal {
     CopyDialog(CopyDialogState),
     MoveDialog(CopyDialogState),
+    PackDialog(PackDialogState),
     DeleteDialog {
         sources: Vec<PathBuf>,
         source_name: String,
         use_filters: bool,
+        filter: FileFilter,
         focus: usize, // 0=checkbox, 1=Delete, 2=Filters, 3=Cancel
     },
     Prompt {
@@ -156,6 +359,8 @@ pub enum Modal {
         items: Vec<TreeItem>,
         selected: usize,
         scroll: usize,
+        /// Copy/move dialog waiting for a destination picked from the tree.
+        pick_for: Option<Box<Modal>>,
     },
     DriveMenu {
         pane: ActivePane,
@@ -168,8 +373,20 @@ pub enum Modal {
         selected: usize,
         show_hidden: bool,
         auto_save: bool,
+        internal_editor: bool,
         confirm_delete: bool,
         confirm_overwrite: bool,
+        confirm_delete_dirs: bool,
+        confirm_quit: bool,
+    },
+    Filter {
+        target: FilterTarget,
+        saved: Vec<SavedFilter>,
+        selected: usize,
+        masks: String,
+        cursor: usize,
+        case_sensitive: bool,
+        focus: usize, // 0=masks, 1=case checkbox, 2=saved list
     },
     PanelOptions {
         pane: ActivePane,
@@ -177,11 +394,34 @@ pub enum Modal {
         dirs_first: bool,
         sort_mode: SortMode,
     },
+    History {
+        items: Vec<String>,
+        selected: usize,
+        scroll: usize,
+    },
     UserMenu {
+        title: String,
         items: Vec<UserMenuItem>,
         selected: usize,
         scroll: usize,
         config_path: PathBuf,
+        stack: Vec<UserMenuLevel>,
+    },
+    Progress {
+        job_id: usize,
+        focus: usize, // 0=Cancel, 1=Pause, 2=Background
+    },
+    Jobs {
+        selected: usize,
+    },
+    Conflict {
+        job_id: usize,
+        focus: usize,
+    },
+    ViewerSearch {
+        search: ViewerSearch,
+        cursor: usize,
+        focus: usize, // 0=input, 1=case, 2=whole words, 3=regex, 4=backward, 5=hex
     },
     About,
     Help {
@@ -205,6 +445,11 @@ pub enum ViewerAction {
     None,
     Close,
     Quit,
+    Search,
+    SearchAgain,
+    Goto,
+    NextFile,
+    PrevFile,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -217,8 +462,13 @@ pub enum MenuAction {
     Move,
     Tree,
     Find,
+    Compare,
+    ExtractArchive,
+    Pack,
     Config,
     PanelOptions,
+    SaveSetup,
+    Jobs,
     // Left panel actions
     LeftBrief,
     LeftFull,
@@ -268,16 +518,85 @@ pub struct TreeItem {
     pub depth: usize,
 }
 
+/// An archive opened in a pane, stacked on whatever the pane was browsing
+/// before.
+#[derive(Debug)]
+pub struct Mount {
+    pub fs: Arc<dyn Vfs>,
+    /// The directory shown, relative to the archive root.
+    pub dir: PathBuf,
+    /// Where entries get extracted for editing and opening; created on first
+    /// use and removed once the pane leaves the archive.
+    pub extract_dir: Option<Arc<ExtractDir>>,
+}
+
+/// An archive entry extracted for editing, with a digest of its contents at
+/// the time so a change can be spotted once the editor closes.
 #[derive(Debug, Clone)]
-pub struct VfsState {
-    pub zip_path: PathBuf,
-    pub prefix: String,
+pub struct ArchiveEdit {
+    pub archive: Arc<dyn Vfs>,
+    pub entry: PathBuf,
+    pub temp_path: PathBuf,
+    pub original: u64,
+}
+
+/// One piece of a parsed user menu command. Literal text is kept as-is, the
+/// other variants are NC/MC-style placeholders expanded when the entry runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuMacro {
+    Text(String),
+    /// `!.!` or `%f`: current file name with extension
+    FileName,
+    /// `!`: current file name without extension
+    FileStem,
+    /// `!:` or `%d`: current directory
+    CurrentDir,
+    /// `!\`: current directory with a trailing separator
+    CurrentDirSlash,
+    /// `%s`: selected files (or the current file when nothing is selected)
+    Selected,
+    /// `%D`: the other panel's directory
+    OtherDir,
+    /// `%F`: the other panel's current file
+    OtherFile,
+    /// `!?prompt?default!`: ask the user before running
+    Input { prompt: String, default: String },
+}
+
+/// `+` line in the user menu file that restricts when the next entry is shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuCondition {
+    /// `+ f *.rs *.toml`: current file matches one of the masks
+    FileMatches(Vec<String>),
+    /// `+ d`: current entry is a directory
+    IsDir,
+    /// `+ !d`: current entry is a file
+    IsFile,
 }
 
 #[derive(Debug, Clone)]
 pub struct UserMenuItem {
+    pub hotkey: Option<char>,
     pub label: String,
     pub command: String,
+    pub macros: Vec<MenuMacro>,
+    pub conditions: Vec<MenuCondition>,
+    pub children: Vec<UserMenuItem>,
+}
+
+impl UserMenuItem {
+    pub fn is_submenu(&self) -> bool {
+        !self.children.is_empty()
+    }
+}
+
+/// A parent level of the user menu, restored when leaving a submenu.
+#[derive(Debug, Clone)]
+pub struct UserMenuLevel {
+    pub title: String,
+    pub items: Vec<UserMenuItem>,
+    pub selected: usize,
+    pub scroll: usize,
 }
 
 #[derive(Debug)]
@@ -288,9 +607,11 @@ pub struct Pane {
     pub selected: HashSet<PathBuf>,
     pub sort_mode: SortMode,
     pub dirs_first: bool,
-    pub vfs: Option<VfsState>,
+    /// Archives entered from this pane, innermost last.
+    pub mounts: Vec<Mount>,
     pub panelized: Option<Vec<PathBuf>>,
     pub mode: PanelMode,
+    pub filter: Option<FileFilter>,
 }
 
 impl Pane {
@@ -302,9 +623,10 @@ impl Pane {
             selected: HashSet::new(),
             sort_mode: SortMode::NameAsc,
             dirs_first: true,
-            vfs: None,
+            mounts: Vec::new(),
             panelized: None,
             mode: PanelMode::default(),
+            filter: None,
         }
     }
 }
@@ -0,0 +1,226 @@
+#![forbid(unsafe_code)]
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Component, Path, PathBuf};
+
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::filter::FileFilter;
+use crate::fs_ops::Progress;
+use crate::tar::TarWriter;
+use crate::vfs::{create_temp_beside, local_file_options};
+
+/// Archive formats the pack dialog can create.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackFormat {
+    Zip,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl PackFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PackFormat::Zip => ".zip",
+            PackFormat::TarGz => ".tar.gz",
+            PackFormat::TarXz => ".tar.xz",
+            PackFormat::TarZst => ".tar.zst",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            PackFormat::Zip => PackFormat::TarGz,
+            PackFormat::TarGz => PackFormat::TarXz,
+            PackFormat::TarXz => PackFormat::TarZst,
+            PackFormat::TarZst => PackFormat::Zip,
+        }
+    }
+
+    pub fn prev(self) -> Self {
+        match self {
+            PackFormat::Zip => PackFormat::TarZst,
+            PackFormat::TarGz => PackFormat::Zip,
+            PackFormat::TarXz => PackFormat::TarGz,
+            PackFormat::TarZst => PackFormat::TarXz,
+        }
+    }
+
+    /// Compression levels the format accepts; 0 stores a zip uncompressed.
+    /// zstd stops at 19 because the levels above need a lot of memory.
+    pub fn levels(self) -> RangeInclusive<u32> {
+        match self {
+            PackFormat::Zip | PackFormat::TarGz | PackFormat::TarXz => 0..=9,
+            PackFormat::TarZst => 1..=19,
+        }
+    }
+
+    pub fn default_level(self) -> u32 {
+        match self {
+            PackFormat::TarZst => 3,
+            _ => 6,
+        }
+    }
+}
+
+/// How the pack dialog's choices are applied.
+#[derive(Debug, Clone)]
+pub struct PackOptions {
+    pub format: PackFormat,
+    pub level: u32,
+    /// Store names relative to the panel directory; off stores the full path
+    /// without its leading `/`.
+    pub relative_paths: bool,
+    pub filter: Option<FileFilter>,
+    /// Remove what went into the archive once it is complete.
+    pub delete_sources: bool,
+}
+
+/// Something found below the sources that goes into the archive.
+struct PackItem {
+    path: PathBuf,
+    name: String,
+    metadata: fs::Metadata,
+}
+
+/// Number of files and total bytes `pack_sources` will store.
+pub fn measure_pack(sources: &[PathBuf], options: &PackOptions) -> (u64, u64) {
+    let items = collect_items(sources, options).unwrap_or_default();
+    let files = items.iter().filter(|item| item.metadata.is_file());
+    files.fold((0, 0), |(count, bytes), item| (count + 1, bytes + item.metadata.len()))
+}
+
+/// Pack `sources` into a new archive at `dest`. The archive is written under
+/// a temp name and only renamed into place once complete; the sources are
+/// deleted after that when the options ask for it.
+pub fn pack_sources(sources: &[PathBuf], dest: &Path, options: &PackOptions, progress: &mut dyn Progress) -> io::Result<()> {
+    let items = collect_items(sources, options)?;
+    let (temp_path, file) = create_temp_beside(dest)?;
+    let result = write_archive(file, &items, options, progress).and_then(|()| fs::rename(&temp_path, dest));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+    if options.delete_sources {
+        delete_packed(&items)?;
+    }
+    Ok(())
+}
+
+/// Walk `sources`, parents before children. Symlinks are stored as links;
+/// sockets, devices and the like are left out.
+fn collect_items(sources: &[PathBuf], options: &PackOptions) -> io::Result<Vec<PackItem>> {
+    let mut items = Vec::new();
+    for src in sources {
+        collect_item(src, Path::new(src.file_name().unwrap_or_default()), options, &mut items)?;
+    }
+    Ok(items)
+}
+
+fn collect_item(path: &Path, rel: &Path, options: &PackOptions, items: &mut Vec<PackItem>) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    let filter = options.filter.as_ref();
+    if metadata.is_dir() {
+        if filter.is_some_and(|filter| filter.excludes_dir(rel)) {
+            return Ok(());
+        }
+        items.push(PackItem { path: path.to_path_buf(), name: archive_name(path, rel, options), metadata });
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            collect_item(&entry.path(), &rel.join(entry.file_name()), options, items)?;
+        }
+        return Ok(());
+    }
+    let storable = metadata.is_file() || metadata.is_symlink();
+    if storable && filter.is_none_or(|filter| filter.matches_file(rel)) {
+        items.push(PackItem { path: path.to_path_buf(), name: archive_name(path, rel, options), metadata });
+    }
+    Ok(())
+}
+
+fn archive_name(path: &Path, rel: &Path, options: &PackOptions) -> String {
+    let path = if options.relative_paths { rel } else { path };
+    let parts: Vec<_> = path
+        .components()
+        .filter_map(|part| match part {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+fn write_archive(file: fs::File, items: &[PackItem], options: &PackOptions, progress: &mut dyn Progress) -> io::Result<()> {
+    let level = options.level;
+    let file = match options.format {
+        PackFormat::Zip => write_zip(file, items, level, progress)?,
+        PackFormat::TarGz => {
+            let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::new(level));
+            write_tar(encoder, items, progress)?.finish()?
+        }
+        PackFormat::TarXz => write_tar(xz2::write::XzEncoder::new(file, level), items, progress)?.finish()?,
+        PackFormat::TarZst => write_tar(zstd::Encoder::new(file, level as i32)?, items, progress)?.finish()?,
+    };
+    file.sync_all()
+}
+
+fn write_zip(file: fs::File, items: &[PackItem], level: u32, progress: &mut dyn Progress) -> io::Result<fs::File> {
+    let mut writer = ZipWriter::new(file);
+    let mut buf = vec![0u8; 64 * 1024];
+    for item in items {
+        let options = local_file_options(&item.metadata);
+        if item.metadata.is_dir() {
+            writer.add_directory(item.name.as_str(), options)?;
+        } else if item.metadata.is_symlink() {
+            let target = fs::read_link(&item.path)?;
+            writer.add_symlink(item.name.as_str(), target.to_string_lossy(), options)?;
+        } else {
+            let options = match level {
+                0 => options.compression_method(CompressionMethod::Stored),
+                _ => options.compression_method(CompressionMethod::Deflated).compression_level(Some(level as i64)),
+            };
+            progress.file(&item.path)?;
+            writer.start_file(item.name.as_str(), options)?;
+            let mut input = fs::File::open(&item.path)?;
+            loop {
+                let read = input.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                writer.write_all(&buf[..read])?;
+                progress.bytes(read as u64)?;
+            }
+        }
+    }
+    Ok(writer.finish()?)
+}
+
+fn write_tar<W: Write>(out: W, items: &[PackItem], progress: &mut dyn Progress) -> io::Result<W> {
+    let mut writer = TarWriter::new(out);
+    for item in items {
+        if item.metadata.is_dir() {
+            writer.append_dir(&item.name, &item.metadata)?;
+        } else if item.metadata.is_symlink() {
+            writer.append_symlink(&item.name, &fs::read_link(&item.path)?, &item.metadata)?;
+        } else {
+            progress.file(&item.path)?;
+            writer.append_file(&item.name, &item.metadata, &mut fs::File::open(&item.path)?, progress)?;
+        }
+    }
+    writer.finish()
+}
+
+/// Remove the packed files, then the directories they were in, deepest
+/// first. Directories still holding something the filter left out stay.
+fn delete_packed(items: &[PackItem]) -> io::Result<()> {
+    for item in items.iter().filter(|item| !item.metadata.is_dir()) {
+        fs::remove_file(&item.path)?;
+    }
+    for item in items.iter().rev().filter(|item| item.metadata.is_dir()) {
+        let _ = fs::remove_dir(&item.path);
+    }
+    Ok(())
+}
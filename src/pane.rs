@@ -1,20 +1,65 @@
 #![forbid(unsafe_code)]
 
 use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::app::ensure_visible;
-use crate::fs_ops::{read_entries, read_panelized};
-use crate::model::{Pane, RefreshMode};
-use crate::vfs::{read_zip_entries, zip_child_prefix, zip_parent_prefix};
+use crate::fs_ops::{arrange_entries, read_panelized};
+use crate::model::{Mount, Pane, RefreshMode};
+use crate::vfs::{is_archive, open_archive, LocalFs, Vfs};
 
 impl Pane {
+    /// The filesystem the pane is browsing: the innermost archive, or the disk.
+    pub fn fs(&self) -> &dyn Vfs {
+        self.mounts.last().map_or(&LocalFs, |mount| mount.fs.as_ref())
+    }
+
+    /// The directory shown, in the terms of `fs()`.
+    pub fn dir(&self) -> &Path {
+        self.mounts.last().map_or(&self.cwd, |mount| &mount.dir)
+    }
+
+    pub fn set_dir(&mut self, dir: PathBuf) {
+        match self.mounts.last_mut() {
+            Some(mount) => mount.dir = dir,
+            None => self.cwd = dir,
+        }
+        self.panelized = None;
+    }
+
+    pub fn in_archive(&self) -> bool {
+        !self.mounts.is_empty()
+    }
+
+    /// How to show `path` from `fs()` to the user, e.g. `/tmp/a.zip/docs/x`.
+    pub fn display_path(&self, path: &Path) -> PathBuf {
+        match self.mounts.last() {
+            Some(mount) => PathBuf::from(mount.fs.name()).join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Open the archive file at `path` of `fs()`. An archive inside an
+    /// archive is opened from an extracted copy.
+    pub fn open_archive_at(&mut self, path: &Path) -> io::Result<Arc<dyn Vfs>> {
+        let name = self.display_path(path).to_string_lossy().to_string();
+        match self.mounts.last_mut() {
+            Some(outer) => {
+                let file = outer.extract(path)?;
+                open_archive(&file, name, outer.extract_dir.clone())
+            }
+            None => open_archive(path, name, None),
+        }
+    }
+
     pub fn refresh(&mut self, mode: RefreshMode, show_hidden: bool) -> io::Result<()> {
         if let Some(panelized) = &self.panelized {
-            self.entries = read_panelized(panelized)?;
-        } else if let Some(vfs) = &self.vfs {
-            self.entries = read_zip_entries(vfs, show_hidden)?;
+            self.entries = read_panelized(self.fs(), panelized)?;
         } else {
-            self.entries = read_entries(&self.cwd, self.sort_mode, self.dirs_first, show_hidden)?;
+            let mut entries = self.fs().list(self.dir())?;
+            arrange_entries(&mut entries, self.sort_mode, self.dirs_first, show_hidden, self.filter.as_ref());
+            self.entries = entries;
         }
         self.selected.retain(|path| self.entries.iter().any(|e| &e.path == path));
 
@@ -60,17 +105,19 @@ impl Pane {
     }
 
     pub fn go_parent(&mut self, show_hidden: bool) -> io::Result<()> {
-        if let Some(vfs) = &mut self.vfs {
-            if let Some(parent) = zip_parent_prefix(&vfs.prefix) {
-                vfs.prefix = parent;
-                return self.refresh(RefreshMode::Reset, show_hidden);
-            }
-            self.vfs = None;
-        }
         if self.panelized.is_some() {
             self.panelized = None;
             return self.refresh(RefreshMode::Reset, show_hidden);
         }
+        if let Some(mount) = self.mounts.last_mut() {
+            match mount.fs.parent(&mount.dir) {
+                Some(parent) => mount.dir = parent,
+                None => {
+                    self.mounts.pop();
+                }
+            }
+            return self.refresh(RefreshMode::Reset, show_hidden);
+        }
         if let Some(parent) = self.cwd.parent() {
             self.cwd = parent.to_path_buf();
             self.refresh(RefreshMode::Reset, show_hidden)?;
@@ -84,23 +131,15 @@ impl Pane {
         };
         let entry_path = entry.path.clone();
         let entry_name = entry.name.clone();
-        let is_dir = entry.is_dir;
-        if is_dir {
-            if let Some(vfs) = &mut self.vfs {
-                vfs.prefix = zip_child_prefix(&vfs.prefix, &entry_path);
-                self.refresh(RefreshMode::Reset, show_hidden)?;
-            } else {
-                self.cwd = entry_path;
-                self.panelized = None;
-                self.refresh(RefreshMode::Reset, show_hidden)?;
-            }
+        if entry.is_dir {
+            self.set_dir(entry_path);
+            self.refresh(RefreshMode::Reset, show_hidden)?;
             return Ok(true);
         }
-        if entry_name.to_lowercase().ends_with(".zip") && self.vfs.is_none() {
-            self.vfs = Some(crate::model::VfsState {
-                zip_path: entry_path,
-                prefix: String::new(),
-            });
+        if is_archive(&entry_name) {
+            let archive = self.open_archive_at(&entry_path)?;
+            self.mounts.push(Mount::new(archive));
+            self.panelized = None;
             self.refresh(RefreshMode::Reset, show_hidden)?;
             return Ok(true);
         }
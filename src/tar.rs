@@ -0,0 +1,679 @@
+#![forbid(unsafe_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::fs_ops::{available_space, format_size, Progress};
+use crate::model::Entry;
+use crate::vfs::{read_only, ExtractDir, Vfs, VfsWrite};
+
+const BLOCK: usize = 512;
+/// How many symlinks in a row `open_read` follows before giving up.
+const MAX_LINK_HOPS: usize = 8;
+/// File name of the decoded stream inside a compressed archive's temp dir.
+const SPILL_NAME: &str = "archive.tar";
+
+/// Names of tar archives, plain or compressed.
+pub const TAR_SUFFIXES: [&str; 10] =
+    [".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tbz", ".tar.xz", ".txz", ".tar.zst", ".tzst"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Tell the format from the first bytes of the file, whatever its name.
+    fn detect(file: &Path) -> io::Result<Self> {
+        let mut magic = [0u8; 6];
+        let read = fs::File::open(file)?.read(&mut magic)?;
+        let magic = &magic[..read];
+        Ok(if magic.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if magic.starts_with(b"BZh") {
+            Self::Bzip2
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+            Self::Xz
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else {
+            Self::None
+        })
+    }
+}
+
+/// The archive's contents as one byte stream, read front to back when the
+/// archive is indexed and when a compressed one is decoded to a temp file.
+/// Only a plain tar can skip ahead by seeking.
+enum TarStream {
+    Plain(BufReader<fs::File>),
+    Compressed(Box<dyn Read + Send>),
+}
+
+impl TarStream {
+    fn open(file: &Path, compression: Compression) -> io::Result<Self> {
+        let reader = BufReader::new(fs::File::open(file)?);
+        Ok(match compression {
+            Compression::None => Self::Plain(reader),
+            Compression::Gzip => Self::Compressed(Box::new(flate2::read::MultiGzDecoder::new(reader))),
+            Compression::Bzip2 => Self::Compressed(Box::new(bzip2::read::MultiBzDecoder::new(reader))),
+            Compression::Xz => Self::Compressed(Box::new(xz2::read::XzDecoder::new_multi_decoder(reader))),
+            Compression::Zstd => Self::Compressed(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        })
+    }
+
+    fn skip(&mut self, count: u64) -> io::Result<()> {
+        match self {
+            Self::Plain(reader) => reader.seek_relative(count as i64),
+            Self::Compressed(reader) => {
+                if io::copy(&mut reader.take(count), &mut io::sink())? < count {
+                    return Err(truncated());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Fill `block`, or return false at a clean end of the stream.
+    fn read_block(&mut self, block: &mut [u8; BLOCK]) -> io::Result<bool> {
+        let mut filled = 0;
+        while filled < BLOCK {
+            match self.read(&mut block[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(truncated()),
+                Ok(count) => filled += count,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Read for TarStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(reader) => reader.read(buf),
+            Self::Compressed(reader) => reader.read(buf),
+        }
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "tar archive is truncated")
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    File,
+    Dir,
+    Symlink,
+    HardLink,
+    /// Devices and fifos: listed, but without contents.
+    Special,
+}
+
+/// One member of the archive, found while indexing.
+#[derive(Debug, Clone)]
+struct Member {
+    /// Normalized path without `./` or a trailing slash.
+    path: String,
+    kind: Kind,
+    size: u64,
+    mode: u32,
+    mtime: Option<SystemTime>,
+    link: Option<String>,
+    /// Where the contents start in the uncompressed stream.
+    offset: u64,
+}
+
+/// Header fields carried over to the next member by pax and GNU long-name
+/// records.
+#[derive(Default)]
+struct Overrides {
+    path: Option<String>,
+    link: Option<String>,
+    size: Option<u64>,
+    mtime: Option<SystemTime>,
+}
+
+/// A tar archive, optionally compressed with gzip, bzip2, xz or zstd. The
+/// whole stream is read once when the archive is opened to find every
+/// member and where its contents start. A compressed stream is decoded to a
+/// temp file on the first read, so reads after that seek instead of decoding
+/// everything before the member again.
+#[derive(Debug)]
+pub struct TarFs {
+    path: PathBuf,
+    name: String,
+    compression: Compression,
+    members: Vec<Member>,
+    /// Index into `members` by path; a later member with the same path wins,
+    /// as it would when extracting.
+    by_path: HashMap<String, usize>,
+    /// Holds the decoded stream of a compressed archive once something was read.
+    spill: Mutex<Option<ExtractDir>>,
+    _keep: Option<Arc<ExtractDir>>,
+}
+
+impl TarFs {
+    pub fn open(file: &Path, name: String, keep: Option<Arc<ExtractDir>>) -> io::Result<Self> {
+        let compression = Compression::detect(file)?;
+        let members = index(&mut TarStream::open(file, compression)?)?;
+        let by_path = members.iter().enumerate().map(|(idx, member)| (member.path.clone(), idx)).collect();
+        let spill = Mutex::new(None);
+        Ok(Self { path: file.to_path_buf(), name, compression, members, by_path, spill, _keep: keep })
+    }
+
+    /// The uncompressed tar, decoding it to a private temp dir the first time.
+    fn spilled(&self) -> io::Result<PathBuf> {
+        let mut spill = self.spill.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(dir) = spill.as_ref() {
+            return Ok(dir.path.join(SPILL_NAME));
+        }
+        let needed = self.members.iter().map(|member| member.offset + padded(member.size)).max().unwrap_or(0);
+        let free = available_space(&std::env::temp_dir())?;
+        if needed > free {
+            let (needed, free) = (format_size(needed), format_size(free));
+            let message = format!("not enough temp space to unpack {}: need {needed}, free {free}", self.name);
+            return Err(io::Error::new(io::ErrorKind::StorageFull, message));
+        }
+        let dir = ExtractDir::create()?;
+        let path = dir.path.join(SPILL_NAME);
+        let mut output = fs::File::create(&path)?;
+        io::copy(&mut TarStream::open(&self.path, self.compression)?, &mut output)?;
+        *spill = Some(dir);
+        Ok(path)
+    }
+
+    fn member(&self, path: &str) -> Option<&Member> {
+        self.by_path.get(path).map(|&idx| &self.members[idx])
+    }
+
+    /// Follow symlinks and hard links to the member holding the contents.
+    fn resolve(&self, path: &str) -> io::Result<&Member> {
+        let mut path = path.to_string();
+        for _ in 0..MAX_LINK_HOPS {
+            let Some(member) = self.member(&path) else {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("{path}: not in archive")));
+            };
+            let target = member.link.as_deref().unwrap_or_default();
+            path = match member.kind {
+                Kind::HardLink => normalize(target),
+                Kind::Symlink => {
+                    let parent = Path::new(&member.path).parent().unwrap_or(Path::new(""));
+                    match resolve_relative(parent, target) {
+                        Some(path) => path,
+                        None => {
+                            let message = format!("{}: link points outside the archive", member.path);
+                            return Err(io::Error::new(io::ErrorKind::NotFound, message));
+                        }
+                    }
+                }
+                Kind::Dir => return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{path}: is a directory"))),
+                Kind::File | Kind::Special => return Ok(member),
+            };
+        }
+        Err(io::Error::other(format!("{path}: too many levels of links")))
+    }
+
+    fn entry(&self, member: &Member) -> Entry {
+        let name = member.path.rsplit('/').next().unwrap_or(&member.path).to_string();
+        // A hard link is another name for a file, so it shows as one.
+        let size = match member.kind {
+            Kind::File => member.size,
+            Kind::HardLink => self.resolve(&member.path).map_or(0, |target| target.size),
+            _ => 0,
+        };
+        Entry {
+            is_system: name.starts_with('.'),
+            name,
+            path: PathBuf::from(&member.path),
+            is_dir: member.kind == Kind::Dir,
+            size,
+            modified: member.mtime,
+            mode: Some(member.mode),
+            link_target: member.link.as_ref().filter(|_| member.kind == Kind::Symlink).map(PathBuf::from),
+        }
+    }
+}
+
+fn dir_entry(path: &str) -> Entry {
+    let name = path.rsplit('/').next().unwrap_or(path).to_string();
+    Entry {
+        is_system: name.starts_with('.'),
+        name,
+        path: PathBuf::from(path),
+        is_dir: true,
+        size: 0,
+        modified: None,
+        mode: None,
+        link_target: None,
+    }
+}
+
+impl Vfs for TarFs {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<Entry>> {
+        let dir = dir.to_string_lossy();
+        let prefix = if dir.is_empty() { String::new() } else { format!("{dir}/") };
+        let mut entries = Vec::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        for (idx, member) in self.members.iter().enumerate() {
+            let Some(rest) = member.path.strip_prefix(prefix.as_str()) else { continue };
+            match rest.split_once('/') {
+                // A directory known only from the paths below it.
+                Some((name, _)) if !self.by_path.contains_key(&format!("{prefix}{name}")) && seen.insert(name) => {
+                    entries.push(dir_entry(&format!("{prefix}{name}")));
+                }
+                None if !rest.is_empty() && self.by_path.get(&member.path) == Some(&idx) => {
+                    entries.push(self.entry(member));
+                }
+                _ => {}
+            }
+        }
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Entry> {
+        let path = path.to_string_lossy();
+        if path.is_empty() {
+            return Ok(dir_entry(""));
+        }
+        if let Some(member) = self.member(&path) {
+            return Ok(self.entry(member));
+        }
+        let prefix = format!("{path}/");
+        if self.members.iter().any(|member| member.path.starts_with(&prefix)) {
+            return Ok(dir_entry(&path));
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("{path}: not in archive")))
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn reads_quickly(&self) -> bool {
+        self.compression == Compression::None || self.spill.try_lock().is_ok_and(|spill| spill.is_some())
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        let member = self.resolve(&path.to_string_lossy())?;
+        if member.kind == Kind::Special {
+            return Ok(Box::new(io::empty()));
+        }
+        let mut reader = match self.compression {
+            Compression::None => fs::File::open(&self.path)?,
+            _ => fs::File::open(self.spilled()?)?,
+        };
+        reader.seek(SeekFrom::Start(member.offset))?;
+        Ok(Box::new(BufReader::new(reader).take(member.size)))
+    }
+
+    fn open_write(&self, _path: &Path) -> io::Result<Box<dyn VfsWrite>> {
+        Err(read_only())
+    }
+
+    fn mkdir(&self, _path: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove(&self, _path: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn parent(&self, dir: &Path) -> Option<PathBuf> {
+        if dir.as_os_str().is_empty() {
+            return None;
+        }
+        Some(dir.parent().map(Path::to_path_buf).unwrap_or_default())
+    }
+}
+
+/// Read every header of the stream, skipping over the contents.
+fn index(stream: &mut TarStream) -> io::Result<Vec<Member>> {
+    let mut members = Vec::new();
+    let mut overrides = Overrides::default();
+    let mut global = Overrides::default();
+    let mut block = [0u8; BLOCK];
+    let mut offset = 0u64;
+    loop {
+        match stream.read_block(&mut block) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) if members.is_empty() && err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(corrupt("not a tar archive"));
+            }
+            Err(err) => return Err(err),
+        }
+        offset += BLOCK as u64;
+        if block.iter().all(|&byte| byte == 0) {
+            // End-of-archive marker.
+            break;
+        }
+        if !checksum_ok(&block) {
+            return Err(corrupt(if members.is_empty() { "not a tar archive" } else { "corrupt tar header" }));
+        }
+        let typeflag = block[156];
+        match typeflag {
+            b'x' | b'g' | b'L' | b'K' => {
+                let size = number(&block[124..136])?;
+                let data = read_data(stream, size)?;
+                let padded = padded(size);
+                stream.skip(padded - size)?;
+                offset += padded;
+                match typeflag {
+                    b'x' => parse_pax(&data, &mut overrides),
+                    b'g' => parse_pax(&data, &mut global),
+                    b'L' => overrides.path = Some(c_string(&data)),
+                    _ => overrides.link = Some(c_string(&data)),
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let size = match overrides.size.take().or(global.size) {
+            Some(size) => size,
+            None => number(&block[124..136])?,
+        };
+        let path = overrides.path.take().or_else(|| global.path.clone()).unwrap_or_else(|| header_path(&block));
+        let link = overrides.link.take().or_else(|| global.link.clone()).unwrap_or_else(|| c_string(&block[157..257]));
+        let mtime = overrides.mtime.take().or(global.mtime).or_else(|| {
+            let secs = number(&block[136..148]).ok()?;
+            SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+        });
+        let kind = match typeflag {
+            b'0' | 0 | b'7' | b'S' => Kind::File,
+            b'1' => Kind::HardLink,
+            b'2' => Kind::Symlink,
+            b'5' | b'D' => Kind::Dir,
+            _ => Kind::Special,
+        };
+        // Old tars mark directories only with a trailing slash.
+        let kind = if kind == Kind::File && path.ends_with('/') { Kind::Dir } else { kind };
+        let path = normalize(&path);
+        if !path.is_empty() {
+            members.push(Member {
+                path,
+                kind,
+                size,
+                mode: number(&block[100..108]).unwrap_or(0) as u32 & 0o7777,
+                mtime,
+                link: matches!(kind, Kind::Symlink | Kind::HardLink).then_some(link),
+                offset,
+            });
+        }
+        // Links and directories may still carry a size, but no contents.
+        if matches!(typeflag, b'0' | 0 | b'7' | b'S') {
+            stream.skip(padded(size))?;
+            offset += padded(size);
+        }
+    }
+    // Seeking a plain tar past its end does not fail by itself.
+    let end = match stream {
+        TarStream::Plain(reader) => reader.get_ref().metadata()?.len(),
+        TarStream::Compressed(_) => offset,
+    };
+    if offset > end {
+        return Err(truncated());
+    }
+    Ok(members)
+}
+
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK as u64) * BLOCK as u64
+}
+
+fn read_data(stream: &mut TarStream, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if stream.take(size).read_to_end(&mut data)? < size as usize {
+        return Err(truncated());
+    }
+    Ok(data)
+}
+
+/// The header checksum counts its own field as eight spaces.
+fn checksum_ok(block: &[u8; BLOCK]) -> bool {
+    let Ok(stored) = number(&block[148..156]) else { return false };
+    let sum: u64 = block
+        .iter()
+        .enumerate()
+        .map(|(idx, &byte)| if (148..156).contains(&idx) { b' ' as u64 } else { byte as u64 })
+        .sum();
+    sum == stored
+}
+
+/// A numeric header field: octal text, or big-endian binary when the top bit
+/// of the first byte is set (GNU, for values that do not fit).
+fn number(field: &[u8]) -> io::Result<u64> {
+    if field.first().is_some_and(|&byte| byte & 0x80 != 0) {
+        let value = field[1..].iter().fold(0u64, |value, &byte| value << 8 | byte as u64);
+        return Ok(value);
+    }
+    let text = c_string(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| corrupt("bad number in tar header"))
+}
+
+fn c_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+/// The name field, joined to the ustar prefix field when there is one.
+fn header_path(block: &[u8; BLOCK]) -> String {
+    let name = c_string(&block[0..100]);
+    // GNU tar writes "ustar  " and uses the prefix bytes for other things.
+    if &block[257..263] != b"ustar\0" {
+        return name;
+    }
+    let prefix = c_string(&block[345..500]);
+    if prefix.is_empty() { name } else { format!("{prefix}/{name}") }
+}
+
+/// Pax records are `"<length> <key>=<value>\n"`.
+fn parse_pax(data: &[u8], overrides: &mut Overrides) {
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&byte| byte == b' ') {
+        let Some(len) = std::str::from_utf8(&rest[..space]).ok().and_then(|len| len.parse::<usize>().ok()) else {
+            return;
+        };
+        if len <= space || len > rest.len() {
+            return;
+        }
+        let record = String::from_utf8_lossy(&rest[space + 1..len]);
+        let record = record.strip_suffix('\n').unwrap_or(&record);
+        if let Some((key, value)) = record.split_once('=') {
+            match key {
+                "path" => overrides.path = Some(value.to_string()),
+                "linkpath" => overrides.link = Some(value.to_string()),
+                "size" => overrides.size = value.parse().ok(),
+                "mtime" => {
+                    overrides.mtime = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|secs| *secs >= 0.0)
+                        .and_then(|secs| SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs_f64(secs)));
+                }
+                _ => {}
+            }
+        }
+        rest = &rest[len..];
+    }
+}
+
+/// Drop leading `/` and `./` and any trailing slash. `..` is kept so
+/// extraction can refuse it.
+fn normalize(path: &str) -> String {
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty() && *part != ".").collect();
+    parts.join("/")
+}
+
+/// Where a symlink in `dir` pointing at `target` leads, if it stays inside
+/// the archive.
+fn resolve_relative(dir: &Path, target: &str) -> Option<String> {
+    if target.starts_with('/') {
+        return None;
+    }
+    let mut parts: Vec<String> = Vec::new();
+    for part in dir.join(target).components() {
+        match part {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            _ => {}
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Writes a tar stream in the format GNU tar uses by default: ustar headers
+/// with `././@LongLink` members in front of names that do not fit.
+pub struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn append_dir(&mut self, name: &str, metadata: &fs::Metadata) -> io::Result<()> {
+        self.header(&format!("{name}/"), b'5', 0, metadata, "")
+    }
+
+    pub fn append_symlink(&mut self, name: &str, target: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+        self.header(name, b'2', 0, metadata, &target.to_string_lossy())
+    }
+
+    /// Store `metadata.len()` bytes from `data`, reporting them to `progress`.
+    /// A file that shrank since `metadata` was taken is an error, since the
+    /// header already promised its size.
+    pub fn append_file(
+        &mut self,
+        name: &str,
+        metadata: &fs::Metadata,
+        data: &mut dyn Read,
+        progress: &mut dyn Progress,
+    ) -> io::Result<()> {
+        let size = metadata.len();
+        self.header(name, b'0', size, metadata, "")?;
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut left = size;
+        while left > 0 {
+            let want = left.min(buf.len() as u64) as usize;
+            let read = data.read(&mut buf[..want])?;
+            if read == 0 {
+                let message = format!("{name}: file changed while packing");
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message));
+            }
+            self.out.write_all(&buf[..read])?;
+            progress.bytes(read as u64)?;
+            left -= read as u64;
+        }
+        self.pad(size)
+    }
+
+    /// End the archive with its two zero blocks and hand back the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0u8; BLOCK * 2])?;
+        Ok(self.out)
+    }
+
+    fn header(&mut self, name: &str, kind: u8, size: u64, metadata: &fs::Metadata, link: &str) -> io::Result<()> {
+        if name.len() > 100 {
+            self.long_name(b'L', name, metadata)?;
+        }
+        if link.len() > 100 {
+            self.long_name(b'K', link, metadata)?;
+        }
+        let mut block = [0u8; BLOCK];
+        put_bytes(&mut block[0..100], name.as_bytes());
+        put_number(&mut block[100..108], (metadata.mode() & 0o7777) as u64);
+        put_number(&mut block[108..116], metadata.uid() as u64);
+        put_number(&mut block[116..124], metadata.gid() as u64);
+        put_number(&mut block[124..136], size);
+        put_number(&mut block[136..148], metadata.mtime().max(0) as u64);
+        block[156] = kind;
+        put_bytes(&mut block[157..257], link.as_bytes());
+        block[257..265].copy_from_slice(b"ustar  \0");
+        self.write_header(block)
+    }
+
+    /// A GNU member carrying a name or link target too long for its field.
+    fn long_name(&mut self, kind: u8, name: &str, metadata: &fs::Metadata) -> io::Result<()> {
+        let mut data = name.as_bytes().to_vec();
+        data.push(0);
+        let mut block = [0u8; BLOCK];
+        put_bytes(&mut block[0..100], b"././@LongLink");
+        put_number(&mut block[100..108], 0o644);
+        put_number(&mut block[124..136], data.len() as u64);
+        put_number(&mut block[136..148], metadata.mtime().max(0) as u64);
+        block[156] = kind;
+        block[257..265].copy_from_slice(b"ustar  \0");
+        self.write_header(block)?;
+        self.out.write_all(&data)?;
+        self.pad(data.len() as u64)
+    }
+
+    fn write_header(&mut self, mut block: [u8; BLOCK]) -> io::Result<()> {
+        block[148..156].fill(b' ');
+        let sum: u64 = block.iter().map(|&byte| byte as u64).sum();
+        block[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+        self.out.write_all(&block)
+    }
+
+    fn pad(&mut self, size: u64) -> io::Result<()> {
+        self.out.write_all(&[0u8; BLOCK][..(padded(size) - size) as usize])
+    }
+}
+
+/// Copy as much of `value` as fits; names that do not fit already went out
+/// in a long-name member.
+fn put_bytes(field: &mut [u8], value: &[u8]) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value[..len]);
+}
+
+/// The other direction of `number`: octal text when it fits, else binary.
+fn put_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{value:0digits$o}");
+    if text.len() <= digits {
+        field[..digits].copy_from_slice(text.as_bytes());
+        return;
+    }
+    field.fill(0);
+    for (idx, byte) in value.to_be_bytes().iter().rev().enumerate() {
+        if let Some(slot) = field.len().checked_sub(idx + 1) {
+            field[slot] = *byte;
+        }
+    }
+    field[0] |= 0x80;
+}
@@ -1,5 +1,7 @@
 #![forbid(unsafe_code)]
 
+use std::io::{self, Read};
+
 use ftui::layout::{Constraint, Flex};
 use ftui::render::cell::PackedRgba;
 use ftui::style::Style;
@@ -13,9 +15,17 @@ use ftui::widgets::{StatefulWidget, Widget};
 use ftui::Frame;
 
 use crate::app::ThemeColors;
-use crate::fs_ops::{format_time, sort_indicator, sort_label};
+use crate::encoding::Encoding;
+use crate::filter::FileFilter;
+use crate::fs_ops::{format_mode, format_size, format_time, sort_indicator, sort_label};
+use crate::jobs::{format_duration, ConflictInfo, Job, JobManager};
 use crate::menu::{menu_items, MENU_TITLES};
-use crate::model::{ActivePane, CopyDialogFocus, CopyDialogState, LayoutCache, MenuAction, Modal, Pane, PanelMode, SortMode, Viewer};
+use crate::model::{
+    ActivePane, Compare, CompareContent, CopyDialogFocus, CopyDialogState, Editor, FilterTarget, LayoutCache, MenuAction, Modal,
+    PackDialogFocus, PackDialogState, Pane, PanelMode, PendingConfirm, SearchHit, SortMode, ViewMode, Viewer, ViewerSearch,
+};
+use crate::diff::DiffKind;
+use crate::viewer::{expand_tabs, hex_columns, hex_row, looks_binary, HEX_ROW};
 
 pub const MENU_HEIGHT: u16 = 1;
 pub const STATUS_HEIGHT: u16 = 1;
@@ -24,19 +34,318 @@ pub const KEYBAR_HEIGHT: u16 = 1;
 pub const HEADER_HEIGHT: u16 = 1;
 
 pub fn render_viewer(viewer: &Viewer, frame: &mut Frame, theme: ThemeColors) {
-    let area = ftui::core::geometry::Rect::new(0, 0, frame.width(), frame.height());
+    let full = ftui::core::geometry::Rect::new(0, 0, frame.width(), frame.height());
+    let area = ftui::core::geometry::Rect::new(0, 0, full.width, full.height.saturating_sub(STATUS_HEIGHT));
+    let style = Style::new().fg(theme.panel_fg).bg(theme.panel_bg);
+    let follow = if viewer.follow && viewer.at_end() {
+        " [follow]"
+    } else if viewer.follow {
+        " [follow: paused]"
+    } else {
+        ""
+    };
+    let title = format!("View [{}]{}", viewer.encoding.name(), follow);
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
+        .borders(Borders::ALL)
+        .style(style)
+        .title(&title);
+    let inner = block.inner(area);
+    block.render(area, frame);
+    viewer.view_height.set(inner.height as usize);
+    viewer.view_width.set(inner.width as usize);
+    match viewer.mode {
+        ViewMode::Text => render_viewer_text(viewer, frame, inner, theme),
+        ViewMode::Hex => render_viewer_hex(viewer, frame, inner, theme),
+    }
+
+    let name = viewer.path.display().to_string();
+    let mode = match viewer.mode {
+        ViewMode::Text => "Text",
+        ViewMode::Hex => "Hex",
+    };
+    let mut status = format!(" {}  [{}{}]", name, mode, if viewer.wrap { " wrap" } else { "" });
+    match viewer.mode {
+        ViewMode::Text => {
+            if let Some(line) = viewer.known_line_of(viewer.top) {
+                status.push_str(&format!("  Line {}", line + 1));
+            }
+            let column = if viewer.wrap { viewer.top_row * inner.width as usize } else { viewer.left };
+            status.push_str(&format!("  Col {}", column + 1));
+        }
+        ViewMode::Hex => status.push_str(&format!("  Offset {:08X}", viewer.top)),
+    }
+    status.push_str(&format!("  {}%", viewer.percent()));
+    if let Some(message) = &viewer.message {
+        status.push_str(&format!("  {}", message));
+    }
+    let status_para = Paragraph::new(Text::from(status)).style(Style::new().fg(theme.status_fg).bg(theme.status_bg));
+    status_para.render(
+        ftui::core::geometry::Rect::new(0, full.height.saturating_sub(STATUS_HEIGHT), full.width, STATUS_HEIGHT),
+        frame,
+    );
+}
+
+pub fn render_editor(editor: &Editor, frame: &mut Frame, theme: ThemeColors) {
+    let full = ftui::core::geometry::Rect::new(0, 0, frame.width(), frame.height());
+    let area = ftui::core::geometry::Rect::new(0, 0, full.width, full.height.saturating_sub(STATUS_HEIGHT));
+    let style = Style::new().fg(theme.panel_fg).bg(theme.panel_bg);
+    let selected_style = Style::new().fg(theme.selection_fg).bg(theme.selection_bg);
+    let title = format!("Edit {}{}", editor.path.display(), if editor.modified { " *" } else { "" });
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
+        .borders(Borders::ALL)
+        .style(style)
+        .title(&title);
+    let inner = block.inner(area);
+    block.render(area, frame);
+    editor.view_height.set(inner.height as usize);
+    editor.view_width.set(inner.width as usize);
+    for (row, editor_row) in editor.rows().into_iter().enumerate() {
+        let y = inner.y + row as u16;
+        let line_para = Paragraph::new(Text::from(editor_row.text.as_str())).wrap(WrapMode::None).style(style);
+        line_para.render(ftui::core::geometry::Rect::new(inner.x, y, inner.width, 1), frame);
+        if let Some((from, to)) = editor_row.selected {
+            // Pad so a selected line break shows past the end of the text.
+            let text: String = editor_row.text.chars().chain(std::iter::repeat(' ')).skip(from).take(to - from).collect();
+            let para = Paragraph::new(Text::from(text)).style(selected_style);
+            para.render(ftui::core::geometry::Rect::new(inner.x + from as u16, y, (to - from) as u16, 1), frame);
+        }
+    }
+    let row = editor.cursor.line.saturating_sub(editor.top);
+    let column = editor.cursor_column().saturating_sub(editor.left);
+    if row < inner.height as usize && column < inner.width as usize {
+        frame.set_cursor(Some((inner.x + column as u16, inner.y + row as u16)));
+    }
+
+    let mut status = format!(
+        " Line {}  Col {}  [{}]",
+        editor.cursor.line + 1,
+        editor.cursor.col + 1,
+        if editor.overwrite { "Ovr" } else { "Ins" }
+    );
+    if let Some(message) = &editor.message {
+        status.push_str(&format!("  {}", message));
+    }
+    let status_para = Paragraph::new(Text::from(status)).style(Style::new().fg(theme.status_fg).bg(theme.status_bg));
+    status_para.render(
+        ftui::core::geometry::Rect::new(0, full.height.saturating_sub(STATUS_HEIGHT), full.width, STATUS_HEIGHT),
+        frame,
+    );
+}
+
+pub fn render_compare(compare: &Compare, frame: &mut Frame, theme: ThemeColors) {
+    let full = ftui::core::geometry::Rect::new(0, 0, frame.width(), frame.height());
+    let area = ftui::core::geometry::Rect::new(0, 0, full.width, full.height.saturating_sub(STATUS_HEIGHT));
+    let style = Style::new().fg(theme.panel_fg).bg(theme.panel_bg);
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
+        .borders(Borders::ALL)
+        .style(style)
+        .title("Compare");
+    let inner = block.inner(area);
+    block.render(area, frame);
+    compare.view_height.set(inner.height as usize);
+    compare.view_width.set(inner.width as usize);
+    let right_name = compare.right_path.display().to_string();
+    match &compare.content {
+        CompareContent::Text { left, right, rows, .. } => {
+            let half = inner.width.saturating_sub(1) / 2;
+            let separator_style = Style::new().fg(theme.panel_border_active).bg(theme.panel_bg);
+            let first = compare.top as usize;
+            for (row, diff_row) in rows.iter().skip(first).take(inner.height as usize).enumerate() {
+                let y = inner.y + row as u16;
+                let sides = [
+                    (inner.x, half, diff_row.left.map(|idx| left[idx].as_str())),
+                    (inner.x + half + 1, inner.width - half - 1, diff_row.right.map(|idx| right[idx].as_str())),
+                ];
+                for (x, width, line) in sides {
+                    let line_style = match (diff_row.kind, line) {
+                        (DiffKind::Same, _) | (_, None) => style,
+                        (DiffKind::Changed, _) => Style::new().fg(theme.diff_fg).bg(theme.diff_changed_bg),
+                        (DiffKind::Deleted, _) => Style::new().fg(theme.diff_fg).bg(theme.diff_removed_bg),
+                        (DiffKind::Inserted, _) => Style::new().fg(theme.diff_fg).bg(theme.diff_added_bg),
+                    };
+                    // Padded so the highlight covers the whole column.
+                    let text: String = expand_tabs(line.unwrap_or(""))
+                        .chars()
+                        .chain(std::iter::repeat(' '))
+                        .skip(compare.left)
+                        .take(width as usize)
+                        .collect();
+                    let para = Paragraph::new(Text::from(text)).wrap(WrapMode::None).style(line_style);
+                    para.render(ftui::core::geometry::Rect::new(x, y, width, 1), frame);
+                }
+                let bar = Paragraph::new(Text::from("│")).style(separator_style);
+                bar.render(ftui::core::geometry::Rect::new(inner.x + half, y, 1, 1), frame);
+            }
+        }
+        CompareContent::Binary { left, right, .. } => {
+            let page = compare.page() as u16;
+            let divider: String = format!("── {} ", right_name).chars().chain(std::iter::repeat('─')).take(inner.width as usize).collect();
+            let divider_para = Paragraph::new(Text::from(divider)).style(Style::new().fg(theme.panel_border_active).bg(theme.panel_bg));
+            divider_para.render(ftui::core::geometry::Rect::new(inner.x, inner.y + page, inner.width, 1), frame);
+            let changed_style = Style::new().fg(theme.diff_fg).bg(theme.diff_changed_bg);
+            let len = (page as usize) * HEX_ROW;
+            let (left_bytes, right_bytes) = (left.read_at(compare.top, len), right.read_at(compare.top, len));
+            for (y, bytes, other) in [(inner.y, &left_bytes, &right_bytes), (inner.y + page + 1, &right_bytes, &left_bytes)] {
+                for (row, chunk) in bytes.chunks(HEX_ROW).enumerate() {
+                    let row_y = y + row as u16;
+                    let offset = compare.top + (row * HEX_ROW) as u64;
+                    let para = Paragraph::new(Text::from(hex_row(offset, chunk))).wrap(WrapMode::None).style(style);
+                    para.render(ftui::core::geometry::Rect::new(inner.x, row_y, inner.width, 1), frame);
+                    for (idx, byte) in chunk.iter().enumerate() {
+                        if other.get(row * HEX_ROW + idx) == Some(byte) {
+                            continue;
+                        }
+                        let (hex, ascii) = hex_columns(idx);
+                        let shown = if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' };
+                        for (column, text) in [(hex, format!("{:02X}", byte)), (ascii, shown.to_string())] {
+                            if column + text.len() <= inner.width as usize {
+                                let mark = Paragraph::new(Text::from(text.clone())).style(changed_style);
+                                let mark_area = ftui::core::geometry::Rect::new(inner.x + column as u16, row_y, text.len() as u16, 1);
+                                mark.render(mark_area, frame);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut status = format!(" {}  |  {}", compare.left_path.display(), right_name);
+    match &compare.content {
+        CompareContent::Text { .. } => {
+            if let Some((current, total)) = compare.position() {
+                status.push_str(&format!("  Difference {}/{}", current, total));
+            }
+            status.push_str(&format!("  Line {}", compare.top + 1));
+        }
+        CompareContent::Binary { .. } => status.push_str(&format!("  Offset {:08X}", compare.top)),
+    }
+    if let Some(message) = &compare.message {
+        status.push_str(&format!("  {}", message));
+    }
+    let status_para = Paragraph::new(Text::from(status)).style(Style::new().fg(theme.status_fg).bg(theme.status_bg));
+    status_para.render(
+        ftui::core::geometry::Rect::new(0, full.height.saturating_sub(STATUS_HEIGHT), full.width, STATUS_HEIGHT),
+        frame,
+    );
+}
+
+fn render_viewer_text(viewer: &Viewer, frame: &mut Frame, inner: ftui::core::geometry::Rect, theme: ThemeColors) {
+    let style = Style::new().fg(theme.panel_fg).bg(theme.panel_bg);
+    let hit_style = Style::new().fg(theme.selection_fg).bg(theme.selection_bg);
+    let match_style = Style::new().fg(theme.match_fg).bg(theme.match_bg);
+    for (row, text_row) in viewer.text_rows().into_iter().enumerate() {
+        let y = inner.y + row as u16;
+        let line_para = Paragraph::new(Text::from(text_row.text.as_str())).wrap(WrapMode::None).style(style);
+        line_para.render(ftui::core::geometry::Rect::new(inner.x, y, inner.width, 1), frame);
+        for (from, to, is_hit) in text_row.marks {
+            let text: String = text_row.text.chars().skip(from).take(to - from).collect();
+            let para = Paragraph::new(Text::from(text)).style(if is_hit { hit_style } else { match_style });
+            para.render(ftui::core::geometry::Rect::new(inner.x + from as u16, y, (to - from) as u16, 1), frame);
+        }
+    }
+}
+
+fn render_viewer_hex(viewer: &Viewer, frame: &mut Frame, inner: ftui::core::geometry::Rect, theme: ThemeColors) {
     let style = Style::new().fg(theme.panel_fg).bg(theme.panel_bg);
-    let paragraph = Paragraph::new(Text::from(viewer.lines.join("\n")))
-        .wrap(WrapMode::None)
-        .scroll((viewer.scroll as u16, 0))
+    let hit_style = Style::new().fg(theme.selection_fg).bg(theme.selection_bg);
+    let match_style = Style::new().fg(theme.match_fg).bg(theme.match_bg);
+    let first = viewer.top;
+    let last = (first + (inner.height as usize * HEX_ROW) as u64).min(viewer.len);
+    // Start early enough to catch a match running into the first row.
+    let pattern = viewer.finder.as_ref().map_or(0, |finder| finder.byte_len());
+    let from = first.saturating_sub(pattern.saturating_sub(1) as u64);
+    let data = viewer.source.read_at(from, (last - from) as usize + pattern.saturating_sub(1));
+    let byte_at = |offset: u64| data[(offset - from) as usize];
+    for (row, row_start) in (first..last).step_by(HEX_ROW).enumerate() {
+        let y = inner.y + row as u16;
+        let begin = (row_start - from) as usize;
+        let line = hex_row(row_start, &data[begin.min(data.len())..(begin + HEX_ROW).min((last - from) as usize)]);
+        let para = Paragraph::new(Text::from(line)).wrap(WrapMode::None).style(style);
+        para.render(ftui::core::geometry::Rect::new(inner.x, y, inner.width, 1), frame);
+    }
+
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    if let Some(finder) = &viewer.finder {
+        ranges = finder
+            .byte_matches(&data)
+            .into_iter()
+            .map(|(start, end)| (from + start as u64, from + end as u64))
+            .filter(|&(start, end)| end > first && start < last)
+            .collect();
+    }
+    if let Some(SearchHit::Bytes { start, end }) = viewer.hit {
+        ranges.retain(|&range| range != (start, end));
+        ranges.push((start, end));
+    }
+    for (start, end) in ranges {
+        let is_hit = viewer.hit == Some(SearchHit::Bytes { start, end });
+        let cell_style = if is_hit { hit_style } else { match_style };
+        for offset in start.max(first)..end.min(last) {
+            let y = inner.y + ((offset - first) / HEX_ROW as u64) as u16;
+            let (hex_col, ascii_col) = hex_columns((offset % HEX_ROW as u64) as usize);
+            let byte = byte_at(offset);
+            let ascii = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+            for (col, text) in [(hex_col, format!("{:02X}", byte)), (ascii_col, ascii.to_string())] {
+                if col + text.len() > inner.width as usize {
+                    continue;
+                }
+                let para = Paragraph::new(Text::from(text.as_str())).style(cell_style);
+                para.render(ftui::core::geometry::Rect::new(inner.x + col as u16, y, text.len() as u16, 1), frame);
+            }
+        }
+    }
+}
+
+fn render_viewer_search_dialog(
+    frame: &mut Frame,
+    area: ftui::core::geometry::Rect,
+    search: &ViewerSearch,
+    cursor: usize,
+    focus: usize,
+    theme: ThemeColors,
+) {
+    let style = Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg);
+    let selected_style = Style::new().fg(theme.selection_fg).bg(theme.selection_bg);
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
         .style(style)
-        .block(
-            Block::bordered()
-                .border_style(Style::new().fg(theme.panel_border_active))
-                .borders(Borders::ALL)
-                .title("View"),
-        );
-    paragraph.render(area, frame);
+        .title("Search");
+    let inner = block.inner(area);
+    block.render(area, frame);
+
+    let label = Paragraph::new(Text::from("Search for:")).style(style);
+    label.render(ftui::core::geometry::Rect::new(inner.x, inner.y, inner.width, 1), frame);
+
+    let field_width = (inner.width as usize).saturating_sub(2);
+    let start = cursor.saturating_sub(field_width.saturating_sub(1));
+    let visible: String = search.query.chars().skip(start).take(field_width).collect();
+    let padding = field_width.saturating_sub(visible.chars().count());
+    let input = format!("[{}{}]", visible, ".".repeat(padding));
+    let input_para = Paragraph::new(Text::from(input)).style(if focus == 0 { selected_style } else { style });
+    input_para.render(ftui::core::geometry::Rect::new(inner.x, inner.y + 1, inner.width, 1), frame);
+
+    let half = inner.width / 2;
+    let options = [
+        (search.case_sensitive, "Case sensitive"),
+        (search.whole_word, "Whole words"),
+        (search.regex, "Regular expression"),
+        (search.backward, "Backward"),
+        (search.hex, "Hex bytes"),
+    ];
+    for (idx, (checked, label)) in options.iter().enumerate() {
+        let text = format!("{} {}", if *checked { "[x]" } else { "[ ]" }, label);
+        let x = inner.x + if idx % 2 == 0 { 0 } else { half };
+        let y = inner.y + 3 + (idx / 2) as u16;
+        let para = Paragraph::new(Text::from(text)).style(if focus == idx + 1 { selected_style } else { style });
+        para.render(ftui::core::geometry::Rect::new(x, y, half, 1), frame);
+    }
+
+    let hint = Paragraph::new(Text::from("Enter search  Tab options  Shift+F7 again")).style(style);
+    hint.render(ftui::core::geometry::Rect::new(inner.x, inner.y + 7, inner.width, 1), frame);
 }
 
 pub fn render_status(
@@ -189,6 +498,12 @@ fn render_copy_move_dialog(
     let cb4_area = ftui::core::geometry::Rect::new(inner.x + half, inner.y + 4, half, 1);
     cb4_para.render(cb4_area, frame);
 
+    if state.use_filters {
+        let filter_para = Paragraph::new(Text::from(format!("Filter: {}", state.filter.spec()))).style(style);
+        let filter_area = ftui::core::geometry::Rect::new(inner.x, inner.y + 5, inner.width, 1);
+        filter_para.render(filter_area, frame);
+    }
+
     // Buttons row
     let btn_copy = if is_copy { "[ Copy ]" } else { "[Rename/Move]" };
     let btn_tree = "[F10-Tree]";
@@ -235,12 +550,68 @@ fn render_copy_move_dialog(
     }
 }
 
+fn render_pack_dialog(frame: &mut Frame, area: ftui::core::geometry::Rect, state: &PackDialogState, theme: ThemeColors) {
+    let style = Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg);
+    let focused = |focus: PackDialogFocus| {
+        if state.focus == focus { Style::new().fg(theme.selection_fg).bg(theme.selection_bg) } else { style }
+    };
+    let checkbox = |on: bool| if on { "[x]" } else { "[ ]" };
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
+        .style(style)
+        .title("Pack");
+    let inner = block.inner(area);
+    block.render(area, frame);
+    let half = inner.width / 2;
+    let line = |frame: &mut Frame, text: String, x: u16, row: u16, width: u16, style: Style| {
+        let area = ftui::core::geometry::Rect::new(inner.x + x, inner.y + row, width, 1);
+        Paragraph::new(Text::from(text)).style(style).render(area, frame);
+    };
+
+    line(frame, format!("Pack \"{}\" into", state.source_name), 0, 0, inner.width, style);
+
+    // Archive path with dotted fill, scrolled to keep the end visible
+    let field_width = (inner.width as usize).saturating_sub(2);
+    let dest_len = state.dest.chars().count();
+    let scroll = dest_len.saturating_sub(field_width);
+    let input_display = if scroll == 0 {
+        format!("[{}{}]", state.dest, ".".repeat(field_width - dest_len))
+    } else {
+        format!("[{}]", state.dest.chars().skip(scroll).collect::<String>())
+    };
+    line(frame, input_display, 0, 1, inner.width, focused(PackDialogFocus::Input));
+
+    let format = state.format.extension().trim_start_matches('.');
+    line(frame, format!("Format: < {format} >"), 0, 3, half, focused(PackDialogFocus::Format));
+    line(frame, format!("Level: < {} >", state.level), half, 3, half, focused(PackDialogFocus::Level));
+    let relative = format!("{} Store relative paths", checkbox(state.relative_paths));
+    line(frame, relative, 0, 4, half, focused(PackDialogFocus::RelativePaths));
+    let delete = format!("{} Delete originals", checkbox(state.delete_sources));
+    line(frame, delete, half, 4, half, focused(PackDialogFocus::DeleteSources));
+    let filters = format!("{} Use Filters", checkbox(state.use_filters));
+    line(frame, filters, 0, 5, half, focused(PackDialogFocus::UseFilters));
+    if state.use_filters {
+        line(frame, format!("Filter: {}", state.filter.spec()), 0, 6, inner.width, style);
+    }
+
+    let spacing = inner.width / 3;
+    line(frame, "[ Pack ]".to_string(), 0, 8, spacing, focused(PackDialogFocus::BtnPack));
+    line(frame, "[Filters]".to_string(), spacing, 8, spacing, focused(PackDialogFocus::BtnFilters));
+    line(frame, "[Cancel]".to_string(), spacing * 2, 8, spacing, focused(PackDialogFocus::BtnCancel));
+
+    if state.focus == PackDialogFocus::Input {
+        let cursor_col = state.dest[..state.cursor].chars().count().saturating_sub(scroll);
+        let cursor_x = area.x + 2 + cursor_col as u16;
+        frame.set_cursor(Some((cursor_x, area.y + 2)));
+    }
+}
+
 fn render_delete_dialog(
     frame: &mut Frame,
     area: ftui::core::geometry::Rect,
     source_name: &str,
     source_count: usize,
-    use_filters: bool,
+    filter: Option<&FileFilter>,
     focus: usize,
     theme: ThemeColors,
 ) {
@@ -263,11 +634,13 @@ fn render_delete_dialog(
     msg_para.render(msg_area, frame);
 
     // Checkbox
-    let cb = if use_filters { "[x]" } else { "[ ]" };
     let cb_style = if focus == 0 {
         Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
     } else { style };
-    let cb_text = format!("{} Use Filters", cb);
+    let cb_text = match filter {
+        Some(filter) => format!("[x] Use Filters ({})", filter.spec()),
+        None => "[ ] Use Filters".to_string(),
+    };
     let cb_para = Paragraph::new(Text::from(cb_text)).style(cb_style);
     let cb_area = ftui::core::geometry::Rect::new(inner.x, inner.y + 3, inner.width, 1);
     cb_para.render(cb_area, frame);
@@ -299,6 +672,192 @@ fn render_delete_dialog(
     btn_can_para.render(btn_can_area, frame);
 }
 
+#[allow(clippy::too_many_arguments)]
+fn render_filter_dialog(frame: &mut Frame, area: ftui::core::geometry::Rect, modal: &Modal, theme: ThemeColors) {
+    let Modal::Filter { target, saved, selected, masks, cursor, case_sensitive, focus } = modal else { return };
+    let (selected, cursor, focus) = (*selected, *cursor, *focus);
+    let style = Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg);
+    let selected_style = Style::new().fg(theme.selection_fg).bg(theme.selection_bg);
+    let title = match target {
+        FilterTarget::Panel(ActivePane::Left) => "Left panel filter",
+        FilterTarget::Panel(ActivePane::Right) => "Right panel filter",
+        FilterTarget::Dialog(_) => "Filters",
+    };
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
+        .style(style)
+        .title(title);
+    let inner = block.inner(area);
+    block.render(area, frame);
+
+    let label = Paragraph::new(Text::from("Masks (include|exclude, empty = none):")).style(style);
+    label.render(ftui::core::geometry::Rect::new(inner.x, inner.y, inner.width, 1), frame);
+
+    let field_width = (inner.width as usize).saturating_sub(2);
+    let start = cursor.saturating_sub(field_width.saturating_sub(1));
+    let visible: String = masks.chars().skip(start).take(field_width).collect();
+    let padding = field_width.saturating_sub(visible.chars().count());
+    let input = format!("[{}{}]", visible, ".".repeat(padding));
+    let input_para = Paragraph::new(Text::from(input)).style(if focus == 0 { selected_style } else { style });
+    input_para.render(ftui::core::geometry::Rect::new(inner.x, inner.y + 1, inner.width, 1), frame);
+
+    let cb = format!("{} Case sensitive", if *case_sensitive { "[x]" } else { "[ ]" });
+    let cb_para = Paragraph::new(Text::from(cb)).style(if focus == 1 { selected_style } else { style });
+    cb_para.render(ftui::core::geometry::Rect::new(inner.x, inner.y + 2, inner.width, 1), frame);
+
+    let header = Paragraph::new(Text::from("Saved filters (Ins save, Del remove):")).style(style);
+    header.render(ftui::core::geometry::Rect::new(inner.x, inner.y + 4, inner.width, 1), frame);
+
+    let rows = inner.height.saturating_sub(5) as usize;
+    if saved.is_empty() {
+        let empty = Paragraph::new(Text::from("  (none)")).style(style);
+        empty.render(ftui::core::geometry::Rect::new(inner.x, inner.y + 5, inner.width, 1), frame);
+    }
+    let scroll = (selected + 1).saturating_sub(rows);
+    for (row, (idx, item)) in saved.iter().enumerate().skip(scroll).take(rows).enumerate() {
+        let marker = if idx == selected { ">" } else { " " };
+        let line = format!("{} {:<16} {}", marker, item.name, item.filter.spec());
+        let line_style = if focus == 2 && idx == selected { selected_style } else { style };
+        let para = Paragraph::new(Text::from(line)).style(line_style);
+        para.render(ftui::core::geometry::Rect::new(inner.x, inner.y + 5 + row as u16, inner.width, 1), frame);
+    }
+}
+
+fn render_progress_dialog(
+    frame: &mut Frame,
+    area: ftui::core::geometry::Rect,
+    job: &Job,
+    focus: usize,
+    theme: ThemeColors,
+) {
+    let style = Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg);
+    let title = if job.is_paused() {
+        format!("{} (paused)", job.task.verb())
+    } else {
+        job.task.verb().to_string()
+    };
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
+        .style(style)
+        .title(title.as_str());
+    let inner = block.inner(area);
+    block.render(area, frame);
+    let width = inner.width as usize;
+    let progress = &job.progress;
+
+    let line = |text: String, row: u16, frame: &mut Frame| {
+        let para = Paragraph::new(Text::from(text)).style(style);
+        para.render(ftui::core::geometry::Rect::new(inner.x, inner.y + row, inner.width, 1), frame);
+    };
+
+    line(tail_chars(&job.task.describe(), width), 0, frame);
+    let current = progress.current.as_ref().map(|path| path.display().to_string()).unwrap_or_default();
+    line(tail_chars(&current, width), 2, frame);
+
+    // NC-style bar: ███████░░░░░░  45%
+    let bar_width = width.saturating_sub(6);
+    let percent = progress.percent() as usize;
+    let filled = bar_width * percent / 100;
+    line(format!("{}{} {:>3}%", "█".repeat(filled), "░".repeat(bar_width - filled), percent), 3, frame);
+
+    line(
+        format!(
+            "Files {}/{}   {} of {}",
+            progress.files_done.min(progress.files_total),
+            progress.files_total,
+            format_size(progress.bytes_done),
+            format_size(progress.bytes_total)
+        ),
+        5,
+        frame,
+    );
+    let eta = job.eta().map(format_duration).unwrap_or_else(|| "--:--".to_string());
+    line(
+        format!(
+            "{}/s   Elapsed {}   ETA {}",
+            format_size(job.throughput()),
+            format_duration(job.elapsed()),
+            eta
+        ),
+        6,
+        frame,
+    );
+
+    let pause_label = if job.is_paused() { "[Resume]" } else { "[Pause]" };
+    let buttons = ["[Cancel]", pause_label, "[Background]"];
+    let btn_spacing = inner.width / 3;
+    for (idx, label) in buttons.iter().enumerate() {
+        let btn_style = if focus == idx {
+            Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
+        } else {
+            style
+        };
+        let para = Paragraph::new(Text::from(*label)).style(btn_style);
+        let btn_area = ftui::core::geometry::Rect::new(inner.x + btn_spacing * idx as u16, inner.y + 8, btn_spacing, 1);
+        para.render(btn_area, frame);
+    }
+}
+
+fn render_conflict_dialog(
+    frame: &mut Frame,
+    area: ftui::core::geometry::Rect,
+    info: &ConflictInfo,
+    focus: usize,
+    theme: ThemeColors,
+) {
+    let style = Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg);
+    let block = Block::bordered()
+        .border_style(Style::new().fg(theme.panel_border_active))
+        .style(style)
+        .title("Target exists");
+    let inner = block.inner(area);
+    block.render(area, frame);
+    let width = inner.width as usize;
+
+    let describe = |size: u64, modified| {
+        let (date, clock) = format_time(modified);
+        format!("          {} bytes  {}  {}", size, date, clock)
+    };
+    let lines = [
+        (0, "The following file already exists:".to_string()),
+        (2, format!("Source:   {}", tail_chars(&info.src.display().to_string(), width.saturating_sub(10)))),
+        (3, describe(info.src_size, info.src_modified)),
+        (4, format!("Target:   {}", tail_chars(&info.target.display().to_string(), width.saturating_sub(10)))),
+        (5, describe(info.target_size, info.target_modified)),
+    ];
+    for (row, text) in lines {
+        let para = Paragraph::new(Text::from(text)).style(style);
+        para.render(ftui::core::geometry::Rect::new(inner.x, inner.y + row, inner.width, 1), frame);
+    }
+
+    let buttons = [
+        "[Overwrite]", "[Skip]", "[Overwrite all]", "[Skip all]",
+        "[If older]", "[Rename]", "[Append]", "[Cancel]",
+    ];
+    let btn_spacing = inner.width / 4;
+    for (idx, label) in buttons.iter().enumerate() {
+        let btn_style = if focus == idx {
+            Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
+        } else {
+            style
+        };
+        let para = Paragraph::new(Text::from(*label)).style(btn_style);
+        let btn_x = inner.x + btn_spacing * (idx % 4) as u16;
+        let btn_y = inner.y + 7 + (idx / 4) as u16;
+        para.render(ftui::core::geometry::Rect::new(btn_x, btn_y, btn_spacing, 1), frame);
+    }
+}
+
+/// Keep the last `width` characters so long paths show their file name.
+fn tail_chars(text: &str, width: usize) -> String {
+    let count = text.chars().count();
+    if count <= width {
+        return text.to_string();
+    }
+    let skip = count - width + 1;
+    format!("…{}", text.chars().skip(skip).collect::<String>())
+}
+
 pub fn render_keybar(frame: &mut Frame, area: ftui::core::geometry::Rect, theme: ThemeColors) {
     let bg = Block::new().style(Style::new().fg(theme.keybar_fg).bg(theme.keybar_bg));
     bg.render(area, frame);
@@ -321,12 +880,21 @@ pub fn render_keybar(frame: &mut Frame, area: ftui::core::geometry::Rect, theme:
     status.render(area, frame);
 }
 
-pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left: &Pane, right: &Pane) {
+pub fn render_modal(
+    frame: &mut Frame,
+    modal: &Modal,
+    theme: ThemeColors,
+    left: &Pane,
+    right: &Pane,
+    jobs: &JobManager,
+) {
     let full = ftui::core::geometry::Rect::new(0, 0, frame.width(), frame.height());
     let width = full.width.min(70).max(30);
     let height = match modal {
         Modal::CopyDialog(_) | Modal::MoveDialog(_) => 12,
+        Modal::PackDialog(_) => 12,
         Modal::DeleteDialog { .. } => 10,
+        Modal::Filter { .. } => 14,
         Modal::Prompt { .. } => 8,
         Modal::Confirm { .. } => 8,
         Modal::FindResults { .. } => 10,
@@ -334,7 +902,12 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
         Modal::DriveMenu { .. } => 10,
         Modal::Config { .. } => 12,
         Modal::PanelOptions { .. } => 9,
+        Modal::History { .. } => 12,
+        Modal::Progress { .. } => 11,
+        Modal::Jobs { .. } => 10,
+        Modal::Conflict { .. } => 12,
         Modal::UserMenu { .. } => 10,
+        Modal::ViewerSearch { .. } => 11,
         Modal::About => 8,
         Modal::Help { .. } => 18,
         Modal::PullDown { .. } => 10,
@@ -360,9 +933,12 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
         Modal::CopyDialog(state) | Modal::MoveDialog(state) => {
             render_copy_move_dialog(frame, area, state, matches!(modal, Modal::CopyDialog(_)), theme);
         }
-        Modal::DeleteDialog { sources, source_name, use_filters, focus } => {
-            render_delete_dialog(frame, area, source_name, sources.len(), *use_filters, *focus, theme);
+        Modal::PackDialog(state) => render_pack_dialog(frame, area, state, theme),
+        Modal::DeleteDialog { sources, source_name, use_filters, filter, focus } => {
+            let filter = use_filters.then_some(filter);
+            render_delete_dialog(frame, area, source_name, sources.len(), filter, *focus, theme);
         }
+        Modal::Filter { .. } => render_filter_dialog(frame, area, modal, theme),
         Modal::Prompt { title, label, value, cursor, .. } => {
             // NC5-style prompt with dotted input field
             let inner = block.inner(area);
@@ -405,7 +981,7 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
             let cursor_y = area.y + 1 + 3;
             frame.set_cursor(Some((cursor_x, cursor_y)));
         }
-        Modal::Confirm { title, message, .. } => {
+        Modal::Confirm { title, message, action } => {
             // NC5-style confirm dialog
             let inner = block.inner(area);
             block.render(area, frame);
@@ -423,7 +999,11 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
             msg_para.render(msg_area, frame);
 
             // NC5-style buttons
-            let btn_text = "[ Yes ]    [ No ]";
+            let btn_text = if matches!(action, PendingConfirm::CloseEditor) {
+                "[ Yes ]    [ No ]    [ Cancel ]"
+            } else {
+                "[ Yes ]    [ No ]"
+            };
             let btn_para = Paragraph::new(Text::from(btn_text))
                 .style(Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg));
             let btn_area = ftui::core::geometry::Rect::new(inner.x, inner.y + 5, inner.width, 1);
@@ -443,8 +1023,9 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
                 .block(block);
             paragraph.render(area, frame);
         }
-        Modal::Tree { items, selected, scroll, .. } => {
-            let mut lines = vec!["Directory tree".to_string()];
+        Modal::Tree { items, selected, scroll, pick_for, .. } => {
+            let title = if pick_for.is_some() { "Choose destination" } else { "Directory tree" };
+            let mut lines = vec![title.to_string()];
             let view_height = (area.height.saturating_sub(2)) as usize;
             let start = *scroll;
             let end = (*scroll + view_height).min(items.len());
@@ -482,7 +1063,17 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
                 .block(block);
             paragraph.render(area, frame);
         }
-        Modal::Config { page, selected, show_hidden, auto_save, confirm_delete, confirm_overwrite } => {
+        Modal::Config {
+            page,
+            selected,
+            show_hidden,
+            auto_save,
+            internal_editor,
+            confirm_delete,
+            confirm_overwrite,
+            confirm_delete_dirs,
+            confirm_quit,
+        } => {
             let inner = block.inner(area);
             block.render(area, frame);
 
@@ -526,40 +1117,40 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
                 }
                 1 => {
                     // Confirmations
-                    let cb1 = if *confirm_delete { "[x]" } else { "[ ]" };
-                    let cb1_style = if *selected == 0 {
-                        Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
-                    } else {
-                        Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg)
-                    };
-                    let cb1_text = format!("{} Confirm file delete", cb1);
-                    let cb1_para = Paragraph::new(Text::from(cb1_text)).style(cb1_style);
-                    let cb1_area = ftui::core::geometry::Rect::new(inner.x, content_y, inner.width, 1);
-                    cb1_para.render(cb1_area, frame);
-
-                    let cb2 = if *confirm_overwrite { "[x]" } else { "[ ]" };
-                    let cb2_style = if *selected == 1 {
-                        Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
-                    } else {
-                        Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg)
-                    };
-                    let cb2_text = format!("{} Confirm file overwrite", cb2);
-                    let cb2_para = Paragraph::new(Text::from(cb2_text)).style(cb2_style);
-                    let cb2_area = ftui::core::geometry::Rect::new(inner.x, content_y + 1, inner.width, 1);
-                    cb2_para.render(cb2_area, frame);
+                    let items = [
+                        (*confirm_delete, "Confirm file delete"),
+                        (*confirm_overwrite, "Confirm file overwrite"),
+                        (*confirm_delete_dirs, "Confirm delete of non-empty dirs"),
+                        (*confirm_quit, "Confirm exit"),
+                    ];
+                    for (idx, (checked, label)) in items.iter().enumerate() {
+                        let checkbox = if *checked { "[x]" } else { "[ ]" };
+                        let item_style = if *selected == idx {
+                            Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
+                        } else {
+                            Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg)
+                        };
+                        let item_para = Paragraph::new(Text::from(format!("{} {}", checkbox, label))).style(item_style);
+                        let item_area =
+                            ftui::core::geometry::Rect::new(inner.x, content_y + idx as u16, inner.width, 1);
+                        item_para.render(item_area, frame);
+                    }
                 }
                 _ => {
                     // Other options
-                    let checkbox = if *auto_save { "[x]" } else { "[ ]" };
-                    let item_style = if *selected == 0 {
-                        Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
-                    } else {
-                        Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg)
-                    };
-                    let item_text = format!("{} Auto save setup", checkbox);
-                    let item_para = Paragraph::new(Text::from(item_text)).style(item_style);
-                    let item_area = ftui::core::geometry::Rect::new(inner.x, content_y, inner.width, 1);
-                    item_para.render(item_area, frame);
+                    let items = [(*auto_save, "Auto save setup"), (*internal_editor, "Use internal editor")];
+                    for (idx, (checked, label)) in items.iter().enumerate() {
+                        let checkbox = if *checked { "[x]" } else { "[ ]" };
+                        let item_style = if *selected == idx {
+                            Style::new().fg(theme.selection_fg).bg(theme.selection_bg)
+                        } else {
+                            Style::new().fg(theme.dialog_fg).bg(theme.dialog_bg)
+                        };
+                        let item_para = Paragraph::new(Text::from(format!("{} {}", checkbox, label))).style(item_style);
+                        let item_area =
+                            ftui::core::geometry::Rect::new(inner.x, content_y + idx as u16, inner.width, 1);
+                        item_para.render(item_area, frame);
+                    }
                 }
             }
 
@@ -615,21 +1206,80 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
             let btn_area = ftui::core::geometry::Rect::new(inner.x, inner.y + 6, inner.width, 1);
             btn_para.render(btn_area, frame);
         }
-        Modal::UserMenu { items, selected, scroll, .. } => {
-            let mut lines = vec!["User menu".to_string()];
+        Modal::Progress { job_id, focus } => {
+            if let Some(job) = jobs.get(*job_id) {
+                render_progress_dialog(frame, area, job, *focus, theme);
+            }
+        }
+        Modal::Conflict { job_id, focus } => {
+            if let Some(info) = jobs.get(*job_id).and_then(|job| job.conflict.as_ref()) {
+                render_conflict_dialog(frame, area, info, *focus, theme);
+            }
+        }
+        Modal::Jobs { selected } => {
+            let mut lines = vec!["Background jobs".to_string()];
+            let view_height = (area.height.saturating_sub(4)) as usize;
+            let selected = (*selected).min(jobs.jobs().len().saturating_sub(1));
+            let start = selected.saturating_sub(view_height.saturating_sub(1));
+            for (idx, job) in jobs.jobs().iter().enumerate().skip(start).take(view_height) {
+                let marker = if idx == selected { ">" } else { " " };
+                let state = if job.is_cancelled() {
+                    "cancel"
+                } else if job.is_paused() {
+                    "paused"
+                } else {
+                    "run"
+                };
+                let percent = job.progress.percent();
+                lines.push(format!("{} #{:<3} {:>3}% {:<6} {}", marker, job.id, percent, state, job.task.describe()));
+            }
+            lines.push(String::from("\nEnter Progress  P Pause  Del Cancel"));
+            let paragraph = Paragraph::new(Text::from(lines.join("\n")))
+                .style(style)
+                .block(block);
+            paragraph.render(area, frame);
+        }
+        Modal::History { items, selected, scroll } => {
+            let mut lines = vec!["Command history".to_string()];
+            let view_height = (area.height.saturating_sub(4)) as usize;
+            let start = *scroll;
+            let end = (*scroll + view_height).min(items.len());
+            for (idx, item) in items.iter().enumerate().take(end).skip(start) {
+                let marker = if idx == *selected { ">" } else { " " };
+                lines.push(format!("{} {}", marker, item));
+            }
+            lines.push(String::from("\nEnter Run  F4 Edit"));
+            let paragraph = Paragraph::new(Text::from(lines.join("\n")))
+                .style(style)
+                .block(block);
+            paragraph.render(area, frame);
+        }
+        Modal::UserMenu { title, items, selected, scroll, stack, .. } => {
+            let mut breadcrumb: Vec<&str> = stack.iter().map(|level| level.title.as_str()).collect();
+            breadcrumb.push(title.as_str());
+            let mut lines = vec![breadcrumb.join(" > ")];
             let view_height = (area.height.saturating_sub(2)) as usize;
             let start = *scroll;
             let end = (*scroll + view_height).min(items.len());
             for (idx, item) in items.iter().enumerate().take(end).skip(start) {
                 let marker = if idx == *selected { ">" } else { " " };
-                lines.push(format!("{} {}", marker, item.label));
+                let hotkey = item.hotkey.map(|key| key.to_string()).unwrap_or_else(|| " ".to_string());
+                let submenu = if item.is_submenu() { " ►" } else { "" };
+                lines.push(format!("{} {}  {}{}", marker, hotkey, item.label, submenu));
             }
-            lines.push(String::from("\nF4 Edit"));
+            if let Some(item) = items.get(*selected) {
+                let first_line = item.command.lines().next().unwrap_or("");
+                lines.push(format!("\n{}", first_line));
+            }
+            lines.push(String::from("F4 Edit"));
             let paragraph = Paragraph::new(Text::from(lines.join("\n")))
                 .style(style)
                 .block(block);
             paragraph.render(area, frame);
         }
+        Modal::ViewerSearch { search, cursor, focus } => {
+            render_viewer_search_dialog(frame, area, search, *cursor, *focus, theme);
+        }
         Modal::About => {
             let text = "FrankenCommander\n\nBuilt with FrankenTUI\n2026";
             let paragraph = Paragraph::new(Text::from(text)).style(style).block(block);
@@ -640,7 +1290,7 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
             block.render(area, frame);
 
             // NC5-style help pages
-            let pages = ["Overview", "Keys", "Panels", "Files"];
+            let pages = ["Overview", "Keys", "Panels", "Files", "Viewer", "Editor"];
             let help_content: &[&str] = match page {
                 0 => &[
                     "FrankenCommander Help",
@@ -668,6 +1318,7 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
                     "F7       Make new directory",
                     "F8       Delete files/directories",
                     "F9       Pull-down menu",
+                    "Shift+F9 Save setup",
                     "F10      Quit",
                     "F11      File attributes (chmod)",
                     "",
@@ -690,21 +1341,29 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
                     "Ctrl+F1  Toggle left panel",
                     "Ctrl+F2  Toggle right panel",
                     "Ctrl+O   Command line mode",
+                    "Enter    Run command line in $SHELL",
+                    "Ctrl+E   Previous command (history)",
+                    "Ctrl+X   Next command (history)",
+                    "Alt+F8   Command history list",
+                    "Alt+J    Background jobs",
                     "",
                     "Sort Modes (via menu):",
                     "Name, Extension, Time, Size, Unsorted",
                 ],
-                _ => &[
+                3 => &[
                     "File Operations",
                     "",
                     "Alt+F1   Drive menu (left panel)",
                     "Alt+F2   Drive menu (right panel)",
+                    "Alt+F5   Pack files",
                     "Alt+F7   Find file",
+                    "Alt+F9   Extract archive",
                     "Ctrl+F8  Sync directories",
                     "",
                     "Quick Search:",
-                    "Type characters to jump to matching",
-                    "file names. Backspace to edit search.",
+                    "Alt+letter jumps to matching file",
+                    "names; keep typing to refine it.",
+                    "Other typing goes to the command line.",
                     "",
                     "Selection:",
                     "Insert or Space to toggle selection.",
@@ -712,6 +1371,50 @@ pub fn render_modal(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left:
                     "Operations apply to selected files",
                     "or current file if none selected.",
                 ],
+                4 => &[
+                    "Viewer (F3)",
+                    "",
+                    "Home/End Start / end of file",
+                    "Left/Right Scroll long lines sideways",
+                    "Ctrl+Right/Left, Gray +/-",
+                    "         Next / previous file of the panel",
+                    "F2       Wrap / unwrap long lines",
+                    "F5       Go to line or percentage (50%)",
+                    "Ctrl+0..9 Set bookmark, 0..9 go to it",
+                    "Ctrl+F   Follow appended data (tail -f);",
+                    "         scroll up to pause, End resumes",
+                    "F7       Search (case, whole words,",
+                    "         regular expression, backward)",
+                    "Shift+F7 Search again",
+                    "F4       Toggle text / hex dump",
+                    "F8       Cycle encoding (UTF-8, Latin-1,",
+                    "         CP437, Windows-1252, UTF-16)",
+                    "         (binary files open in hex)",
+                    "Esc      Close viewer",
+                ],
+                _ => &[
+                    "Editor (F4)",
+                    "",
+                    "Arrows, Home/End, PgUp/PgDn  Move",
+                    "Ctrl+Left/Right  Word left / right",
+                    "Ctrl+Home/End    Start / end of file",
+                    "Shift+movement   Select a block",
+                    "Ctrl+A           Select all",
+                    "Ctrl+C, Ctrl+Ins Copy block",
+                    "Ctrl+X, Shift+Del Cut block",
+                    "Ctrl+V, Shift+Ins Paste",
+                    "Ctrl+Z / Ctrl+Y  Undo / redo",
+                    "Ins              Insert / overwrite",
+                    "F2               Save",
+                    "Shift+F2         Save as",
+                    "F7               Search",
+                    "Shift+F7         Search again",
+                    "F4               Replace all",
+                    "Esc              Close (asks to save changes)",
+                    "",
+                    "Options → Configuration → Other switches",
+                    "to $EDITOR instead.",
+                ],
             };
 
             // Title with page tabs
@@ -1063,7 +1766,7 @@ fn render_panel_info(
 
     // Show directory/file info
     let mut lines = Vec::new();
-    lines.push(format!("Path: {}", pane.cwd.display()));
+    lines.push(format!("Path: {}", pane.display_path(pane.dir()).display()));
     lines.push(String::new());
 
     let total_files = pane.entries.iter().filter(|e| !e.is_dir).count();
@@ -1080,6 +1783,17 @@ fn render_panel_info(
         lines.push(format!("Selected size: {} bytes", pane.selected_total_size()));
     }
 
+    if let Some(entry) = pane.selected_entry() {
+        lines.push(String::new());
+        lines.push(format!("Current: {}", entry.name));
+        if let Some(mode) = format_mode(entry) {
+            lines.push(format!("Mode: {}", mode));
+        }
+        if let Some(target) = &entry.link_target {
+            lines.push(format!("Link to: {}", target.display()));
+        }
+    }
+
     let text = lines.join("\n");
     let para = Paragraph::new(Text::from(text))
         .style(Style::new().fg(theme.panel_fg).bg(theme.panel_bg));
@@ -1151,6 +1865,10 @@ fn render_panel_tree(
     area
 }
 
+/// How much of a file quick view reads for its preview.
+const QUICK_VIEW_BYTES: u64 = 16 * 1024;
+const QUICK_VIEW_ARCHIVE_LIMIT: u64 = 1024 * 1024;
+
 fn render_quick_view(
     frame: &mut Frame,
     area: ftui::core::geometry::Rect,
@@ -1196,12 +1914,22 @@ fn render_quick_view(
     }
 
     // Try to read first few lines of the file for preview
-    let preview = match std::fs::read_to_string(&entry.path) {
-        Ok(content) => {
-            let lines: Vec<&str> = content.lines().take(inner.height as usize).collect();
-            lines.join("\n")
-        }
-        Err(_) => format!("{}\n{} bytes", entry.name, entry.size),
+    let mut head = Vec::new();
+    // Some archive entries are unpacked whole, so only small ones get a
+    // preview, and none before a compressed tar was decoded by a view or
+    // extraction.
+    let read = if other.in_archive() && (entry.size > QUICK_VIEW_ARCHIVE_LIMIT || !other.fs().reads_quickly()) {
+        Err(io::Error::from(io::ErrorKind::FileTooLarge))
+    } else {
+        other.fs().open_read(&entry.path).and_then(|file| file.take(QUICK_VIEW_BYTES).read_to_end(&mut head))
+    };
+    let encoding = Encoding::detect(&head);
+    let preview = if read.is_ok() && (encoding.unit() == 2 || !looks_binary(&head)) {
+        let content = encoding.decode(&head);
+        let lines: Vec<&str> = content.lines().take(inner.height as usize).collect();
+        lines.join("\n")
+    } else {
+        format!("{}\n{} bytes", entry.name, entry.size)
     };
 
     let para = Paragraph::new(Text::from(preview))
@@ -1212,16 +1940,17 @@ fn render_quick_view(
 }
 
 fn panel_title(pane: &Pane) -> String {
-    if let Some(vfs) = &pane.vfs {
-        if vfs.prefix.is_empty() {
-            format!("{}:", vfs.zip_path.display())
-        } else {
-            format!("{}:{}", vfs.zip_path.display(), vfs.prefix)
-        }
-    } else if pane.panelized.is_some() {
+    let dir = match pane.mounts.last() {
+        Some(mount) if mount.dir.as_os_str().is_empty() => format!("{}:", mount.fs.name()),
+        Some(mount) => format!("{}:{}/", mount.fs.name(), mount.dir.display()),
+        None => pane.cwd.display().to_string(),
+    };
+    if pane.panelized.is_some() {
         "Search results".to_string()
+    } else if let Some(filter) = &pane.filter {
+        format!("{} [{}]", dir, filter.spec())
     } else {
-        pane.cwd.display().to_string()
+        dir
     }
 }
 
@@ -1270,7 +1999,7 @@ pub fn render_layout(
             .style(Style::new().fg(theme.panel_fg).bg(theme.panel_bg))
             .block(block);
         paragraph.render(body_area, frame);
-        let cursor_x = body_area.x + 1 + cmd_cursor as u16;
+        let cursor_x = body_area.x + 1 + cmdline[..cmd_cursor].chars().count() as u16;
         let cursor_y = body_area.y + 1;
         frame.set_cursor(Some((cursor_x, cursor_y)));
     }
@@ -1339,6 +2068,13 @@ pub fn render_status_and_keybar(
     render_keybar(frame, key_area, theme);
 }
 
-pub fn render_modal_wrapper(frame: &mut Frame, modal: &Modal, theme: ThemeColors, left: &Pane, right: &Pane) {
-    render_modal(frame, modal, theme, left, right);
+pub fn render_modal_wrapper(
+    frame: &mut Frame,
+    modal: &Modal,
+    theme: ThemeColors,
+    left: &Pane,
+    right: &Pane,
+    jobs: &JobManager,
+) {
+    render_modal(frame, modal, theme, left, right, jobs);
 }
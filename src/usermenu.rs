@@ -0,0 +1,315 @@
+#![forbid(unsafe_code)]
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fs_ops::wildcard_match;
+use crate::model::{Entry, MenuCondition, MenuMacro, UserMenuItem};
+
+const SAMPLE_MENU: &str = "\
+# FrankenCommander user menu
+#
+# \"<hotkey>: <label>\" starts an entry; the indented lines below it are the
+# command. \"+ f <masks>\" shows the next entry only for matching files,
+# \"+ d\" only on directories and \"+ !d\" only on files. An entry followed by
+# lines wrapped in { and } opens a submenu. A .fc.menu file in a directory
+# replaces this menu while you are in that directory.
+
+l: List directory
+    ls -la
++ !d
+v: View current file
+    less !.!
+g: Grep selected files
+    grep -n !?Search for:?! %s
+c: Copy to other panel
+    cp -r %s %D
++ d
+u: Disk usage
+    du -sh !.!
++ f *.tar *.tar.gz *.tgz
+x: Extract tarball
+    tar xf !.!
+t: Tools
+{
+d: Disk free
+    df -h
+p: Processes
+    ps aux | less
+}
+e: Edit this menu
+    $EDITOR ~/.frankencommander/usermenu.txt
+";
+
+/// What the placeholders of a user menu command expand to.
+#[derive(Debug, Clone)]
+pub struct MacroContext {
+    pub current_dir: PathBuf,
+    pub current_file: Option<String>,
+    pub selected: Vec<String>,
+    pub other_dir: PathBuf,
+    pub other_file: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expansion {
+    Ready(String),
+    NeedInput { prompt: String, default: String },
+}
+
+pub fn ensure_user_menu_file(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if !path.exists() {
+        fs::write(path, SAMPLE_MENU)?;
+    }
+    Ok(())
+}
+
+pub fn load_user_menu(path: &Path) -> Vec<UserMenuItem> {
+    match fs::read_to_string(path) {
+        Ok(content) => parse_user_menu(&content),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Parse the NC/MC-style menu format. Lines at column 0 start entries
+/// (`x: Label`), indented lines form the command body, `+` lines are conditions
+/// for the following entry and `{` / `}` wrap the children of a submenu.
+/// Old flat `label|command` lines are still accepted.
+pub fn parse_user_menu(content: &str) -> Vec<UserMenuItem> {
+    let mut levels: Vec<Vec<UserMenuItem>> = vec![Vec::new()];
+    let mut pending: Vec<MenuCondition> = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            if let Some(item) = levels.last_mut().and_then(|level| level.last_mut()) {
+                if !item.command.is_empty() {
+                    item.command.push('\n');
+                }
+                item.command.push_str(trimmed);
+            }
+            continue;
+        }
+        if trimmed == "{" {
+            levels.push(Vec::new());
+            continue;
+        }
+        if trimmed == "}" {
+            if levels.len() > 1 {
+                let children = levels.pop().unwrap_or_default();
+                if let Some(parent) = levels.last_mut().and_then(|level| level.last_mut()) {
+                    parent.children = children;
+                }
+            }
+            continue;
+        }
+        if let Some(rest) = trimmed.strip_prefix('+') {
+            pending.extend(parse_condition(rest));
+            continue;
+        }
+        let conditions = std::mem::take(&mut pending);
+        let (hotkey, label, command) = parse_entry_header(trimmed);
+        if label.is_empty() {
+            continue;
+        }
+        levels.last_mut().expect("menu level").push(UserMenuItem {
+            hotkey,
+            label,
+            command,
+            macros: Vec::new(),
+            conditions,
+            children: Vec::new(),
+        });
+    }
+    // Close any submenu left open at the end of the file.
+    while levels.len() > 1 {
+        let children = levels.pop().unwrap_or_default();
+        if let Some(parent) = levels.last_mut().and_then(|level| level.last_mut()) {
+            parent.children = children;
+        }
+    }
+    let mut items = levels.pop().unwrap_or_default();
+    finish_items(&mut items);
+    items
+}
+
+fn parse_entry_header(line: &str) -> (Option<char>, String, String) {
+    let mut chars = line.chars();
+    if let (Some(key), Some(':')) = (chars.next(), chars.next()) {
+        let rest = chars.as_str();
+        if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+            return (Some(key), rest.trim().to_string(), String::new());
+        }
+    }
+    if let Some((label, command)) = line.split_once('|') {
+        return (None, label.trim().to_string(), command.trim().to_string());
+    }
+    (None, line.to_string(), String::new())
+}
+
+fn parse_condition(spec: &str) -> Option<MenuCondition> {
+    let mut words = spec.split_whitespace();
+    match words.next()? {
+        "f" => Some(MenuCondition::FileMatches(words.map(|w| w.to_string()).collect())),
+        "d" => Some(MenuCondition::IsDir),
+        "!d" => Some(MenuCondition::IsFile),
+        _ => None,
+    }
+}
+
+fn finish_items(items: &mut [UserMenuItem]) {
+    for item in items {
+        item.macros = parse_macros(&item.command);
+        finish_items(&mut item.children);
+    }
+}
+
+/// Drop entries whose conditions don't hold for `entry`, including inside submenus.
+pub fn filter_user_menu(items: &[UserMenuItem], entry: Option<&Entry>) -> Vec<UserMenuItem> {
+    items
+        .iter()
+        .filter(|item| item.conditions.iter().all(|cond| condition_holds(cond, entry)))
+        .map(|item| UserMenuItem {
+            children: filter_user_menu(&item.children, entry),
+            ..item.clone()
+        })
+        .filter(|item| item.is_submenu() || !item.command.is_empty())
+        .collect()
+}
+
+fn condition_holds(cond: &MenuCondition, entry: Option<&Entry>) -> bool {
+    let Some(entry) = entry else { return false };
+    match cond {
+        MenuCondition::FileMatches(masks) => masks.iter().any(|mask| wildcard_match(mask, &entry.name)),
+        MenuCondition::IsDir => entry.is_dir,
+        MenuCondition::IsFile => !entry.is_dir,
+    }
+}
+
+pub fn parse_macros(command: &str) -> Vec<MenuMacro> {
+    let mut macros = Vec::new();
+    let mut text = String::new();
+    let chars: Vec<char> = command.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let rest = &chars[i..];
+        let (token, consumed) = match rest {
+            ['!', '!', ..] => (MenuMacro::Text("!".to_string()), 2),
+            ['!', '.', '!', ..] => (MenuMacro::FileName, 3),
+            // NC writes the full directory as `!:!\`; there are no drives here.
+            ['!', ':', '!', '\\', ..] => (MenuMacro::CurrentDirSlash, 4),
+            ['!', ':', ..] => (MenuMacro::CurrentDir, 2),
+            ['!', '\\', ..] => (MenuMacro::CurrentDirSlash, 2),
+            ['!', '?', ..] => match parse_input(&rest[2..]) {
+                Some((token, used)) => (token, used + 2),
+                None => (MenuMacro::Text("!?".to_string()), 2),
+            },
+            ['!', ..] => (MenuMacro::FileStem, 1),
+            ['%', '%', ..] => (MenuMacro::Text("%".to_string()), 2),
+            ['%', 'f', ..] => (MenuMacro::FileName, 2),
+            ['%', 'd', ..] => (MenuMacro::CurrentDir, 2),
+            ['%', 's', ..] => (MenuMacro::Selected, 2),
+            ['%', 'D', ..] => (MenuMacro::OtherDir, 2),
+            ['%', 'F', ..] => (MenuMacro::OtherFile, 2),
+            [ch, ..] => {
+                text.push(*ch);
+                i += 1;
+                continue;
+            }
+            [] => break,
+        };
+        match token {
+            MenuMacro::Text(literal) => text.push_str(&literal),
+            token => {
+                if !text.is_empty() {
+                    macros.push(MenuMacro::Text(std::mem::take(&mut text)));
+                }
+                macros.push(token);
+            }
+        }
+        i += consumed;
+    }
+    if !text.is_empty() {
+        macros.push(MenuMacro::Text(text));
+    }
+    macros
+}
+
+/// Parse `prompt?default!` (the part after `!?`), returning the token and the
+/// number of characters used.
+fn parse_input(chars: &[char]) -> Option<(MenuMacro, usize)> {
+    let prompt_end = chars.iter().position(|&c| c == '?')?;
+    let default_end = chars[prompt_end + 1..].iter().position(|&c| c == '!')? + prompt_end + 1;
+    let prompt: String = chars[..prompt_end].iter().collect();
+    let default: String = chars[prompt_end + 1..default_end].iter().collect();
+    Some((MenuMacro::Input { prompt, default }, default_end + 1))
+}
+
+/// Expand `macros` into a shell command. `answers` holds the replies to the
+/// `!?prompt?!` placeholders asked so far; the first unanswered one is returned
+/// as `NeedInput`.
+pub fn expand_macros(macros: &[MenuMacro], ctx: &MacroContext, answers: &[String]) -> Expansion {
+    let mut out = String::new();
+    let mut asked = 0;
+    for token in macros {
+        match token {
+            MenuMacro::Text(text) => out.push_str(text),
+            MenuMacro::FileName => {
+                if let Some(name) = &ctx.current_file {
+                    out.push_str(&shell_quote(name));
+                }
+            }
+            MenuMacro::FileStem => {
+                if let Some(name) = &ctx.current_file {
+                    let stem = Path::new(name).with_extension("");
+                    out.push_str(&shell_quote(&stem.to_string_lossy()));
+                }
+            }
+            MenuMacro::CurrentDir => out.push_str(&shell_quote(&ctx.current_dir.to_string_lossy())),
+            MenuMacro::CurrentDirSlash => {
+                let dir = ctx.current_dir.to_string_lossy();
+                out.push_str(&shell_quote(&dir));
+                if !dir.ends_with('/') {
+                    out.push('/');
+                }
+            }
+            MenuMacro::Selected => {
+                let quoted: Vec<String> = ctx.selected.iter().map(|name| shell_quote(name)).collect();
+                out.push_str(&quoted.join(" "));
+            }
+            MenuMacro::OtherDir => out.push_str(&shell_quote(&ctx.other_dir.to_string_lossy())),
+            MenuMacro::OtherFile => {
+                if let Some(name) = &ctx.other_file {
+                    out.push_str(&shell_quote(name));
+                }
+            }
+            MenuMacro::Input { prompt, default } => {
+                let Some(answer) = answers.get(asked) else {
+                    return Expansion::NeedInput { prompt: prompt.clone(), default: default.clone() };
+                };
+                out.push_str(answer);
+                asked += 1;
+            }
+        }
+    }
+    Expansion::Ready(out)
+}
+
+/// Quote `value` for `sh` only when it contains characters the shell would interpret.
+pub fn shell_quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./+,:@=%".contains(c));
+    if safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
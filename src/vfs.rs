@@ -1,98 +1,675 @@
 #![forbid(unsafe_code)]
 
 use std::collections::HashSet;
+use std::fmt;
 use std::fs;
-use std::io::{self, Read};
-use std::path::{Path, PathBuf};
-
-use zip::ZipArchive;
-
-use crate::model::{Entry, VfsState};
-
-pub fn read_zip_entries(vfs: &VfsState, show_hidden: bool) -> io::Result<Vec<Entry>> {
-    let file = fs::File::open(&vfs.zip_path)?;
-    let mut archive = ZipArchive::new(file)?;
-    let prefix = vfs.prefix.as_str();
-    let mut entries = Vec::new();
-    let mut seen_dirs: HashSet<String> = HashSet::new();
-    for i in 0..archive.len() {
-        let file = archive.by_index(i)?;
-        let name = file.name().to_string();
-        if !name.starts_with(prefix) {
-            continue;
-        }
-        let rest = &name[prefix.len()..];
-        if rest.is_empty() {
-            continue;
-        }
-        let parts: Vec<&str> = rest.split('/').collect();
-        let is_dir = file.is_dir() || rest.ends_with('/');
-        if parts.len() > 1 {
-            let dir_name = parts[0].to_string();
-            if !show_hidden && dir_name.starts_with('.') {
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use time::{OffsetDateTime, UtcOffset};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::fs_ops::Progress;
+use crate::model::{Entry, Mount};
+use crate::tar::{TarFs, TAR_SUFFIXES};
+use crate::viewer::ViewSource;
+
+/// A filesystem a pane can browse: the local disk, or an archive mounted on
+/// top of it. Paths are relative to the filesystem's root, except on the
+/// local disk where they are ordinary absolute paths.
+pub trait Vfs: fmt::Debug + Send + Sync {
+    /// How panel titles and dialogs name this filesystem; empty for the local disk.
+    fn name(&self) -> String;
+    /// Everything directly inside `dir`, hidden entries included, unsorted.
+    fn list(&self, dir: &Path) -> io::Result<Vec<Entry>>;
+    fn stat(&self, path: &Path) -> io::Result<Entry>;
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>>;
+    /// Create or replace the file at `path`; it is only stored on `finish`.
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn VfsWrite>>;
+    fn mkdir(&self, path: &Path) -> io::Result<()>;
+    /// Remove a file, or a directory with everything in it.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// The directory above `dir`, or `None` at the root.
+    fn parent(&self, dir: &Path) -> Option<PathBuf>;
+
+    /// Remove several files or directories; archives rewrite themselves once
+    /// for all of them.
+    fn remove_all(&self, paths: &[PathBuf]) -> io::Result<()> {
+        paths.iter().try_for_each(|path| self.remove(path))
+    }
+
+    /// Store local files and directories, each paired with the path it gets
+    /// here. Existing files of the same name are replaced; callers settle
+    /// collisions with the user first.
+    fn add_files(&self, files: &[(PathBuf, PathBuf)], progress: &mut dyn Progress) -> io::Result<()> {
+        for (source, path) in files {
+            if source.is_dir() {
+                self.mkdir(path)?;
                 continue;
             }
-            if seen_dirs.insert(dir_name.clone()) {
-                let path = PathBuf::from(dir_name.clone());
-                let is_system = dir_name.starts_with('.');
-                entries.push(Entry {
-                    name: dir_name,
-                    path,
-                    is_dir: true,
-                    size: 0,
-                    modified: None,
-                    is_system,
+            progress.file(source)?;
+            let mut writer = self.open_write(path)?;
+            let copied = io::copy(&mut fs::File::open(source)?, &mut writer)?;
+            writer.finish()?;
+            progress.bytes(copied)?;
+        }
+        Ok(())
+    }
+
+    /// Err when changes made here cannot be stored.
+    fn check_writable(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether `open_read` returns without first decoding a large part of
+    /// the archive, so it is fine to call while drawing.
+    fn reads_quickly(&self) -> bool {
+        true
+    }
+
+    /// Random access for the viewer. Archives unpack the entry to a temp file.
+    fn view_source(&self, path: &Path) -> io::Result<ViewSource> {
+        Ok(ViewSource::Unpacked(spool(&mut self.open_read(path)?)?))
+    }
+}
+
+/// A file being written through a `Vfs`.
+pub trait VfsWrite: Write {
+    /// Store what was written and report whether that worked.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl VfsWrite for fs::File {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.sync_all()
+    }
+}
+
+/// Names of archives Enter can open like a directory.
+pub fn is_archive(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".zip") || TAR_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Open the archive stored in the local file `file`. `name` is what the
+/// panel title shows for it; `keep` holds the temp dir when `file` was
+/// extracted from an outer archive.
+pub fn open_archive(file: &Path, name: String, keep: Option<Arc<ExtractDir>>) -> io::Result<Arc<dyn Vfs>> {
+    if !file.to_string_lossy().to_lowercase().ends_with(".zip") {
+        return Ok(Arc::new(TarFs::open(file, name, keep)?));
+    }
+    let zip = ZipFs { path: file.to_path_buf(), name, keep };
+    zip.archive()?;
+    Ok(Arc::new(zip))
+}
+
+/// The local disk.
+#[derive(Debug)]
+pub struct LocalFs;
+
+fn local_entry(path: PathBuf, metadata: &fs::Metadata) -> Entry {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    Entry {
+        is_system: name.starts_with('.'),
+        name,
+        link_target: if metadata.is_symlink() { fs::read_link(&path).ok() } else { None },
+        path,
+        is_dir: metadata.is_dir(),
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+        mode: Some(metadata.permissions().mode() & 0o7777),
+    }
+}
+
+impl Vfs for LocalFs {
+    fn name(&self) -> String {
+        String::new()
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for item in fs::read_dir(dir)? {
+            let item = item?;
+            entries.push(local_entry(item.path(), &item.metadata()?));
+        }
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Entry> {
+        Ok(local_entry(path.to_path_buf(), &fs::metadata(path)?))
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn VfsWrite>> {
+        Ok(Box::new(fs::File::create(path)?))
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn parent(&self, dir: &Path) -> Option<PathBuf> {
+        dir.parent().map(Path::to_path_buf)
+    }
+
+    fn view_source(&self, path: &Path) -> io::Result<ViewSource> {
+        Ok(ViewSource::File(fs::File::open(path)?))
+    }
+}
+
+/// A zip archive. Directories without an entry of their own are implied by
+/// the names of the files inside them. Every change rewrites the whole
+/// archive through `rewrite_zip`.
+#[derive(Debug)]
+pub struct ZipFs {
+    path: PathBuf,
+    name: String,
+    keep: Option<Arc<ExtractDir>>,
+}
+
+/// The name prefix shared by everything inside `dir`: `""` or `"a/b/"`.
+fn zip_prefix(dir: &Path) -> String {
+    let dir = dir.to_string_lossy();
+    if dir.is_empty() { String::new() } else { format!("{}/", dir.trim_end_matches('/')) }
+}
+
+fn zip_time(time: Option<DateTime>) -> Option<SystemTime> {
+    // Zip stores local time without a zone.
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    let time = OffsetDateTime::try_from(time?).ok()?;
+    Some(time.replace_offset(offset).into())
+}
+
+/// The other direction of `zip_time`.
+fn to_zip_time(time: SystemTime) -> Option<DateTime> {
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    DateTime::try_from(OffsetDateTime::from(time).to_offset(offset)).ok()
+}
+
+/// The entry name for a new file at `path`, refusing anything that is not a
+/// plain relative path.
+fn zip_name(path: &Path) -> io::Result<String> {
+    let mut parts = Vec::new();
+    for part in path.components() {
+        let Component::Normal(part) = part else {
+            let message = format!("{}: not a valid name in an archive", path.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        };
+        parts.push(part.to_string_lossy());
+    }
+    if parts.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty name"));
+    }
+    Ok(parts.join("/"))
+}
+
+/// Options for storing the local file described by `metadata`.
+pub(crate) fn local_file_options(metadata: &fs::Metadata) -> SimpleFileOptions {
+    let modified = metadata.modified().ok().and_then(to_zip_time);
+    SimpleFileOptions::default()
+        .last_modified_time(modified.unwrap_or_else(DateTime::default_for_write))
+        .unix_permissions(metadata.permissions().mode() & 0o7777)
+        .large_file(metadata.len() >= u32::MAX as u64)
+}
+
+fn zip_dir_entry(path: &str) -> Entry {
+    let name = path.rsplit('/').next().unwrap_or(path).to_string();
+    Entry {
+        is_system: name.starts_with('.'),
+        name,
+        path: PathBuf::from(path),
+        is_dir: true,
+        size: 0,
+        modified: None,
+        mode: None,
+        link_target: None,
+    }
+}
+
+pub(crate) fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "archive is read-only")
+}
+
+impl ZipFs {
+    fn archive(&self) -> io::Result<ZipArchive<fs::File>> {
+        Ok(ZipArchive::new(fs::File::open(&self.path)?)?)
+    }
+
+}
+
+impl Vfs for ZipFs {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// A zip opened from inside another archive is a temp copy; changes to
+    /// it would be thrown away with the copy.
+    fn check_writable(&self) -> io::Result<()> {
+        match self.keep {
+            Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "archive inside an archive is read-only")),
+            None => Ok(()),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<Entry>> {
+        let prefix = zip_prefix(dir);
+        let mut archive = self.archive()?;
+        let mut entries = Vec::new();
+        let mut seen_dirs: HashSet<String> = HashSet::new();
+        for idx in 0..archive.len() {
+            let file = archive.by_index_raw(idx)?;
+            let Some(rest) = file.name().strip_prefix(prefix.as_str()) else { continue };
+            match rest.split_once('/') {
+                Some((name, _)) if !name.is_empty() && seen_dirs.insert(name.to_string()) => {
+                    entries.push(zip_dir_entry(&format!("{prefix}{name}")));
+                }
+                None if !rest.is_empty() => {
+                    entries.push(Entry {
+                        name: rest.to_string(),
+                        path: PathBuf::from(file.name()),
+                        is_dir: false,
+                        size: file.size(),
+                        modified: zip_time(file.last_modified()),
+                        is_system: rest.starts_with('.'),
+                        mode: file.unix_mode().map(|mode| mode & 0o7777),
+                        link_target: None,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Entry> {
+        let name = path.to_string_lossy();
+        if name.is_empty() {
+            return Ok(zip_dir_entry(""));
+        }
+        let prefix = zip_prefix(path);
+        let mut archive = self.archive()?;
+        for idx in 0..archive.len() {
+            let file = archive.by_index_raw(idx)?;
+            if file.name() == name {
+                let file_name = name.rsplit('/').next().unwrap_or(&name).to_string();
+                return Ok(Entry {
+                    is_system: file_name.starts_with('.'),
+                    name: file_name,
+                    path: path.to_path_buf(),
+                    is_dir: false,
+                    size: file.size(),
+                    modified: zip_time(file.last_modified()),
+                    mode: file.unix_mode().map(|mode| mode & 0o7777),
+                    link_target: None,
                 });
             }
-            continue;
-        }
-        let base = parts[0].to_string();
-        if base.is_empty() {
-            continue;
-        }
-        if !show_hidden && base.starts_with('.') {
-            continue;
-        }
-        let is_system = base.starts_with('.');
-        entries.push(Entry {
-            name: base.clone(),
-            path: PathBuf::from(base),
-            is_dir,
-            size: file.size(),
-            modified: None,
-            is_system,
-        });
-    }
-    entries.sort_by(|a, b| match (a.is_dir, b.is_dir) {
-        (true, false) => std::cmp::Ordering::Less,
-        (false, true) => std::cmp::Ordering::Greater,
-        _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            if file.name().starts_with(&prefix) {
+                return Ok(zip_dir_entry(&name));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("{name}: not in archive")))
+    }
+
+    /// Stored and deflated entries are read straight from the archive file;
+    /// other methods are unpacked to a temp file first.
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        let mut archive = self.archive()?;
+        let mut file = archive.by_name(&path.to_string_lossy())?;
+        let method = file.compression();
+        if !matches!(method, CompressionMethod::Stored | CompressionMethod::Deflated) {
+            return Ok(Box::new(spool(&mut file)?));
+        }
+        let (start, size) = (file.data_start(), file.compressed_size());
+        drop(file);
+        let mut reader = archive.into_inner();
+        reader.seek(SeekFrom::Start(start))?;
+        let data = BufReader::new(reader).take(size);
+        Ok(match method {
+            CompressionMethod::Stored => Box::new(data),
+            _ => Box::new(flate2::read::DeflateDecoder::new(data)),
+        })
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn VfsWrite>> {
+        self.check_writable()?;
+        Ok(Box::new(ZipEntryWriter {
+            zip_path: self.path.clone(),
+            name: path.to_string_lossy().to_string(),
+            data: spool_file()?,
+        }))
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        self.check_writable()?;
+        let name = format!("{}/", zip_name(path)?);
+        match self.stat(path) {
+            Ok(entry) if entry.is_dir => return Ok(()),
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{}: file exists", path.display()))),
+            Err(_) => {}
+        }
+        let options = SimpleFileOptions::default().last_modified_time(DateTime::default_for_write());
+        rewrite_zip(&self.path, |archive, writer| {
+            copy_entries(archive, writer, |name| Some(name.to_string()))?;
+            writer.add_directory(name, options)?;
+            Ok(())
+        })
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        self.remove_all(&[path.to_path_buf()])
+    }
+
+    fn remove_all(&self, paths: &[PathBuf]) -> io::Result<()> {
+        self.check_writable()?;
+        // `zip_name` refuses an empty path, whose prefix would match every entry.
+        let targets = paths.iter().map(|path| zip_name(path)).collect::<io::Result<Vec<_>>>()?;
+        let prefixes: Vec<String> = targets.iter().map(|target| format!("{target}/")).collect();
+        let mut found = vec![false; targets.len()];
+        rewrite_zip(&self.path, |archive, writer| {
+            copy_entries(archive, writer, |name| {
+                let mut keep = true;
+                for (idx, (target, prefix)) in targets.iter().zip(&prefixes).enumerate() {
+                    if name == target || name.starts_with(prefix.as_str()) {
+                        found[idx] = true;
+                        keep = false;
+                    }
+                }
+                keep.then(|| name.to_string())
+            })?;
+            match found.iter().position(|found| !found) {
+                Some(idx) => Err(io::Error::new(io::ErrorKind::NotFound, format!("{}: not in archive", targets[idx]))),
+                None => Ok(()),
+            }
+        })
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check_writable()?;
+        let to_name = zip_name(to)?;
+        if self.stat(to).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{to_name}: already exists")));
+        }
+        let from_name = zip_name(from)?;
+        let (from_prefix, to_prefix) = (zip_prefix(from), format!("{to_name}/"));
+        rewrite_zip(&self.path, |archive, writer| {
+            let renamed = copy_entries(archive, writer, |name| {
+                if name == from_name {
+                    return Some(to_name.clone());
+                }
+                match name.strip_prefix(from_prefix.as_str()) {
+                    Some(rest) => Some(format!("{to_prefix}{rest}")),
+                    None => Some(name.to_string()),
+                }
+            })?;
+            match renamed {
+                0 => Err(io::Error::new(io::ErrorKind::NotFound, format!("{from_name}: not in archive"))),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn parent(&self, dir: &Path) -> Option<PathBuf> {
+        if dir.as_os_str().is_empty() {
+            return None;
+        }
+        Some(dir.parent().map(Path::to_path_buf).unwrap_or_default())
+    }
+
+    /// Everything goes in with a single rewrite instead of one per file.
+    fn add_files(&self, files: &[(PathBuf, PathBuf)], progress: &mut dyn Progress) -> io::Result<()> {
+        self.check_writable()?;
+        let mut added = Vec::with_capacity(files.len());
+        for (source, path) in files {
+            let metadata = fs::metadata(source)?;
+            let name = if metadata.is_dir() { format!("{}/", zip_name(path)?) } else { zip_name(path)? };
+            added.push((source, name, metadata));
+        }
+        let replaced: HashSet<&str> = added.iter().map(|(_, name, _)| name.as_str()).collect();
+        rewrite_zip(&self.path, |archive, writer| {
+            copy_entries(archive, writer, |name| (!replaced.contains(name)).then(|| name.to_string()))?;
+            let mut buf = vec![0u8; 64 * 1024];
+            for (source, name, metadata) in &added {
+                let options = local_file_options(metadata);
+                if metadata.is_dir() {
+                    writer.add_directory(name.as_str(), options)?;
+                    continue;
+                }
+                progress.file(source)?;
+                writer.start_file(name.as_str(), options)?;
+                let mut file = fs::File::open(source)?;
+                loop {
+                    let read = file.read(&mut buf)?;
+                    if read == 0 {
+                        break;
+                    }
+                    writer.write_all(&buf[..read])?;
+                    progress.bytes(read as u64)?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Collects a zip entry's new contents and rewrites the archive on `finish`.
+/// Collects what is written in a temp file until `finish` stores it.
+struct ZipEntryWriter {
+    zip_path: PathBuf,
+    name: String,
+    data: fs::File,
+}
+
+impl Write for ZipEntryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl VfsWrite for ZipEntryWriter {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.data.rewind()?;
+        replace_zip_entry(&self.zip_path, &self.name, &mut self.data)
+    }
+}
+
+/// A private (mode 0700) directory under the system temp dir for files
+/// extracted from an archive. Dropping it removes the directory and
+/// everything in it.
+#[derive(Debug)]
+pub struct ExtractDir {
+    pub path: PathBuf,
+}
+
+impl ExtractDir {
+    pub fn create() -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let name = format!("frankencommander-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+            let path = std::env::temp_dir().join(name);
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for ExtractDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+impl Mount {
+    pub fn new(fs: Arc<dyn Vfs>) -> Self {
+        Self { fs, dir: PathBuf::new(), extract_dir: None }
+    }
+
+    /// Copy the file at `path` out to this mount's private temp dir, keeping
+    /// its place in the archive's tree. Returns the local copy.
+    pub fn extract(&mut self, path: &Path) -> io::Result<PathBuf> {
+        if !path.components().all(|part| matches!(part, Component::Normal(_))) {
+            let message = format!("unsafe path in archive: {}", path.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        let mut input = self.fs.open_read(path)?;
+        let dir = match &self.extract_dir {
+            Some(dir) => Arc::clone(dir),
+            None => self.extract_dir.insert(Arc::new(ExtractDir::create()?)).clone(),
+        };
+        let target = dir.path.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut input, &mut fs::File::create(&target)?)?;
+        Ok(target)
+    }
+}
+
+/// Rewrite `zip_path` with entry `name` holding `data`, copying every other
+/// entry over unchanged.
+pub fn replace_zip_entry(zip_path: &Path, name: &str, data: &mut fs::File) -> io::Result<()> {
+    let len = data.metadata()?.len();
+    rewrite_zip(zip_path, |archive, writer| {
+        let mut options = SimpleFileOptions::default()
+            .last_modified_time(DateTime::default_for_write())
+            .large_file(len >= u32::MAX as u64);
+        for idx in 0..archive.len() {
+            let file = archive.by_index_raw(idx)?;
+            if file.name() != name {
+                continue;
+            }
+            if file.compression() == CompressionMethod::Stored {
+                options = options.compression_method(CompressionMethod::Stored);
+            }
+            if let Some(mode) = file.unix_mode() {
+                options = options.unix_permissions(mode);
+            }
+        }
+        copy_entries(archive, writer, |other| (other != name).then(|| other.to_string()))?;
+        writer.start_file(name, options)?;
+        io::copy(data, writer)?;
+        Ok(())
+    })
+}
+
+/// Replace `zip_path` with the archive `build` writes, given the current
+/// one to read from. The new archive is written and synced next to the old
+/// one and then renamed over it, so neither a failure nor a crash can leave
+/// the original half-written.
+fn rewrite_zip(
+    zip_path: &Path,
+    build: impl FnOnce(&mut ZipArchive<fs::File>, &mut ZipWriter<fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut archive = ZipArchive::new(fs::File::open(zip_path)?)?;
+    let (temp_path, temp) = create_temp_beside(zip_path)?;
+    let result = write_rewritten(&mut archive, temp, build).and_then(|()| {
+        fs::set_permissions(&temp_path, fs::metadata(zip_path)?.permissions())?;
+        fs::rename(&temp_path, zip_path)
     });
-    Ok(entries)
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_rewritten(
+    archive: &mut ZipArchive<fs::File>,
+    temp: fs::File,
+    build: impl FnOnce(&mut ZipArchive<fs::File>, &mut ZipWriter<fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut writer = ZipWriter::new(temp);
+    build(archive, &mut writer)?;
+    writer.finish()?.sync_all()
 }
 
-pub fn read_zip_file_lines(vfs: &VfsState, entry_path: &Path) -> io::Result<Vec<String>> {
-    let file = fs::File::open(&vfs.zip_path)?;
-    let mut archive = ZipArchive::new(file)?;
-    let full = format!("{}{}", vfs.prefix, entry_path.to_string_lossy());
-    let mut zip_file = archive.by_name(&full)?;
-    let mut data = Vec::new();
-    zip_file.read_to_end(&mut data)?;
-    let content = String::from_utf8_lossy(&data);
-    Ok(content.lines().map(|line| line.to_string()).collect())
+/// A new hidden file next to `path`, named so that two rewrites running at
+/// once never share one.
+pub(crate) fn create_temp_beside(path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    loop {
+        let temp_name = format!(".{file_name}.{}-{}.tmp", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let temp_path = path.with_file_name(temp_name);
+        match fs::OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
 }
 
-pub fn zip_parent_prefix(prefix: &str) -> Option<String> {
-    let trimmed = prefix.trim_end_matches('/');
-    let parent = Path::new(trimmed).parent()?.to_string_lossy().to_string();
-    if parent.is_empty() {
-        Some(String::new())
-    } else {
-        Some(format!("{}/", parent))
+/// A private temp file that is already unlinked, so it goes away with the
+/// handle. Holds archive data too large to keep in memory.
+fn spool_file() -> io::Result<fs::File> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let name = format!("frankencommander-{}-{}.spool", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        match fs::OpenOptions::new().read(true).write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
     }
 }
 
-pub fn zip_child_prefix(prefix: &str, entry_path: &Path) -> String {
-    let child = entry_path.to_string_lossy();
-    format!("{}{}/", prefix, child)
+/// Copy `reader` to a `spool_file`, ready to be read from the start.
+fn spool(reader: &mut dyn Read) -> io::Result<fs::File> {
+    let mut file = spool_file()?;
+    io::copy(reader, &mut file)?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Copy the entries of `archive` into `writer` without recompressing them,
+/// each under the name `rename` maps it to; `None` leaves an entry out.
+/// Returns how many entries were renamed or left out.
+fn copy_entries(
+    archive: &mut ZipArchive<fs::File>,
+    writer: &mut ZipWriter<fs::File>,
+    mut rename: impl FnMut(&str) -> Option<String>,
+) -> io::Result<usize> {
+    let mut changed = 0;
+    for idx in 0..archive.len() {
+        let file = archive.by_index_raw(idx)?;
+        match rename(file.name()) {
+            Some(name) if name == file.name() => writer.raw_copy_file(file)?,
+            Some(name) => {
+                writer.raw_copy_file_rename(file, name)?;
+                changed += 1;
+            }
+            None => changed += 1,
+        }
+    }
+    Ok(changed)
 }
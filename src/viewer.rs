@@ -0,0 +1,881 @@
+#![forbid(unsafe_code)]
+
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::PathBuf;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::encoding::Encoding;
+use crate::model::{SearchHit, ViewMode, Viewer, ViewerSearch};
+
+/// Bytes per row of the hex dump.
+pub const HEX_ROW: usize = 16;
+
+/// Column of the ASCII part in a hex dump row.
+const HEX_ASCII_COLUMN: usize = 10 + HEX_ROW * 3 + 2;
+
+/// How much is read at a time when scanning forward.
+const CHUNK: usize = 64 * 1024;
+
+/// How much is read at a time when scanning backward for a line start.
+const BACK_CHUNK: usize = 4 * 1024;
+
+/// Longest prefix of a line that is decoded for display and search.
+const MAX_LINE: usize = 64 * 1024;
+
+/// The line index remembers the start of every this many lines.
+const INDEX_STEP: u64 = 1024;
+
+/// How much more of the file the line index covers per tick.
+const INDEX_BUDGET: u64 = 4 * 1024 * 1024;
+
+/// What the viewer reads from, on demand: a local file, or an archive entry
+/// unpacked to a temp file.
+#[derive(Debug)]
+pub enum ViewSource {
+    File(fs::File),
+    Unpacked(fs::File),
+}
+
+impl ViewSource {
+    pub fn size(&self) -> io::Result<u64> {
+        match self {
+            ViewSource::File(file) | ViewSource::Unpacked(file) => Ok(file.metadata()?.len()),
+        }
+    }
+
+    /// Up to `len` bytes at `offset`; fewer at the end of the data or on a read error.
+    pub fn read_at(&self, offset: u64, len: usize) -> Vec<u8> {
+        match self {
+            ViewSource::File(file) | ViewSource::Unpacked(file) => {
+                let mut buf = vec![0; len];
+                let mut filled = 0;
+                while filled < len {
+                    match file.read_at(&mut buf[filled..], offset + filled as u64) {
+                        Ok(0) => break,
+                        Ok(read) => filled += read,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    }
+                }
+                buf.truncate(filled);
+                buf
+            }
+        }
+    }
+}
+
+/// Sparse map from line numbers to file offsets, extended only as far as a
+/// caller asks for.
+#[derive(Debug, Default)]
+pub struct LineIndex {
+    /// Offset of line 0, `INDEX_STEP`, `2 * INDEX_STEP`, ...
+    checkpoints: Vec<u64>,
+    /// Bytes scanned so far.
+    scanned: u64,
+    /// Newlines seen before `scanned`.
+    newlines: u64,
+}
+
+impl LineIndex {
+    /// Scan at least up to `target` (or the end of the data).
+    fn extend_to(&mut self, viewer: &Viewer, target: u64) {
+        if self.checkpoints.is_empty() {
+            self.checkpoints.push(0);
+        }
+        let newline = viewer.encoding.newline();
+        while self.scanned < target.min(viewer.len) {
+            let chunk = viewer.source.read_at(self.scanned, CHUNK);
+            if chunk.is_empty() {
+                break;
+            }
+            for idx in newlines(&chunk, newline) {
+                self.newlines += 1;
+                if self.newlines.is_multiple_of(INDEX_STEP) {
+                    self.checkpoints.push(self.scanned + (idx + newline.len()) as u64);
+                }
+            }
+            self.scanned += chunk.len() as u64;
+        }
+    }
+
+    /// Zero-based number of the line containing `offset`.
+    fn line_of(&mut self, viewer: &Viewer, offset: u64) -> u64 {
+        self.extend_to(viewer, offset);
+        let step = self.checkpoints.partition_point(|&start| start <= offset).saturating_sub(1);
+        let mut line = step as u64 * INDEX_STEP;
+        let mut pos = self.checkpoints[step];
+        while pos < offset {
+            let chunk = viewer.source.read_at(pos, CHUNK.min((offset - pos) as usize));
+            if chunk.is_empty() {
+                break;
+            }
+            line += newlines(&chunk, viewer.encoding.newline()).count() as u64;
+            pos += chunk.len() as u64;
+        }
+        line
+    }
+
+    /// Offset where zero-based line `line` starts, if the data has that many lines.
+    fn line_start(&mut self, viewer: &Viewer, line: u64) -> Option<u64> {
+        while self.newlines < line && self.scanned < viewer.len {
+            let target = self.scanned + CHUNK as u64;
+            self.extend_to(viewer, target);
+        }
+        if self.checkpoints.is_empty() {
+            self.checkpoints.push(0);
+        }
+        let step = ((line / INDEX_STEP) as usize).min(self.checkpoints.len() - 1);
+        let mut pos = self.checkpoints[step];
+        for _ in step as u64 * INDEX_STEP..line {
+            pos = viewer.next_line_start(pos)?;
+        }
+        Some(pos)
+    }
+}
+
+/// Offsets of the line breaks in `chunk`, which starts on a code unit boundary.
+fn newlines<'a>(chunk: &'a [u8], newline: &'a [u8]) -> impl DoubleEndedIterator<Item = usize> + 'a {
+    let unit = newline.len();
+    chunk.chunks_exact(unit).enumerate().filter(move |(_, code)| *code == newline).map(move |(idx, _)| idx * unit)
+}
+
+const TAB_WIDTH: usize = 8;
+
+pub fn expand_tabs(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    for ch in line.chars() {
+        if ch == '\t' {
+            let pad = TAB_WIDTH - out.chars().count() % TAB_WIDTH;
+            out.extend(std::iter::repeat_n(' ', pad));
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Screen column of byte offset `byte` in `line` once tabs are expanded.
+pub fn display_column(line: &str, byte: usize) -> usize {
+    line[..byte]
+        .chars()
+        .fold(0, |col, ch| if ch == '\t' { col + TAB_WIDTH - col % TAB_WIDTH } else { col + 1 })
+}
+
+/// Screen rows a line of `columns` expanded characters takes when wrapped.
+fn wrapped_rows(columns: usize, width: usize) -> usize {
+    columns.div_ceil(width).max(1)
+}
+
+/// One screen row of the text view.
+#[derive(Debug)]
+pub struct TextRow {
+    /// Start of the line the row belongs to.
+    pub line: u64,
+    /// What is shown: tabs expanded, cut to the screen width.
+    pub text: String,
+    /// Search matches as column ranges of `text`, flagged when it is the current hit.
+    pub marks: Vec<(usize, usize, bool)>,
+}
+
+/// Lines of a viewer from some offset on, read a chunk at a time.
+pub struct Lines<'a> {
+    viewer: &'a Viewer,
+    pos: u64,
+    buf_start: u64,
+    buf: Vec<u8>,
+}
+
+impl Iterator for Lines<'_> {
+    /// Offset where the line starts and its text.
+    type Item = (u64, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.pos >= self.viewer.len {
+                return None;
+            }
+            let rel = (self.pos - self.buf_start) as usize;
+            let newline = self.viewer.encoding.newline();
+            if let Some(end) = self.buf.get(rel..).and_then(|rest| newlines(rest, newline).next()) {
+                let line = (self.pos, self.viewer.decode_line(&self.buf[rel..rel + end]));
+                self.pos += (end + newline.len()) as u64;
+                return Some(line);
+            }
+            // A followed file may have grown past the length seen so far.
+            self.buf.truncate((self.viewer.len - self.buf_start) as usize);
+            let buf_end = self.buf_start + self.buf.len() as u64;
+            if buf_end >= self.viewer.len {
+                let line = (self.pos, self.viewer.decode_line(&self.buf[rel.min(self.buf.len())..]));
+                self.pos = self.viewer.len;
+                return Some(line);
+            }
+            if rel == 0 && !self.buf.is_empty() {
+                // Longer than a chunk: take its start and skip to the next line.
+                let line = (self.pos, self.viewer.line_text(self.pos));
+                self.pos = self.viewer.next_line_start(self.pos).unwrap_or(self.viewer.len);
+                self.buf_start = self.pos;
+                self.buf = self.viewer.source.read_at(self.pos, CHUNK);
+                return Some(line);
+            }
+            self.buf_start = self.pos;
+            self.buf = self.viewer.source.read_at(self.pos, CHUNK);
+            if self.buf.is_empty() {
+                return None;
+            }
+        }
+    }
+}
+
+/// A viewer search ready to run: the dialog settings plus the compiled regex
+/// or byte pattern.
+#[derive(Debug, Clone)]
+pub struct Finder {
+    pub search: ViewerSearch,
+    regex: Option<Regex>,
+    bytes: Vec<u8>,
+}
+
+impl Finder {
+    pub fn new(search: ViewerSearch) -> Result<Self, String> {
+        let regex = if search.regex && !search.hex {
+            let pattern = if search.whole_word { format!(r"\b(?:{})\b", search.query) } else { search.query.clone() };
+            let regex = RegexBuilder::new(&pattern)
+                .case_insensitive(!search.case_sensitive)
+                .build()
+                .map_err(|err| err.to_string())?;
+            Some(regex)
+        } else {
+            None
+        };
+        let bytes = if search.hex { parse_hex(&search.query)? } else { search.query.as_bytes().to_vec() };
+        Ok(Self { search, regex, bytes })
+    }
+
+    /// Byte ranges of the non-overlapping matches in `line`, left to right.
+    pub fn matches(&self, line: &str) -> Vec<(usize, usize)> {
+        if let Some(regex) = &self.regex {
+            return regex
+                .find_iter(line)
+                .filter(|found| found.start() < found.end())
+                .map(|found| (found.start(), found.end()))
+                .collect();
+        }
+        let mut found = Vec::new();
+        if self.search.query.is_empty() {
+            return found;
+        }
+        let mut from = 0;
+        while from < line.len() {
+            let Some((start, end)) = self.find_text(line, from) else { break };
+            found.push((start, end));
+            from = end;
+        }
+        found
+    }
+
+    /// First plain-text match starting at or after byte `from`.
+    fn find_text(&self, line: &str, from: usize) -> Option<(usize, usize)> {
+        for (offset, _) in line[from..].char_indices() {
+            let start = from + offset;
+            let Some(end) = match_at(line, start, &self.search.query, self.search.case_sensitive) else { continue };
+            if !self.search.whole_word || is_word_boundary(line, start, end) {
+                return Some((start, end));
+            }
+        }
+        None
+    }
+
+    /// Whether the search runs over raw bytes rather than lines.
+    fn searches_bytes(&self, mode: ViewMode) -> bool {
+        self.search.hex || mode == ViewMode::Hex
+    }
+
+    /// Whether the byte pattern occurs at the start of `window`. Text queries
+    /// ignore ASCII case unless the search is case sensitive.
+    fn bytes_at(&self, window: &[u8]) -> bool {
+        let Some(window) = window.get(..self.bytes.len()) else { return false };
+        if self.search.hex || self.search.case_sensitive {
+            window == self.bytes.as_slice()
+        } else {
+            window.eq_ignore_ascii_case(&self.bytes)
+        }
+    }
+
+    /// Non-overlapping byte matches in `data`, as offsets into it.
+    pub fn byte_matches(&self, data: &[u8]) -> Vec<(usize, usize)> {
+        let mut found = Vec::new();
+        if self.bytes.is_empty() {
+            return found;
+        }
+        let mut pos = 0;
+        while pos < data.len() {
+            if self.bytes_at(&data[pos..]) {
+                found.push((pos, pos + self.bytes.len()));
+                pos += self.bytes.len();
+            } else {
+                pos += 1;
+            }
+        }
+        found
+    }
+
+    /// Length of the byte pattern.
+    pub fn byte_len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+/// Parse `DE AD be ef` or `deadbeef` into bytes.
+fn parse_hex(query: &str) -> Result<Vec<u8>, String> {
+    let digits: String = query.chars().filter(|ch| !ch.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err("Hex search needs pairs of hex digits".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&digits[idx..idx + 2], 16).map_err(|_| format!("Not a hex byte: {}", &digits[idx..idx + 2])))
+        .collect()
+}
+
+/// End of `needle` when it occurs in `line` at byte `start`.
+fn match_at(line: &str, start: usize, needle: &str, case_sensitive: bool) -> Option<usize> {
+    let mut hay = line[start..].char_indices();
+    for expected in needle.chars() {
+        let (_, actual) = hay.next()?;
+        let same = actual == expected || (!case_sensitive && actual.to_lowercase().eq(expected.to_lowercase()));
+        if !same {
+            return None;
+        }
+    }
+    Some(hay.next().map(|(offset, _)| start + offset).unwrap_or(line.len()))
+}
+
+fn is_word_boundary(line: &str, start: usize, end: usize) -> bool {
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let before = line[..start].chars().next_back().is_some_and(is_word);
+    let after = line[end..].chars().next().is_some_and(is_word);
+    !before && !after
+}
+
+/// Guess whether `data` is binary: a NUL byte, or mostly control characters,
+/// in the first few kilobytes.
+pub fn looks_binary(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(8192)];
+    if head.contains(&0) {
+        return true;
+    }
+    let control = head
+        .iter()
+        .filter(|&&byte| byte < 0x20 && !matches!(byte, b'\n' | b'\r' | b'\t' | 0x0c | 0x1b))
+        .count();
+    control * 10 > head.len()
+}
+
+/// One row of the hex dump: offset, up to 16 bytes in hex, then the printable ASCII.
+pub fn hex_row(offset: u64, bytes: &[u8]) -> String {
+    let mut out = format!("{:08X}  ", offset);
+    for idx in 0..HEX_ROW {
+        match bytes.get(idx) {
+            Some(byte) => out.push_str(&format!("{:02X} ", byte)),
+            None => out.push_str("   "),
+        }
+        if idx == HEX_ROW / 2 - 1 {
+            out.push(' ');
+        }
+    }
+    out.push(' ');
+    out.extend(bytes.iter().take(HEX_ROW).map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }));
+    out
+}
+
+/// Columns of byte `idx` of a row in the hex part and in the ASCII part.
+pub fn hex_columns(idx: usize) -> (usize, usize) {
+    let hex = 10 + idx * 3 + usize::from(idx >= HEX_ROW / 2);
+    (hex, HEX_ASCII_COLUMN + idx)
+}
+
+impl Viewer {
+    /// Open `source` in hex mode when it looks binary, as text otherwise.
+    pub fn new(path: PathBuf, source: ViewSource) -> io::Result<Self> {
+        let len = source.size()?;
+        let head = source.read_at(0, 8192);
+        let encoding = Encoding::detect(&head);
+        let mode = if encoding.unit() == 1 && looks_binary(&head) { ViewMode::Hex } else { ViewMode::Text };
+        Ok(Self {
+            path,
+            source,
+            len,
+            mode,
+            encoding,
+            top: 0,
+            wrap: false,
+            top_row: 0,
+            left: 0,
+            bookmarks: [None; 10],
+            follow: false,
+            index: RefCell::new(LineIndex::default()),
+            finder: None,
+            hit: None,
+            message: None,
+            view_height: Cell::new(0),
+            view_width: Cell::new(0),
+        })
+    }
+
+    /// Lines starting at `offset`, which should be a line start.
+    pub fn lines_from(&self, offset: u64) -> Lines<'_> {
+        Lines { viewer: self, pos: offset, buf_start: offset, buf: Vec::new() }
+    }
+
+    /// Text of one line (without its line break), cut at `MAX_LINE` bytes.
+    fn decode_line(&self, bytes: &[u8]) -> String {
+        let mut text = self.encoding.decode(&bytes[..bytes.len().min(MAX_LINE)]);
+        if text.ends_with('\r') {
+            text.pop();
+        }
+        text
+    }
+
+    /// Text of the line starting at `offset`.
+    pub fn line_text(&self, offset: u64) -> String {
+        let newline = self.encoding.newline();
+        let mut bytes = self.source.read_at(offset, BACK_CHUNK);
+        if newlines(&bytes, newline).next().is_none() && bytes.len() == BACK_CHUNK {
+            bytes = self.source.read_at(offset, MAX_LINE);
+        }
+        let end = newlines(&bytes, newline).next().unwrap_or(bytes.len());
+        self.decode_line(&bytes[..end])
+    }
+
+    /// Offset of the first line break at or after `from`.
+    fn find_newline(&self, from: u64) -> Option<u64> {
+        let newline = self.encoding.newline();
+        let mut pos = from;
+        while pos < self.len {
+            let chunk = self.source.read_at(pos, CHUNK);
+            if chunk.is_empty() {
+                return None;
+            }
+            if let Some(idx) = newlines(&chunk, newline).next() {
+                return Some(pos + idx as u64);
+            }
+            pos += chunk.len() as u64;
+        }
+        None
+    }
+
+    /// Offset of the last line break that ends before `before`.
+    fn find_newline_before(&self, before: u64) -> Option<u64> {
+        let newline = self.encoding.newline();
+        let unit = newline.len() as u64;
+        let mut end = before / unit * unit;
+        while end > 0 {
+            let start = end.saturating_sub(BACK_CHUNK as u64);
+            let chunk = self.source.read_at(start, (end - start) as usize);
+            if let Some(idx) = newlines(&chunk, newline).next_back() {
+                return Some(start + idx as u64);
+            }
+            end = start;
+        }
+        None
+    }
+
+    /// Start of the line containing `offset`.
+    pub fn line_start_before(&self, offset: u64) -> u64 {
+        self.find_newline_before(offset).map_or(0, |newline| newline + self.encoding.unit() as u64)
+    }
+
+    pub fn next_line_start(&self, offset: u64) -> Option<u64> {
+        let next = self.find_newline(offset)? + self.encoding.unit() as u64;
+        (next < self.len).then_some(next)
+    }
+
+    pub fn prev_line_start(&self, offset: u64) -> Option<u64> {
+        (offset > 0).then(|| self.line_start_before(offset - 1))
+    }
+
+    /// Zero-based number of the line containing `offset`, once the line
+    /// index has got that far.
+    pub fn known_line_of(&self, offset: u64) -> Option<u64> {
+        let mut index = self.index.borrow_mut();
+        (index.scanned >= offset.min(self.len)).then(|| index.line_of(self, offset))
+    }
+
+    /// Offset of zero-based line `line`, if there is one.
+    pub fn line_start(&self, line: u64) -> Option<u64> {
+        self.index.borrow_mut().line_start(self, line)
+    }
+
+    /// Grow the line index by another slice of the file. Returns true while
+    /// there is more to index.
+    pub fn index_more(&self) -> bool {
+        let mut index = self.index.borrow_mut();
+        let target = index.scanned + INDEX_BUDGET;
+        index.extend_to(self, target);
+        index.scanned < self.len
+    }
+
+    /// Offset of the last hex row.
+    fn last_hex_row(&self) -> u64 {
+        self.len.saturating_sub(1) / HEX_ROW as u64 * HEX_ROW as u64
+    }
+
+    /// Screen rows the line starting at `offset` takes when wrapped.
+    fn line_rows(&self, offset: u64) -> usize {
+        wrapped_rows(expand_tabs(&self.line_text(offset)).chars().count(), self.view_width.get().max(1))
+    }
+
+    /// Rows of the text view from the top of the screen down.
+    pub fn text_rows(&self) -> Vec<TextRow> {
+        let (height, width) = (self.view_height.get(), self.view_width.get().max(1));
+        let mut rows = Vec::new();
+        for (line, text) in self.lines_from(self.top) {
+            if rows.len() >= height {
+                break;
+            }
+            let expanded: Vec<char> = expand_tabs(&text).chars().collect();
+            let marks: Vec<(usize, usize, bool)> = self
+                .finder
+                .iter()
+                .flat_map(|finder| finder.matches(&text))
+                .map(|(start, end)| {
+                    let is_hit = self.hit == Some(SearchHit::Text { line, start, end });
+                    (display_column(&text, start), display_column(&text, end), is_hit)
+                })
+                .collect();
+            let columns: Vec<usize> = if self.wrap {
+                let skip = if line == self.top { self.top_row } else { 0 };
+                (skip..wrapped_rows(expanded.len(), width)).map(|row| row * width).collect()
+            } else {
+                vec![self.left]
+            };
+            for column in columns.into_iter().take(height - rows.len()) {
+                let end = (column + width).min(expanded.len());
+                rows.push(TextRow {
+                    line,
+                    text: expanded.get(column..end).map_or_else(String::new, |chars| chars.iter().collect()),
+                    marks: marks
+                        .iter()
+                        .filter(|&&(from, to, _)| to > column && from < column + width)
+                        .map(|&(from, to, is_hit)| (from.max(column) - column, to.min(column + width) - column, is_hit))
+                        .collect(),
+                });
+            }
+        }
+        rows
+    }
+
+    /// Scroll by `delta` rows (negative is up), stopping at the first and last row.
+    pub fn scroll_by(&mut self, delta: isize) {
+        match self.mode {
+            ViewMode::Hex => {
+                let step = delta.unsigned_abs() as u64 * HEX_ROW as u64;
+                self.top = if delta < 0 {
+                    self.top.saturating_sub(step)
+                } else {
+                    (self.top + step).min(self.last_hex_row())
+                };
+            }
+            ViewMode::Text if self.wrap => {
+                for _ in 0..delta.unsigned_abs() {
+                    if delta < 0 && self.top_row > 0 {
+                        self.top_row -= 1;
+                    } else if delta < 0 {
+                        let Some(prev) = self.prev_line_start(self.top) else { break };
+                        self.top = prev;
+                        self.top_row = self.line_rows(prev) - 1;
+                    } else if self.top_row + 1 < self.line_rows(self.top) {
+                        self.top_row += 1;
+                    } else {
+                        let Some(next) = self.next_line_start(self.top) else { break };
+                        self.top = next;
+                        self.top_row = 0;
+                    }
+                }
+            }
+            ViewMode::Text => {
+                for _ in 0..delta.unsigned_abs() {
+                    let next = if delta < 0 { self.prev_line_start(self.top) } else { self.next_line_start(self.top) };
+                    match next {
+                        Some(next) => self.top = next,
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move sideways by `delta` columns when long lines are cut off.
+    pub fn scroll_sideways(&mut self, delta: isize) {
+        if self.mode == ViewMode::Text && !self.wrap {
+            self.left = self.left.saturating_add_signed(delta).min(MAX_LINE);
+        }
+    }
+
+    /// Put the row holding `offset` at the top of the screen.
+    fn jump_to(&mut self, offset: u64) {
+        let offset = offset.min(self.len.saturating_sub(1));
+        self.top_row = 0;
+        self.top = match self.mode {
+            ViewMode::Hex => offset / HEX_ROW as u64 * HEX_ROW as u64,
+            ViewMode::Text => self.line_start_before(offset),
+        };
+    }
+
+    pub fn scroll_home(&mut self) {
+        self.jump_to(0);
+        self.left = 0;
+    }
+
+    /// Show the last screenful. Only the end of the file is read.
+    pub fn scroll_end(&mut self) {
+        let height = self.view_height.get().max(1);
+        self.jump_to(self.len);
+        if self.mode == ViewMode::Text && self.wrap {
+            self.top_row = self.line_rows(self.top) - 1;
+        }
+        self.scroll_by(-(height as isize - 1));
+    }
+
+    /// Move to a line number, or to a percentage of the file with `%`.
+    pub fn goto(&mut self, target: &str) -> Result<(), String> {
+        let target = target.trim();
+        if let Some(percent) = target.strip_suffix('%') {
+            let percent: u64 = percent.trim().parse().ok().filter(|&percent| percent <= 100).ok_or_else(|| format!("Not a percentage: {target}"))?;
+            self.jump_to((self.len as u128 * percent as u128 / 100) as u64);
+            return Ok(());
+        }
+        let line: u64 = target.parse().ok().filter(|&line| line > 0).ok_or_else(|| format!("Not a line number: {target}"))?;
+        let offset = self.line_start(line - 1).ok_or_else(|| format!("No line {line}"))?;
+        self.jump_to(offset);
+        Ok(())
+    }
+
+    /// Show the file in the next encoding. Line breaks may be encoded
+    /// differently, so the line index starts over.
+    pub fn cycle_encoding(&mut self) {
+        self.encoding = self.encoding.next();
+        self.index = RefCell::new(LineIndex::default());
+        self.hit = None;
+        if self.mode == ViewMode::Text {
+            self.jump_to(self.top);
+        }
+    }
+
+    pub fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.top_row = 0;
+        self.left = 0;
+    }
+
+    pub fn set_bookmark(&mut self, slot: usize) {
+        self.bookmarks[slot] = Some(self.top);
+        self.message = Some(format!("Bookmark {slot} set"));
+    }
+
+    pub fn goto_bookmark(&mut self, slot: usize) {
+        match self.bookmarks[slot] {
+            Some(offset) => self.jump_to(offset),
+            None => self.message = Some(format!("Bookmark {slot} is not set")),
+        }
+    }
+
+    /// Whether the last row of the file is on screen.
+    pub fn at_end(&self) -> bool {
+        self.bottom() >= self.len
+    }
+
+    /// Turn following on (jumping to the end) or off. Only local files can be followed.
+    pub fn toggle_follow(&mut self) {
+        if !matches!(self.source, ViewSource::File(_)) {
+            self.message = Some("Only local files can be followed".to_string());
+            return;
+        }
+        self.follow = !self.follow;
+        if self.follow {
+            self.scroll_end();
+        }
+    }
+
+    /// Check a followed file for appended data. A file that shrank or was
+    /// replaced under the same name (log rotation) is opened again.
+    pub fn poll_follow(&mut self) {
+        let ViewSource::File(file) = &self.source else { return };
+        if !self.follow {
+            return;
+        }
+        let Ok(opened) = file.metadata() else { return };
+        // Until a rotated log is recreated, keep reading the old one.
+        let replaced = fs::metadata(&self.path)
+            .is_ok_and(|on_disk| (opened.dev(), opened.ino()) != (on_disk.dev(), on_disk.ino()));
+        if replaced || opened.len() < self.len {
+            match fs::File::open(&self.path).and_then(|file| Viewer::new(self.path.clone(), ViewSource::File(file))) {
+                Ok(reloaded) => {
+                    *self = Viewer {
+                        mode: self.mode,
+                        wrap: self.wrap,
+                        follow: true,
+                        finder: self.finder.take(),
+                        view_height: self.view_height.clone(),
+                        view_width: self.view_width.clone(),
+                        ..reloaded
+                    };
+                    self.message = Some("File was truncated or replaced; reloaded".to_string());
+                    self.scroll_end();
+                }
+                Err(err) => self.message = Some(format!("Reload failed: {err}")),
+            }
+        } else if opened.len() > self.len {
+            let pinned = self.at_end();
+            self.len = opened.len();
+            if pinned {
+                self.scroll_end();
+            }
+        }
+    }
+
+    /// How far into the file the bottom of the screen is.
+    pub fn percent(&self) -> u64 {
+        match self.len {
+            0 => 100,
+            len => (self.bottom() as u128 * 100 / len as u128) as u64,
+        }
+    }
+
+    /// Switch between text and hex, keeping the same part of the file on screen.
+    pub fn toggle_mode(&mut self) {
+        let mode = if self.mode == ViewMode::Text { ViewMode::Hex } else { ViewMode::Text };
+        self.set_mode(mode);
+    }
+
+    fn set_mode(&mut self, mode: ViewMode) {
+        if self.mode == mode {
+            return;
+        }
+        self.mode = mode;
+        self.hit = None;
+        self.top_row = 0;
+        self.top = match mode {
+            ViewMode::Hex => self.top / HEX_ROW as u64 * HEX_ROW as u64,
+            ViewMode::Text => self.line_start_before(self.top),
+        };
+    }
+
+    /// Offset just past the last row on screen.
+    fn bottom(&self) -> u64 {
+        let height = self.view_height.get().max(1);
+        match self.mode {
+            ViewMode::Hex => (self.top + (height * HEX_ROW) as u64).min(self.len),
+            ViewMode::Text => self
+                .text_rows()
+                .last()
+                .and_then(|row| self.next_line_start(row.line))
+                .unwrap_or(self.len),
+        }
+    }
+
+    /// Move to the next match of `finder` after the current hit (before it when
+    /// searching backward). Without a hit the search starts at the top of the
+    /// screen, or its bottom going backward. Byte searches switch to hex mode.
+    /// Returns false when nothing is found.
+    pub fn find(&mut self, finder: Finder) -> bool {
+        let found = if finder.searches_bytes(self.mode) {
+            self.find_bytes(&finder)
+        } else {
+            self.find_text(&finder)
+        };
+        self.finder = Some(finder);
+        let Some(hit) = found else { return false };
+        let row = match hit {
+            SearchHit::Text { line, .. } => line,
+            SearchHit::Bytes { start, .. } => {
+                self.set_mode(ViewMode::Hex);
+                start / HEX_ROW as u64 * HEX_ROW as u64
+            }
+        };
+        if row < self.top || row >= self.bottom() {
+            self.jump_to(row);
+            self.scroll_by(-(self.view_height.get() as isize / 3));
+        }
+        self.hit = Some(hit);
+        true
+    }
+
+    fn find_text(&self, finder: &Finder) -> Option<SearchHit> {
+        let current = match self.hit {
+            Some(SearchHit::Text { line, start, .. }) => Some((line, start)),
+            _ => None,
+        };
+        if finder.search.backward {
+            let (mut line, mut before) = current.unwrap_or_else(|| {
+                let last = self.line_start_before(self.bottom().saturating_sub(1));
+                (last, usize::MAX)
+            });
+            loop {
+                let text = self.line_text(line);
+                if let Some((start, end)) = finder.matches(&text).into_iter().rev().find(|&(start, _)| start < before) {
+                    return Some(SearchHit::Text { line, start, end });
+                }
+                line = self.prev_line_start(line)?;
+                before = usize::MAX;
+            }
+        } else {
+            let (line, after) = match current {
+                Some((line, start)) => (line, Some(start)),
+                None => (self.top, None),
+            };
+            self.lines_from(line).find_map(|(offset, text)| {
+                let limit = if offset == line { after } else { None };
+                let (start, end) = finder
+                    .matches(&text)
+                    .into_iter()
+                    .find(|&(start, _)| limit.is_none_or(|limit| start > limit))?;
+                Some(SearchHit::Text { line: offset, start, end })
+            })
+        }
+    }
+
+    fn find_bytes(&self, finder: &Finder) -> Option<SearchHit> {
+        let len = finder.byte_len();
+        if len == 0 {
+            return None;
+        }
+        let current = match self.hit {
+            Some(SearchHit::Bytes { start, .. }) => Some(start),
+            _ => None,
+        };
+        let start = if finder.search.backward {
+            let mut end = current.unwrap_or_else(|| self.bottom().min(self.len));
+            loop {
+                if end == 0 {
+                    return None;
+                }
+                let from = end.saturating_sub(CHUNK as u64);
+                let buf = self.source.read_at(from, (end - from) as usize + len - 1);
+                let found = (0..(end - from) as usize).rev().find(|&idx| finder.bytes_at(&buf[idx..]));
+                if let Some(idx) = found {
+                    break from + idx as u64;
+                }
+                end = from;
+            }
+        } else {
+            let mut from = current.map_or(self.top, |start| start + 1);
+            loop {
+                if from >= self.len {
+                    return None;
+                }
+                let buf = self.source.read_at(from, CHUNK + len - 1);
+                let found = (0..buf.len().min(CHUNK)).find(|&idx| finder.bytes_at(&buf[idx..]));
+                if let Some(idx) = found {
+                    break from + idx as u64;
+                }
+                from += CHUNK as u64;
+            }
+        };
+        Some(SearchHit::Bytes { start, end: start + len as u64 })
+    }
+}